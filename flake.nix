@@ -79,8 +79,13 @@
           packages.f0bot-unwrapped = crane.lib.${system}.buildPackage {
             src = nix-filter.lib {
               root = ./.;
-              include =
-                [ "src" "Cargo.toml" "Cargo.lock" "config.example.yaml" ];
+              include = [
+                "src"
+                "migrations"
+                "Cargo.toml"
+                "Cargo.lock"
+                "config.example.yaml"
+              ];
             };
             nativeBuildInputs = buildDeps;
           };
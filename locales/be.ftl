@@ -0,0 +1,144 @@
+# Адказы бота па-беларуску. Ключы павінны супадаць з іншымі лакалямі, гэта
+# правярае `test_locales`. Тэксты могуць змяшчаць HTML-разметку Telegram.
+
+## Command access, see common::filter_command
+
+command-not-in-group = Гэтая каманда недаступная ў групавых чатах
+command-not-in-private = Гэтая каманда недаступная ў асабістых паведамленнях
+command-admin-only = Гэтая каманда даступная толькі адміністратарам
+command-resident-only = Гэтая каманда даступная толькі рэзідэнтам
+command-resident-chat-only = Гэтая каманда даступная толькі ў чаце рэзідэнтаў
+command-role-only = Для гэтай каманды патрэбная роля { $role }
+command-rate-limited = Занадта шмат запытаў, паспрабуйце зноў праз { $seconds } с
+
+help-header = Даступныя каманды:
+help-footer =
+    Каманды, пазначаныя *, даступныя толькі рэзідэнтам, ** — толькі адміністратарам бота, а [роля] — толькі карыстальнікам з гэтай роляй.
+
+## /language
+
+language-current = Бягучая мова: { $lang }.
+language-usage =
+    Выкарыстанне: <code>/language КОД</code>, дзе КОД — адзін з: { $codes }.
+    <code>/language auto</code> скідае наладу.
+language-set = Мова зменена на { $lang }.
+language-reset = Налада мовы скінутая.
+language-unknown = Невядомая мова.
+language-admins-only = Толькі адміністратары могуць мяняць мову групавога чата.
+
+## basic
+
+reload-config-unchanged = Канфігурацыя перазагружаная, нічога не змянілася.
+reload-config-changes = Канфігурацыя перазагружаная, змены:
+reload-config-restart-required = патрэбны перазапуск
+reload-config-failed = Не ўдалося перазагрузіць канфігурацыю:
+residents-list = Рэзідэнты: { $users }.
+residents-admin-table-failed = Не ўдалося стварыць табліцу.
+residents-timeline-failed = Не ўдалося пабудаваць графік ({ $format }).
+status-in-space = Зараз у спейсе:
+status-no-data = Даных пакуль няма. Магчыма, няправільны пароль ад Mikrotik.
+topics-no-chats = Вас няма ні ў адным з чатаў, якія адсочваюцца.
+topics-no-topics = У вашых чатах няма тэм.
+topics-unnamed = Тэма #{ $id }
+
+## needs
+
+needs-not-configured = Спіс пакупак не наладжаны.
+needs-user-needs = { $user } просіць купіць:
+needs-empty = Нічога не трэба.
+needs-item-by = ад { $user }
+needs-press-button = Націсніце на кнопку, каб пазначыць пакупку.
+needs-done = Гатова!
+needs-not-found = Не ўдалося знайсці пакупку.
+needs-already-bought = Ужо куплена
+needs-marked-bought = { $buyer } купіў(-ла) «{ $item }».
+needs-undo = Адмяніць
+needs-already-undone = Пакупка ўжо адмененая.
+needs-not-buyer = Гэта купілі не вы.
+
+## polls
+
+poll-req-new = няма галасоў (новае апытанне)
+poll-req-not-closed = апытанне не закрытае
+poll-req-not-anonymous = апытанне не ананімнае
+poll-req-regular = звычайнае апытанне, не віктарына
+poll-req-resident = створанае рэзідэнтам
+poll-requirements-failed =
+    Здаецца, вы хацелі стварыць апытанне, якое адсочвае бот, але яно не адпавядае ўсім патрабаванням:
+    { $diag }
+poll-everyone-voted = Усе прагаласавалі!
+poll-unknown = Невядомае апытанне
+poll-by = Апытанне ад { $creator }.
+poll-progress =
+    Прагаласавалі { $voted } { $voted ->
+        [one] карыстальнік
+        [few] карыстальнікі
+       *[many] карыстальнікаў
+    }, не прагаласавалі { $pending } { $pending ->
+        [one] карыстальнік
+        [few] карыстальнікі
+       *[many] карыстальнікаў
+    }: { $users }.
+poll-not-found = Апытанне не знойдзенае.
+poll-not-creator = Вы не аўтар гэтага апытання.
+poll-message-not-found = Паведамленне з апытаннем не знойдзенае.
+# Based on the original Telegram client message
+poll-stop-warning = Калі спыніць апытанне зараз, ніхто больш не зможа ў ім прагаласаваць. Гэта дзеянне нельга адмяніць.
+poll-stop = Спыніць апытанне
+poll-stop-cancel = Адмена (не спыняць)
+poll-stop-confirm = Пацвердзіць (спыніць)
+
+## borrowed_items
+
+borrowed-not-your-message = Гэта не ваша паведамленне.
+borrowed-already-returned = Гэтая рэч ужо вернутая.
+borrowed-internal-error = Унутраная памылка
+borrowed-returned = { $date }: вернута: { $items }
+borrowed-press-button = { $user }, націсніце на кнопку, каб пазначыць рэч як вернутую.
+
+## ldap
+
+ldap-not-configured = LDAP не наладжаны.
+ldap-not-available = LDAP недаступны.
+ldap-not-registered = Вас няма ў базе LDAP. Спачатку выканайце /ldap_register.
+ldap-already-registered = Вы ўжо зарэгістраваныя ў базе LDAP.
+ldap-no-username = Імя карыстальніка не пазначана і не знойдзена.
+ldap-registered = Вы зарэгістраваныя ў базе LDAP з паролем: <code>{ $password }</code>.
+ldap-updated = Вашы налады LDAP абноўленыя.
+ldap-new-password = Ваш новы пароль: <code>{ $password }</code>.
+ldap-groups = Вашы групы LDAP:
+
+## search
+
+search-usage = Выкарыстанне: <code>/search СЛОВЫ</code>. Шукае паведамленні са словамі, што пачынаюцца з кожнага са СЛОЎ, у архіваваных чатах, дзе вы ўдзельнічаеце.
+search-no-hits = Нічога не знойдзена.
+search-hits = Знойдзеныя паведамленні:
+forget-usage = Адкажыце /forget на паведамленне, каб выдаліць яго з архіва.
+forget-not-author = Гэта можа зрабіць толькі аўтар паведамлення або адміністратар бота.
+forget-not-archived = Гэтага паведамлення няма ў архіве.
+forget-done = Паведамленне выдалена з архіва.
+
+## stats
+
+stats-usage = Выкарыстанне: <code>/stats [ПЕРЫЯД]</code>, дзе ПЕРЫЯД кшталту <code>12h</code>, <code>7d</code> або <code>2w</code>, па змаўчанні 30 дзён і не больш за год.
+stats-header = Актыўнасць з { $since } UTC:
+stats-no-data = За гэты перыяд паведамленняў няма.
+stats-messages = { $count } { $count ->
+        [one] паведамленне
+        [few] паведамленні
+       *[many] паведамленняў
+    }
+stats-general-topic = Агульная
+stats-top-posters = Самыя актыўныя:
+stats-active-hours = Актыўныя гадзіны (UTC):
+stats-weekdays = Пн Аў Ср Чц Пт Сб Нд
+stats-topics = Тэм: { $total }, новых: { $new }.
+
+## vortex_of_doom
+
+vortex-of-doom = Час віру лёсу! Перасуньце скрынкі, выкіньце апошнюю і дашліце фота.
+vortex-of-doom-no-camera = Не ўдалося атрымаць здымак з камеры, праверце логі.
+
+## backup
+
+backup-caption = Рэзервовая копія базы даных { $name }
@@ -0,0 +1,141 @@
+# Bot replies in English. Keep the keys in sync with other locales, this is
+# checked by `test_locales`. Texts may contain Telegram HTML markup.
+
+## Command access, see common::filter_command
+
+command-not-in-group = This command is not allowed in group chats
+command-not-in-private = This command is not allowed in private chats
+command-admin-only = You must be an admin to execute this command
+command-resident-only = You must be a resident to execute this command
+command-resident-chat-only = This command is allowed only in resident chat
+command-role-only = You must have the { $role } role to execute this command
+command-rate-limited = Too many requests, try again in { $seconds } seconds
+
+help-header = Available commands:
+help-footer =
+    Commands marked with * are available only to residents, with ** only to bot admins, and with [role] only to users with the role.
+
+## /language
+
+language-current = Current language: { $lang }.
+language-usage =
+    Usage: <code>/language CODE</code>, where CODE is one of: { $codes }.
+    Use <code>/language auto</code> to reset the setting.
+language-set = Language is set to { $lang }.
+language-reset = Language setting is reset.
+language-unknown = Unknown language.
+language-admins-only = Only admins can change the language of a group chat.
+
+## basic
+
+reload-config-unchanged = Config reloaded, nothing changed.
+reload-config-changes = Config reloaded, changes:
+reload-config-restart-required = restart required
+reload-config-failed = Failed to reload config:
+residents-list = Residents: { $users }.
+residents-admin-table-failed = Failed to generate table.
+residents-timeline-failed = Failed to generate timeline ({ $format }).
+status-in-space = Currently in space:
+status-no-data = No data collected yet. Probably Mikrotik password is incorrect.
+topics-no-chats = You are not in any tracked chats.
+topics-no-topics = No topics in your chats.
+topics-unnamed = Topic #{ $id }
+
+## needs
+
+needs-not-configured = The shopping list is not configured.
+needs-user-needs = { $user } needs:
+needs-empty = No items needed.
+needs-item-by = by { $user }
+needs-press-button = Press a button to mark an item as bought.
+needs-done = Done!
+needs-not-found = Could not find item.
+needs-already-bought = Item already bought
+needs-marked-bought = { $buyer } marked an item "{ $item }" as bought.
+needs-undo = Undo
+needs-already-undone = Item already undone.
+needs-not-buyer = You did not buy this item.
+
+## polls
+
+poll-req-new = no votes (aka new poll)
+poll-req-not-closed = not closed already
+poll-req-not-anonymous = not anonymous
+poll-req-regular = regular poll, not quiz
+poll-req-resident = created by resident
+poll-requirements-failed =
+    It seems you tried to create a bot-tracked poll, but it doesn't meet all of the requirements:
+    { $diag }
+poll-everyone-voted = Everyone voted!
+poll-unknown = Unknown poll
+poll-by = Poll by { $creator }.
+poll-progress =
+    Voted { $voted } { $voted ->
+        [one] user
+       *[other] users
+    }, pending vote { $pending } { $pending ->
+        [one] user
+       *[other] users
+    }: { $users }.
+poll-not-found = Poll not found.
+poll-not-creator = You are not the creator of this poll.
+poll-message-not-found = Poll message not found.
+# Based on the original Telegram client message
+poll-stop-warning = If you stop this poll now, nobody will be able to vote in anymore. This action cannot be undone.
+poll-stop = Stop poll
+poll-stop-cancel = Cancel (do not stop)
+poll-stop-confirm = Confirm (stop poll)
+
+## borrowed_items
+
+borrowed-not-your-message = This is not your message.
+borrowed-already-returned = This item is already returned.
+borrowed-internal-error = Internal error
+borrowed-returned = { $date }: returned { $items }
+borrowed-press-button = { $user }, press a button to mark an item as returned.
+
+## ldap
+
+ldap-not-configured = LDAP is not configured.
+ldap-not-available = LDAP is not available.
+ldap-not-registered = You are not in the LDAP database. You need to do /ldap_register first.
+ldap-already-registered = You are already registered in the LDAP database.
+ldap-no-username = No username provided or found.
+ldap-registered = You have been registered in the LDAP database with password: <code>{ $password }</code>.
+ldap-updated = Your LDAP settings have been updated.
+ldap-new-password = Your new password is <code>{ $password }</code>.
+ldap-groups = Your LDAP groups:
+
+## search
+
+search-usage = Usage: <code>/search WORDS</code>. Finds messages with words starting with each of WORDS, in archived chats you are a member of.
+search-no-hits = Nothing found.
+search-hits = Found messages:
+forget-usage = Reply with /forget to a message to remove it from the archive.
+forget-not-author = Only the author of the message or a bot admin can do this.
+forget-not-archived = The message is not in the archive.
+forget-done = The message is removed from the archive.
+
+## stats
+
+stats-usage = Usage: <code>/stats [PERIOD]</code>, where PERIOD is like <code>12h</code>, <code>7d</code> or <code>2w</code>, 30 days by default and at most a year.
+stats-header = Activity since { $since } UTC:
+stats-no-data = No messages in this period.
+stats-messages = { $count } { $count ->
+        [one] message
+       *[other] messages
+    }
+stats-general-topic = General
+stats-top-posters = Top posters:
+stats-active-hours = Active hours (UTC):
+stats-weekdays = Mo Tu We Th Fr Sa Su
+stats-topics = Topics: { $total }, { $new } new.
+
+## vortex_of_doom
+
+vortex-of-doom = It's vortex of doom time! Please move the boxes, and throw away the last one and send a picture.
+vortex-of-doom-no-camera = Failed to fetch camera image, please check the logs.
+
+## backup
+
+backup-caption = Database backup { $name }
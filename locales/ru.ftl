@@ -0,0 +1,144 @@
+# Ответы бота на русском. Ключи должны совпадать с другими локалями, это
+# проверяет `test_locales`. Тексты могут содержать HTML-разметку Telegram.
+
+## Command access, see common::filter_command
+
+command-not-in-group = Эта команда недоступна в групповых чатах
+command-not-in-private = Эта команда недоступна в личных сообщениях
+command-admin-only = Эта команда доступна только администраторам
+command-resident-only = Эта команда доступна только резидентам
+command-resident-chat-only = Эта команда доступна только в чате резидентов
+command-role-only = Для этой команды нужна роль { $role }
+command-rate-limited = Слишком много запросов, попробуйте снова через { $seconds } с
+
+help-header = Доступные команды:
+help-footer =
+    Команды, отмеченные *, доступны только резидентам, ** — только администраторам бота, а [роль] — только пользователям с этой ролью.
+
+## /language
+
+language-current = Текущий язык: { $lang }.
+language-usage =
+    Использование: <code>/language КОД</code>, где КОД — один из: { $codes }.
+    <code>/language auto</code> сбрасывает настройку.
+language-set = Язык изменён на { $lang }.
+language-reset = Настройка языка сброшена.
+language-unknown = Неизвестный язык.
+language-admins-only = Только администраторы могут менять язык группового чата.
+
+## basic
+
+reload-config-unchanged = Конфигурация перезагружена, ничего не изменилось.
+reload-config-changes = Конфигурация перезагружена, изменения:
+reload-config-restart-required = нужен перезапуск
+reload-config-failed = Не удалось перезагрузить конфигурацию:
+residents-list = Резиденты: { $users }.
+residents-admin-table-failed = Не удалось создать таблицу.
+residents-timeline-failed = Не удалось построить график ({ $format }).
+status-in-space = Сейчас в спейсе:
+status-no-data = Данных пока нет. Возможно, неверный пароль от Mikrotik.
+topics-no-chats = Вас нет ни в одном из отслеживаемых чатов.
+topics-no-topics = В ваших чатах нет тем.
+topics-unnamed = Тема #{ $id }
+
+## needs
+
+needs-not-configured = Список покупок не настроен.
+needs-user-needs = { $user } просит купить:
+needs-empty = Ничего не нужно.
+needs-item-by = от { $user }
+needs-press-button = Нажмите на кнопку, чтобы отметить покупку.
+needs-done = Готово!
+needs-not-found = Не удалось найти покупку.
+needs-already-bought = Уже куплено
+needs-marked-bought = { $buyer } купил(а) «{ $item }».
+needs-undo = Отменить
+needs-already-undone = Покупка уже отменена.
+needs-not-buyer = Это купили не вы.
+
+## polls
+
+poll-req-new = нет голосов (новый опрос)
+poll-req-not-closed = опрос не закрыт
+poll-req-not-anonymous = опрос не анонимный
+poll-req-regular = обычный опрос, не викторина
+poll-req-resident = создан резидентом
+poll-requirements-failed =
+    Похоже, вы хотели создать опрос, отслеживаемый ботом, но он не соответствует всем требованиям:
+    { $diag }
+poll-everyone-voted = Все проголосовали!
+poll-unknown = Неизвестный опрос
+poll-by = Опрос от { $creator }.
+poll-progress =
+    Проголосовали { $voted } { $voted ->
+        [one] пользователь
+        [few] пользователя
+       *[many] пользователей
+    }, не проголосовали { $pending } { $pending ->
+        [one] пользователь
+        [few] пользователя
+       *[many] пользователей
+    }: { $users }.
+poll-not-found = Опрос не найден.
+poll-not-creator = Вы не автор этого опроса.
+poll-message-not-found = Сообщение с опросом не найдено.
+# Based on the original Telegram client message
+poll-stop-warning = Если остановить опрос сейчас, никто больше не сможет в нём проголосовать. Это действие нельзя отменить.
+poll-stop = Остановить опрос
+poll-stop-cancel = Отмена (не останавливать)
+poll-stop-confirm = Подтвердить (остановить)
+
+## borrowed_items
+
+borrowed-not-your-message = Это не ваше сообщение.
+borrowed-already-returned = Эта вещь уже возвращена.
+borrowed-internal-error = Внутренняя ошибка
+borrowed-returned = { $date }: возвращено: { $items }
+borrowed-press-button = { $user }, нажмите на кнопку, чтобы отметить вещь как возвращённую.
+
+## ldap
+
+ldap-not-configured = LDAP не настроен.
+ldap-not-available = LDAP недоступен.
+ldap-not-registered = Вас нет в базе LDAP. Сначала выполните /ldap_register.
+ldap-already-registered = Вы уже зарегистрированы в базе LDAP.
+ldap-no-username = Имя пользователя не указано и не найдено.
+ldap-registered = Вы зарегистрированы в базе LDAP с паролем: <code>{ $password }</code>.
+ldap-updated = Ваши настройки LDAP обновлены.
+ldap-new-password = Ваш новый пароль: <code>{ $password }</code>.
+ldap-groups = Ваши группы LDAP:
+
+## search
+
+search-usage = Использование: <code>/search СЛОВА</code>. Ищет сообщения со словами, начинающимися с каждого из СЛОВ, в архивируемых чатах, где вы состоите.
+search-no-hits = Ничего не найдено.
+search-hits = Найденные сообщения:
+forget-usage = Ответьте /forget на сообщение, чтобы удалить его из архива.
+forget-not-author = Это может сделать только автор сообщения или администратор бота.
+forget-not-archived = Этого сообщения нет в архиве.
+forget-done = Сообщение удалено из архива.
+
+## stats
+
+stats-usage = Использование: <code>/stats [ПЕРИОД]</code>, где ПЕРИОД вида <code>12h</code>, <code>7d</code> или <code>2w</code>, по умолчанию 30 дней и не больше года.
+stats-header = Активность с { $since } UTC:
+stats-no-data = За этот период сообщений нет.
+stats-messages = { $count } { $count ->
+        [one] сообщение
+        [few] сообщения
+       *[many] сообщений
+    }
+stats-general-topic = Общая
+stats-top-posters = Самые активные:
+stats-active-hours = Активные часы (UTC):
+stats-weekdays = Пн Вт Ср Чт Пт Сб Вс
+stats-topics = Тем: { $total }, новых: { $new }.
+
+## vortex_of_doom
+
+vortex-of-doom = Время водоворота судьбы! Передвиньте коробки, выбросьте последнюю и пришлите фото.
+vortex-of-doom-no-camera = Не удалось получить снимок с камеры, проверьте логи.
+
+## backup
+
+backup-caption = Резервная копия базы данных { $name }
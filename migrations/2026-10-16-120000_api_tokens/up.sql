@@ -0,0 +1,8 @@
+CREATE TABLE api_tokens (
+  rowid INTEGER PRIMARY KEY NOT NULL,
+  user_id BIGINT NOT NULL,
+  name TEXT NOT NULL,
+  token_hash TEXT NOT NULL UNIQUE,
+  created_at TIMESTAMP NOT NULL,
+  last_used_at TIMESTAMP
+);
@@ -0,0 +1,7 @@
+CREATE TABLE user_roles (
+  user_id BIGINT NOT NULL /* REFERENCES tg_users(id) */,
+  role TEXT NOT NULL,
+  granted_by BIGINT NOT NULL /* REFERENCES tg_users(id) */,
+  granted_at TIMESTAMP NOT NULL,
+  PRIMARY KEY (user_id, role)
+);
@@ -0,0 +1,2 @@
+DROP TABLE tg_messages_fts;
+DROP TABLE tg_messages;
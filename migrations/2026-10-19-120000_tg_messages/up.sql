@@ -0,0 +1,35 @@
+-- Text of messages in chats listed in telegram.chats.archive.
+CREATE TABLE tg_messages (
+  -- An explicit rowid, since VACUUM may renumber implicit ones, which would
+  -- break the index below.
+  rowid INTEGER PRIMARY KEY NOT NULL,
+  chat_id BIGINT NOT NULL /* REFERENCES tg_chats(id) */,
+  message_id INTEGER NOT NULL,
+  thread_id INTEGER NOT NULL,
+  user_id BIGINT /* REFERENCES tg_users(id) */,
+  date TIMESTAMP NOT NULL,
+  text TEXT NOT NULL,
+  UNIQUE (chat_id, message_id)
+);
+
+-- Full-text index of tg_messages, kept in sync by the triggers below.
+CREATE VIRTUAL TABLE tg_messages_fts USING fts5(
+  text,
+  content = 'tg_messages',
+  content_rowid = 'rowid'
+);
+
+CREATE TRIGGER tg_messages_insert AFTER INSERT ON tg_messages BEGIN
+  INSERT INTO tg_messages_fts (rowid, text) VALUES (new.rowid, new.text);
+END;
+
+CREATE TRIGGER tg_messages_delete AFTER DELETE ON tg_messages BEGIN
+  INSERT INTO tg_messages_fts (tg_messages_fts, rowid, text)
+    VALUES ('delete', old.rowid, old.text);
+END;
+
+CREATE TRIGGER tg_messages_update AFTER UPDATE ON tg_messages BEGIN
+  INSERT INTO tg_messages_fts (tg_messages_fts, rowid, text)
+    VALUES ('delete', old.rowid, old.text);
+  INSERT INTO tg_messages_fts (rowid, text) VALUES (new.rowid, new.text);
+END;
@@ -0,0 +1,2 @@
+DROP TABLE tg_message_stats_cursors;
+DROP TABLE tg_message_stats;
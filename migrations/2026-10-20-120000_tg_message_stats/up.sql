@@ -0,0 +1,19 @@
+-- Number of messages per chat, topic, user and hour, counted by tg_scraper.
+CREATE TABLE tg_message_stats (
+  chat_id BIGINT NOT NULL /* REFERENCES tg_chats(id) */,
+  thread_id INTEGER NOT NULL,
+  user_id BIGINT NOT NULL /* REFERENCES tg_users(id) */,
+  -- Start of the hour, in UTC.
+  hour TIMESTAMP NOT NULL,
+  messages INTEGER NOT NULL,
+  PRIMARY KEY (chat_id, thread_id, user_id, hour)
+);
+
+CREATE INDEX tg_message_stats_hour ON tg_message_stats (hour);
+
+-- The last counted message in each chat, so that scraping a trace again
+-- doesn't count messages twice.
+CREATE TABLE tg_message_stats_cursors (
+  chat_id BIGINT NOT NULL PRIMARY KEY,
+  message_id INTEGER NOT NULL
+);
@@ -0,0 +1,11 @@
+DROP TABLE IF EXISTS residents;
+DROP TABLE IF EXISTS tg_users;
+DROP TABLE IF EXISTS tg_chats;
+DROP TABLE IF EXISTS tg_users_in_chats;
+DROP TABLE IF EXISTS tg_chat_topics;
+DROP TABLE IF EXISTS options;
+DROP TABLE IF EXISTS user_macs;
+DROP TABLE IF EXISTS tracked_polls;
+DROP TABLE IF EXISTS borrowed_items;
+DROP TABLE IF EXISTS needed_items;
+DROP TABLE IF EXISTS dashboard_messages;
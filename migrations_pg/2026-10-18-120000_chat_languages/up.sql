@@ -0,0 +1,6 @@
+-- Language of bot replies, set with /language. For private chats, chat_id is
+-- the id of the user.
+CREATE TABLE chat_languages (
+  chat_id BIGINT NOT NULL PRIMARY KEY,
+  lang TEXT NOT NULL
+);
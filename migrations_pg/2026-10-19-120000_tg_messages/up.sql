@@ -0,0 +1,16 @@
+-- Text of messages in chats listed in telegram.chats.archive.
+CREATE TABLE tg_messages (
+  rowid SERIAL PRIMARY KEY NOT NULL,
+  chat_id BIGINT NOT NULL /* REFERENCES tg_chats(id) */,
+  message_id INTEGER NOT NULL,
+  thread_id INTEGER NOT NULL,
+  user_id BIGINT /* REFERENCES tg_users(id) */,
+  date TIMESTAMP NOT NULL,
+  text TEXT NOT NULL,
+  UNIQUE (chat_id, message_id)
+);
+
+-- Full-text index of tg_messages. The 'simple' configuration doesn't stem
+-- words, since messages are in several languages.
+CREATE INDEX tg_messages_text ON tg_messages
+  USING GIN (to_tsvector('simple', text));
@@ -35,7 +35,8 @@ async def main() -> None:
     with open(sys.argv[1]) as f:  # noqa: ASYNC101
         config = yaml.safe_load(f)
 
-    db = sqlite3.connect(f"file:{DB_FILENAME}?mode=ro", uri=True)
+    db_filename = sys.argv[2] if len(sys.argv) > 2 else DB_FILENAME
+    db = sqlite3.connect(f"file:{db_filename}?mode=ro", uri=True)
     client = await telethon.TelegramClient(
         SESSION_NAME, API_ID, API_HASH
     ).start(bot_token=config["telegram"]["token"])
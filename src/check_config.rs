@@ -0,0 +1,597 @@
+//! Config checks, done on startup and by the `check-config` subcommand.
+//!
+//! Static checks look only at the config: cron expressions, URLs, and threads
+//! shared by modules that would steal each other's messages. On startup and
+//! config reload, problems are only logged, so that configs which worked
+//! before these checks existed keep working; `check-config` fails on errors.
+//!
+//! Online checks (`check-config --online`) ask the Telegram Bot API whether
+//! the bot is a member of each configured chat and has the admin rights the
+//! modules need.
+
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{Context as _, Result};
+use itertools::Itertools as _;
+use reqwest::Url;
+use teloxide::requests::Requester;
+use teloxide::types::{
+    ChatId, ChatKind, ChatMember, ChatMemberKind, PublicChatKind,
+    PublicChatSupergroup,
+};
+use teloxide::Bot;
+
+use crate::config::Config;
+use crate::utils::{ThreadIdPair, GENERAL_THREAD_ID};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Debug)]
+pub struct Problem {
+    pub severity: Severity,
+    pub text: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.severity {
+            Severity::Warning => write!(f, "warning: {}", self.text),
+            Severity::Error => write!(f, "error: {}", self.text),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Problems(Vec<Problem>);
+
+impl Problems {
+    fn error(&mut self, text: impl Into<String>) {
+        self.0.push(Problem { severity: Severity::Error, text: text.into() });
+    }
+
+    fn warning(&mut self, text: impl Into<String>) {
+        self.0.push(Problem { severity: Severity::Warning, text: text.into() });
+    }
+}
+
+/// Run static checks on startup and config reload, logging all problems as
+/// warnings. Only invalid schedules are fatal, since modules can't run
+/// without them.
+pub fn ensure_valid(config: &Config) -> Result<()> {
+    for problem in check(config) {
+        log::warn!("Config: {problem}");
+    }
+    let mut fatal = Problems::default();
+    check_schedule(config, &mut fatal);
+    if !fatal.0.is_empty() {
+        let errors = fatal.0.iter().map(|p| p.text.as_str()).collect_vec();
+        anyhow::bail!("Invalid config:\n{}", errors.join("\n"));
+    }
+    Ok(())
+}
+
+/// Static checks of the config.
+pub fn check(config: &Config) -> Vec<Problem> {
+    let mut problems = Problems::default();
+    if config.telegram.token.is_empty() {
+        problems.error("telegram.token: not set");
+    }
+    check_schedule(config, &mut problems);
+    check_urls(config, &mut problems);
+    check_threads(config, &mut problems);
+    check_chats(config, &mut problems);
+    check_services(config, &mut problems);
+    check_database(config, &mut problems);
+    problems.0
+}
+
+fn check_schedule(config: &Config, problems: &mut Problems) {
+    let schedules = [
+        (
+            "telegram.chats.vortex_of_doom.schedule",
+            config.telegram.chats.vortex_of_doom.as_ref().map(|v| &v.schedule),
+        ),
+        ("backup.schedule", config.backup.as_ref().map(|b| &b.schedule)),
+    ];
+    for (name, schedule) in schedules {
+        let Some(schedule) = schedule else { continue };
+        if let Err(e) = cron::Schedule::from_str(schedule) {
+            problems.error(format!(
+                "{name}: invalid cron expression {schedule:?}: {e}"
+            ));
+        }
+    }
+}
+
+fn check_urls(config: &Config, problems: &mut Problems) {
+    let services = &config.services;
+    if let Some(wikijs) = &services.wikijs {
+        match Url::parse(&wikijs.url) {
+            Ok(url) if matches!(url.scheme(), "http" | "https") => (),
+            Ok(_) => problems.error("services.wikijs.url: not an HTTP URL"),
+            Err(e) => problems.error(format!("services.wikijs.url: {e}")),
+        }
+    }
+    let hosts = [
+        ("services.mikrotik.host", services.mikrotik.as_ref().map(|m| &m.host)),
+        (
+            "services.home_assistant.host",
+            services.home_assistant.as_ref().map(|h| &h.host),
+        ),
+    ];
+    for (name, host) in hosts {
+        let Some(host) = host else { continue };
+        let valid = Url::parse(&format!("https://{host}"))
+            .is_ok_and(|url| url.path() == "/" && url.username().is_empty());
+        if !valid {
+            problems.error(format!("{name}: invalid host {host:?}"));
+        }
+    }
+    for (name, cam) in [
+        ("services.vortex_of_doom_cam.url", &services.vortex_of_doom_cam),
+        ("services.racovina_cam.url", &services.racovina_cam),
+    ] {
+        let Some(cam) = cam else { continue };
+        if !matches!(cam.url.scheme(), "http" | "https") {
+            problems.error(format!("{name}: not an HTTP URL"));
+        }
+    }
+
+    if let Some(webhook) = &config.telegram.webhook {
+        if webhook.url.scheme() != "https" {
+            problems.error("telegram.webhook.url: Telegram requires HTTPS");
+        }
+        if !matches!(
+            webhook.url.port_or_known_default(),
+            Some(443 | 80 | 88 | 8443)
+        ) {
+            problems.error(
+                "telegram.webhook.url: port should be one of 443, 80, 88, 8443",
+            );
+        }
+        let token = &webhook.secret_token;
+        if token.is_empty()
+            || token.len() > 256
+            || !token
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            problems.error(
+                "telegram.webhook.secret_token: should be 1-256 characters \
+                of A-Z, a-z, 0-9, _ and -",
+            );
+        }
+    }
+    if config.server_addr.port() == 0 {
+        problems.warning("server_addr: port 0 picks a random port");
+    }
+}
+
+/// The `postgres` section should match the backend the bot is built with, and
+/// backups are only supported for SQLite.
+fn check_database(config: &Config, problems: &mut Problems) {
+    match (&config.postgres, cfg!(feature = "postgres")) {
+        (None, true) => problems.error(
+            "postgres: not set, but the bot is built with the postgres \
+            feature",
+        ),
+        (Some(_), false) => problems.warning(
+            "postgres: ignored, since the bot is built without the postgres \
+            feature",
+        ),
+        (Some(postgres), true) => {
+            if postgres.url.is_empty() && postgres.url_file.is_none() {
+                problems.error("postgres.url: not set");
+            }
+        }
+        (None, false) => (),
+    }
+    if let Some(backup) = &config.backup {
+        if cfg!(feature = "postgres") {
+            problems.warning(
+                "backup: ignored, since the bot is built with the postgres \
+                feature, use pg_dump instead",
+            );
+        } else if backup.keep == 0 {
+            problems.error("backup.keep: should be at least 1");
+        }
+    }
+}
+
+/// Modules that are enabled in `telegram.chats`, but can't work without a
+/// service.
+fn check_services(config: &Config, problems: &mut Problems) {
+    let chats = &config.telegram.chats;
+    let services = &config.services;
+    for (chat, chat_name, service, service_name) in [
+        (
+            chats.dashboard.is_some(),
+            "dashboard",
+            services.wikijs.is_some(),
+            "wikijs",
+        ),
+        (
+            chats.wikijs_updates.is_some(),
+            "wikijs_updates",
+            services.wikijs.is_some(),
+            "wikijs",
+        ),
+        (
+            chats.mac_monitoring.is_some(),
+            "mac_monitoring",
+            services.mikrotik.is_some(),
+            "mikrotik",
+        ),
+    ] {
+        if chat && !service {
+            problems.warning(format!(
+                "telegram.chats.{chat_name}: ignored, since \
+                services.{service_name} is not configured"
+            ));
+        }
+    }
+}
+
+/// Threads used by enabled modules, along with whether the module treats
+/// every message in the thread as its own.
+fn threads(config: &Config) -> Vec<(String, ThreadIdPair, bool)> {
+    let chats = &config.telegram.chats;
+    let mut threads = chats
+        .borrowed_items
+        .iter()
+        .enumerate()
+        .map(|(i, t)| (format!("telegram.chats.borrowed_items[{i}]"), *t, true))
+        .collect::<Vec<_>>();
+    let optional = [
+        ("telegram.chats.needs", chats.needs, true),
+        ("telegram.chats.ask_to_visit", chats.ask_to_visit, true),
+        ("telegram.chats.dashboard", chats.dashboard, false),
+        ("telegram.chats.mac_monitoring", chats.mac_monitoring, false),
+        ("telegram.chats.wikijs_updates", chats.wikijs_updates, false),
+        (
+            "telegram.chats.vortex_of_doom.chat",
+            chats.vortex_of_doom.as_ref().map(|v| v.chat),
+            false,
+        ),
+    ];
+    for (name, thread, exclusive) in optional {
+        if let Some(thread) = thread {
+            threads.push((name.to_string(), thread, exclusive));
+        }
+    }
+    threads
+}
+
+fn check_threads(config: &Config, problems: &mut Problems) {
+    let threads = threads(config);
+    for (i, (name_a, a, exclusive_a)) in threads.iter().enumerate() {
+        for (name_b, b, exclusive_b) in &threads[i + 1..] {
+            if a != b {
+                continue;
+            }
+            if *exclusive_a || *exclusive_b {
+                problems.error(format!(
+                    "{name_a} and {name_b}: same thread {}/{}, messages in \
+                    it would be handled by both modules",
+                    a.chat, a.thread.0 .0,
+                ));
+            }
+        }
+    }
+}
+
+fn check_chats(config: &Config, problems: &mut Problems) {
+    let chats = &config.telegram.chats;
+    for (i, chat) in chats.residential.iter().enumerate() {
+        if chats.residential[..i].contains(chat) {
+            problems.warning(format!(
+                "telegram.chats.residential: {chat} is listed twice"
+            ));
+        }
+    }
+    for (i, fwd) in chats.forward_pins.iter().enumerate() {
+        if fwd.from == fwd.to {
+            problems.error(format!(
+                "telegram.chats.forward_pins[{i}]: forwards to the same chat"
+            ));
+        }
+        if chats.forward_pins[..i].iter().any(|f| f.from == fwd.from) {
+            problems.warning(format!(
+                "telegram.chats.forward_pins[{i}]: {} is already listed, \
+                only the first entry is used",
+                fwd.from,
+            ));
+        }
+    }
+}
+
+/// What a module needs from a chat.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Need {
+    /// The bot should be able to read and send messages.
+    Member,
+    /// The chat should have topics.
+    Forum,
+    /// Admin, to receive `chat_member` updates.
+    Administrator,
+    PinMessages,
+    ManageTopics,
+}
+
+/// Chats to check online, with needs of the modules using them.
+fn chat_needs(config: &Config) -> Vec<(ChatId, String, Vec<Need>)> {
+    let chats = &config.telegram.chats;
+    let mut result = Vec::new();
+    for chat in &chats.residential {
+        result.push((
+            *chat,
+            "telegram.chats.residential".to_string(),
+            vec![Need::Member, Need::Administrator, Need::ManageTopics],
+        ));
+    }
+    for chat in &chats.archive {
+        result.push((
+            *chat,
+            "telegram.chats.archive".to_string(),
+            vec![Need::Member],
+        ));
+    }
+    for (name, thread, exclusive) in threads(config) {
+        let mut needs = vec![Need::Member];
+        if thread.thread != GENERAL_THREAD_ID {
+            needs.push(Need::Forum);
+        }
+        if exclusive && name != "telegram.chats.ask_to_visit" {
+            // needs and borrowed_items pin messages
+            needs.push(Need::PinMessages);
+        }
+        result.push((thread.chat, name, needs));
+    }
+    if let Some(chat) = config.backup.as_ref().and_then(|b| b.chat) {
+        result.push((chat, "backup.chat".to_string(), vec![Need::Member]));
+    }
+    for fwd in &chats.forward_pins {
+        result.push((
+            fwd.from,
+            "forward_pins.from".to_string(),
+            vec![Need::Member],
+        ));
+        result.push((
+            fwd.to,
+            "forward_pins.to".to_string(),
+            vec![Need::Member],
+        ));
+    }
+    result
+}
+
+/// Online checks of the config, via Telegram Bot API at `api_url`.
+pub async fn check_online(
+    config: &Config,
+    api_url: Url,
+) -> Result<Vec<Problem>> {
+    let mut problems = Problems::default();
+    let bot = Bot::new(&config.telegram.token).set_api_url(api_url);
+    let me = bot.get_me().await.context("getMe failed, invalid token?")?;
+
+    let mut by_chat: HashMap<ChatId, Vec<(String, Vec<Need>)>> = HashMap::new();
+    for (chat, name, needs) in chat_needs(config) {
+        by_chat.entry(chat).or_default().push((name, needs));
+    }
+    let mut by_chat = by_chat.into_iter().collect::<Vec<_>>();
+    by_chat.sort_by_key(|(chat, _)| *chat);
+
+    for (chat_id, users) in by_chat {
+        let chat = match bot.get_chat(chat_id).await {
+            Ok(chat) => chat,
+            Err(e) => {
+                problems.error(format!("chat {chat_id}: getChat failed: {e}"));
+                continue;
+            }
+        };
+        let member = match bot.get_chat_member(chat_id, me.id).await {
+            Ok(member) => member,
+            Err(e) => {
+                problems.error(format!(
+                    "chat {chat_id}: getChatMember failed: {e}"
+                ));
+                continue;
+            }
+        };
+        let title = chat.title().unwrap_or_default();
+        for (name, needs) in users {
+            for need in needs {
+                if let Some((severity, text)) =
+                    check_need(need, &chat.kind, &member)
+                {
+                    problems.0.push(Problem {
+                        severity,
+                        text: format!("{name} ({chat_id} {title:?}): {text}"),
+                    });
+                }
+            }
+        }
+    }
+    Ok(problems.0)
+}
+
+fn check_need(
+    need: Need,
+    chat: &ChatKind,
+    member: &ChatMember,
+) -> Option<(Severity, &'static str)> {
+    let is_channel = matches!(
+        chat,
+        ChatKind::Public(p) if matches!(p.kind, PublicChatKind::Channel(_))
+    );
+    let is_forum = matches!(
+        chat,
+        ChatKind::Public(p) if matches!(
+            p.kind,
+            PublicChatKind::Supergroup(PublicChatSupergroup {
+                is_forum: true,
+                ..
+            })
+        )
+    );
+    let admin = match &member.kind {
+        ChatMemberKind::Administrator(admin) => Some(admin),
+        _ => None,
+    };
+    let ok = match need {
+        Need::Member if is_channel => {
+            admin.is_some_and(|a| a.can_post_messages)
+        }
+        Need::Member => match &member.kind {
+            ChatMemberKind::Restricted(r) => r.is_member && r.can_send_messages,
+            kind => kind.is_present(),
+        },
+        Need::Forum => is_forum,
+        Need::Administrator => admin.is_some(),
+        Need::PinMessages => admin.is_some_and(|a| a.can_pin_messages),
+        // rename_closed_topics only acts in chats with topics
+        Need::ManageTopics => {
+            !is_forum || admin.is_some_and(|a| a.can_manage_topics)
+        }
+    };
+    if ok {
+        return None;
+    }
+    Some(match need {
+        Need::Member => {
+            (Severity::Error, "the bot is not a member or can't send messages")
+        }
+        Need::Forum => (Severity::Error, "the chat has no topics"),
+        Need::Administrator => (
+            Severity::Warning,
+            "the bot is not an admin, so joins and leaves are not tracked",
+        ),
+        Need::PinMessages => {
+            (Severity::Error, "the bot has no \"Pin Messages\" admin right")
+        }
+        Need::ManageTopics => (
+            Severity::Warning,
+            "the bot has no \"Manage Topics\" admin right, closed topics \
+            won't be renamed",
+        ),
+    })
+}
+
+/// The `check-config` subcommand.
+pub async fn run(
+    config_fpath: &OsStr,
+    online: bool,
+    api_url: Url,
+) -> Result<()> {
+    let config = crate::parse_config(config_fpath)?;
+    let mut problems = check(&config);
+    if online {
+        problems.extend(check_online(&config, api_url).await?);
+    }
+
+    for problem in &problems {
+        match problem.severity {
+            Severity::Warning => log::warn!("{}", problem.text),
+            Severity::Error => log::error!("{}", problem.text),
+        }
+    }
+    let errors =
+        problems.iter().filter(|p| p.severity == Severity::Error).count();
+    if errors > 0 {
+        anyhow::bail!("Found {errors} errors in the config");
+    }
+    log::info!("Config is OK");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use teloxide::types::{MessageId, ThreadId};
+
+    use super::*;
+    use crate::config::FowardPins;
+
+    #[test]
+    fn test_check() {
+        let config = crate::testing::test_config();
+        assert!(
+            check(&config).is_empty(),
+            "{:?}",
+            check(&config).iter().map(ToString::to_string).collect::<Vec<_>>(),
+        );
+
+        let mut config = crate::testing::test_config();
+        config.telegram.chats.vortex_of_doom.as_mut().unwrap().schedule =
+            "daily".to_string();
+        config.telegram.chats.ask_to_visit = config.telegram.chats.needs;
+        config.telegram.chats.mac_monitoring = config.telegram.chats.dashboard;
+        config.services.mikrotik.as_mut().unwrap().host =
+            "10.0.0.1/path".to_string();
+        config.telegram.chats.forward_pins.push(FowardPins {
+            from: ChatId(1),
+            to: ChatId(1),
+            ignore_threads: vec![ThreadId(MessageId(2))],
+        });
+        let problems = check(&config);
+        assert_eq!(
+            problems
+                .iter()
+                .map(|p| p.text.split(':').next().unwrap())
+                .collect::<Vec<_>>(),
+            [
+                "telegram.chats.vortex_of_doom.schedule",
+                "services.mikrotik.host",
+                "telegram.chats.needs and telegram.chats.ask_to_visit",
+                "telegram.chats.forward_pins[1]",
+            ],
+        );
+        assert!(problems.iter().all(|p| p.severity == Severity::Error));
+
+        // Disabled modules are not checked
+        let mut config = crate::testing::test_config();
+        config.telegram.chats.vortex_of_doom = None;
+        config.telegram.chats.needs = None;
+        config.telegram.chats.ask_to_visit = None;
+        config.services.wikijs = None;
+        let problems = check(&config);
+        assert_eq!(
+            problems.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            [
+                "warning: telegram.chats.dashboard: ignored, since \
+                services.wikijs is not configured",
+                "warning: telegram.chats.wikijs_updates: ignored, since \
+                services.wikijs is not configured",
+            ],
+        );
+
+        let mut config = crate::testing::test_config();
+        config.postgres = Some(crate::config::Postgres {
+            url: String::new(),
+            url_file: None,
+        });
+        let problems = check(&config);
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].text.starts_with("postgres"));
+    }
+
+    #[test]
+    fn test_ensure_valid() {
+        // Shared threads were allowed before, so they are only logged.
+        let mut config = crate::testing::test_config();
+        config.telegram.chats.ask_to_visit = config.telegram.chats.needs;
+        assert!(check(&config).iter().any(|p| p.severity == Severity::Error));
+        ensure_valid(&config).unwrap();
+
+        config.telegram.chats.vortex_of_doom.as_mut().unwrap().schedule =
+            "daily".to_string();
+        let error = ensure_valid(&config).unwrap_err().to_string();
+        assert!(error.contains("vortex_of_doom.schedule"), "{error}");
+    }
+}
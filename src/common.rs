@@ -3,23 +3,25 @@
 use std::collections::HashMap;
 use std::fmt::Write;
 use std::path::PathBuf;
-use std::sync::{Arc, Mutex, MutexGuard};
+use std::sync::Arc;
 
 use anyhow::Result;
-use diesel::{
-    ExpressionMethods, QueryDsl, QueryResult, RunQueryDsl, SqliteConnection,
-};
+use arc_swap::ArcSwap;
+use diesel::{ExpressionMethods, QueryDsl, QueryResult, RunQueryDsl};
 use itertools::Itertools;
 use ldap_rs::LdapClient;
 use teloxide::requests::Requester;
-use teloxide::types::{Me, Message, StickerKind, User, UserId};
+use teloxide::types::{Me, Message, StickerKind, UserId};
 use teloxide::utils::command::BotCommands;
 use teloxide::utils::html::escape;
 use teloxide::Bot;
 
-use crate::config::Config;
-use crate::db::DbUserId;
-use crate::utils::{BotExt, GENERAL_THREAD_ID};
+use crate::config::{Config, ConfigChange};
+use crate::db::{DbConnection, DbUserId, Pool, PooledConnection};
+use crate::i18n::{msg_lang, tr};
+use crate::utils::{
+    ldap, BotExt, RateLimited, RateLimiter, ResultExt as _, GENERAL_THREAD_ID,
+};
 
 /// Wrapper around [`teloxide::dispatching::UpdateHandler`] to be used in this
 /// crate.
@@ -39,6 +41,8 @@ pub struct CommandAccessRules {
     pub in_group: bool,
     /// Allow users to execute this command only in resident chat
     pub in_resident_chat: bool,
+    /// Require an user to have this role, see [`has_role`]
+    pub role: Option<&'static str>,
 }
 
 impl CommandAccessRules {
@@ -49,6 +53,7 @@ impl CommandAccessRules {
             in_private: true,
             in_group: true,
             in_resident_chat: false,
+            role: None,
         }
     }
 }
@@ -70,28 +75,63 @@ pub trait BotCommandsExtTrait: BotCommands {
 
 /// Bot environment: global state shared between all handlers.
 pub struct BotEnv {
-    pub conn: Mutex<SqliteConnection>,
-    pub config: Arc<Config>,
+    /// Shared with the web server, see [`BotEnv::conn`].
+    pub db: Pool,
+    /// The current config, see [`BotEnv::config`].
+    pub config: ArcSwap<Config>,
     pub config_path: PathBuf,
+    /// Notified after the config is reloaded.
+    pub config_reloaded: tokio::sync::Notify,
     pub reqwest_client: reqwest::Client,
     pub openai_client: async_openai::Client<async_openai::config::OpenAIConfig>,
     // For some reason std mutexes not working in teloxide handlers
-    pub ldap_client: tokio::sync::Mutex<LdapClient>,
+    /// `None` if the bot is running without LDAP, e.g. in `replay` mode.
+    pub ldap_client: tokio::sync::Mutex<Option<LdapClient>>,
+    /// Command invocations per user and command name.
+    pub rate_limiter: RateLimiter<(UserId, String)>,
 }
 
 impl BotEnv {
-    pub fn conn(&self) -> MutexGuard<'_, SqliteConnection> {
-        self.conn.lock().unwrap()
+    /// Take a database connection. It blocks the current thread until a
+    /// connection is available, so handlers should use [`Pool::run`] instead.
+    pub fn conn(&self) -> PooledConnection {
+        self.db.get()
+    }
+    /// A snapshot of the current config. Long-running tasks should take a
+    /// new snapshot from time to time to pick up reloaded config.
+    pub fn config(&self) -> Arc<Config> {
+        self.config.load_full()
     }
+
+    /// Re-read the config file, and replace the current config with it.
+    /// Returns changed settings. Paths are always kept as is, since they
+    /// could be overridden from the command line, and can't be changed
+    /// without a restart anyway.
+    pub fn reload_config(&self) -> Result<Vec<ConfigChange>> {
+        let mut config = crate::load_config(self.config_path.as_os_str())?;
+        let old = self.config();
+        config.paths = old.paths.clone();
+        let changes = crate::config::diff(&old, &config);
+        self.config.store(Arc::new(config));
+        self.config_reloaded.notify_waiters();
+        Ok(changes)
+    }
+
     pub fn transaction<T>(
         &self,
-        f: impl FnOnce(&mut SqliteConnection) -> QueryResult<T>,
+        f: impl FnOnce(&mut DbConnection) -> QueryResult<T>,
     ) -> QueryResult<T> {
-        self.conn().exclusive_transaction(f)
+        crate::db::write_transaction(&mut self.conn(), f)
     }
 
-    pub async fn ldap_client(&self) -> tokio::sync::MutexGuard<'_, LdapClient> {
-        self.ldap_client.lock().await
+    pub async fn ldap_client(
+        &self,
+    ) -> Result<tokio::sync::MappedMutexGuard<'_, LdapClient>> {
+        tokio::sync::MutexGuard::try_map(
+            self.ldap_client.lock().await,
+            Option::as_mut,
+        )
+        .map_err(|_| anyhow::anyhow!("LDAP client is not available"))
     }
 }
 
@@ -150,7 +190,7 @@ macro_rules! BotCommandsExt {
         #[custom( $( $meta_key:ident = $meta_value:expr ),* $(,)? )]
         $( #[ $( $rest:tt )* ] )*
     ) => {
-        $( $name.$meta_key = $meta_value; )*
+        $( BotCommandsExt!(impl set_field; $name; $meta_key; $meta_value); )*
         BotCommandsExt!(impl set_meta; $name; $( #[ $( $rest )* ] )* );
     };
     (
@@ -166,6 +206,14 @@ macro_rules! BotCommandsExt {
         $name:expr;
     ) => {};
 
+    // set_field
+    (impl set_field; $name:expr; role; $value:expr) => {
+        $name.role = Some($value);
+    };
+    (impl set_field; $name:expr; $key:ident; $value:expr) => {
+        $name.$key = $value;
+    };
+
     // skip_item_args
     (impl skip_item_args; $v:ident ) => { Self::$v };
     (impl skip_item_args; $v:ident($($t:ty),+) ) => { Self::$v(..) };
@@ -245,30 +293,69 @@ where
 {
     let cmd = C::parse(msg.text()?, &me.user.username?).ok()?;
     let rules = cmd.command_rules();
+    let name = command_name(msg.text()?)?;
+    let lang = msg_lang(&env, &msg).await;
+
+    if let Some(from) = &msg.from {
+        let limit = env.config().telegram.rate_limits.get(&name);
+        let limited = env.rate_limiter.check((from.id, name.clone()), limit);
+        if limited != RateLimited::No {
+            metrics::increment_counter!(
+                "botka_commands_rejected_total",
+                "command" => name,
+                "reason" => "rate_limit",
+            );
+            if let RateLimited::Notify(wait) = limited {
+                let text =
+                    tr!(lang, "command-rate-limited", seconds = wait.as_secs());
+                let _ = bot.reply_message(&msg, text).await;
+            }
+            return None;
+        }
+    }
 
-    let error_text = if !rules.in_group
+    let resident = if rules.resident {
+        let user_id = msg.from.as_ref()?.id;
+        env.db.run(move |conn| is_resident(conn, user_id)).await
+    } else {
+        true
+    };
+    let mut error_text = if !rules.in_group
         && (msg.chat.is_group() || msg.chat.is_supergroup())
     {
-        Some("This command is not allowed in group chats")
+        Some(tr!(lang, "command-not-in-group"))
     } else if !rules.in_private && msg.chat.is_private() {
-        Some("This command is not allowed in private chats")
+        Some(tr!(lang, "command-not-in-private"))
     } else if rules.admin
-        && !env.config.telegram.admins.contains(&msg.from.as_ref()?.id)
+        && !env.config().telegram.admins.contains(&msg.from.as_ref()?.id)
     {
-        Some("You must be an admin to execute this command")
-    } else if rules.resident
-        && !is_resident(&mut env.conn(), msg.from.as_ref()?)
-    {
-        Some("You must be a resident to execute this command")
+        Some(tr!(lang, "command-admin-only"))
+    } else if !resident {
+        Some(tr!(lang, "command-resident-only"))
     } else if rules.in_resident_chat
-        && !env.config.telegram.chats.residential.contains(&msg.chat.id)
+        && !env.config().telegram.chats.residential.contains(&msg.chat.id)
     {
-        Some("This command is allowed only in resident chat")
+        Some(tr!(lang, "command-resident-chat-only"))
     } else {
         None
     };
+    if let (None, Some(role)) = (&error_text, rules.role) {
+        let user_id = msg.from.as_ref()?.id;
+        let allowed = has_role(&env, user_id, role)
+            .await
+            .log_ok(module_path!(), "Failed to check user role")
+            .unwrap_or(false);
+        if !allowed {
+            error_text = Some(tr!(lang, "command-role-only", role = role));
+        }
+    }
 
     if let Some(error_text) = error_text {
+        metrics::increment_counter!(
+            "botka_commands_rejected_total",
+            "command" => name,
+            "reason" => "access",
+        );
         let _ = bot.reply_message(&msg, error_text).await;
         return None;
     }
@@ -276,10 +363,18 @@ where
     Some(cmd)
 }
 
-pub fn is_resident(conn: &mut SqliteConnection, user: &User) -> bool {
+/// Name of the command in the message text, without the leading slash and
+/// the bot username.
+fn command_name(text: &str) -> Option<String> {
+    let command = text.split_whitespace().next()?.strip_prefix('/')?;
+    let command = command.split_once('@').map_or(command, |(c, _)| c);
+    Some(command.to_lowercase())
+}
+
+pub fn is_resident(conn: &mut DbConnection, user_id: UserId) -> bool {
     crate::schema::residents::table
         .filter(crate::schema::residents::end_date.is_null())
-        .filter(crate::schema::residents::tg_id.eq(DbUserId::from(user.id)))
+        .filter(crate::schema::residents::tg_id.eq(DbUserId::from(user_id)))
         .count()
         .get_result::<i64>(conn)
         .ok()
@@ -287,6 +382,53 @@ pub fn is_resident(conn: &mut SqliteConnection, user: &User) -> bool {
         > 0
 }
 
+/// Whether the user has the role, either granted with `/grant_role` or via
+/// an LDAP group listed in `services.ldap.role_groups`. Bot admins have all
+/// roles.
+pub async fn has_role(
+    env: &BotEnv,
+    user_id: UserId,
+    role: &str,
+) -> Result<bool> {
+    let config = env.config();
+    if config.telegram.admins.contains(&user_id) {
+        return Ok(true);
+    }
+    let role_name = role.to_string();
+    let granted = env
+        .db
+        .run(move |conn| {
+            crate::schema::user_roles::table
+                .filter(
+                    crate::schema::user_roles::user_id
+                        .eq(DbUserId::from(user_id)),
+                )
+                .filter(crate::schema::user_roles::role.eq(role_name))
+                .count()
+                .get_result::<i64>(conn)
+        })
+        .await?
+        > 0;
+    if granted {
+        return Ok(true);
+    }
+
+    let Some(ldap_config) = &config.services.ldap else { return Ok(false) };
+    let Some(group) = ldap_config.role_groups.get(role) else {
+        return Ok(false);
+    };
+    let mut ldap_client = env.ldap_client().await?;
+    let Some(user) =
+        ldap::get_user(&mut ldap_client, ldap_config, user_id).await?
+    else {
+        return Ok(false);
+    };
+    let groups =
+        ldap::get_user_groups(&mut ldap_client, ldap_config, &user).await?;
+    drop(ldap_client);
+    Ok(groups.contains(group))
+}
+
 /// A container for associating emojis with topics.
 pub struct TopicEmojis(HashMap<String, String>);
 
@@ -357,6 +499,17 @@ mod tests {
 
         #[custom(in_private = true, in_group = true)]
         WithArgsAndCustom(i32, i32),
+
+        #[custom(role = "technician")]
+        WithRole,
+    }
+
+    #[derive(Clone, BotCommands, BotCommandsExt!)]
+    #[command(rename_rule = "snake_case")]
+    enum RoleCommands {
+        #[command(description = "fix something.")]
+        #[custom(role = "technician")]
+        Fix,
     }
 
     #[test]
@@ -385,5 +538,69 @@ mod tests {
                 ..Default::default()
             }
         );
+        assert_eq!(
+            MyCommand::WithRole.command_rules(),
+            CommandAccessRules {
+                role: Some("technician"),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_role_command() {
+        use teloxide::dispatching::UpdateFilterExt as _;
+        use teloxide::types::Update;
+
+        use crate::testing::{message, message_update, TestBot};
+
+        let t = TestBot::new().await;
+        let admin = crate::testing::test_config().telegram.admins[0].0;
+        let handler = Update::filter_message()
+            .chain(filter_command::<RoleCommands>())
+            .endpoint(|bot: Bot, msg: Message| async move {
+                bot.send_message(msg.chat.id, "Fixed.").await?;
+                Ok(())
+            });
+        let fix = |user: u64| {
+            message_update(message(
+                user.try_into().unwrap(),
+                None,
+                user,
+                "/fix",
+            ))
+        };
+        let reply = |t: &TestBot| {
+            let calls = t.api.take_calls();
+            calls[0].body["text"].as_str().unwrap().to_string()
+        };
+
+        t.dispatch_to(&handler, fix(100)).await.unwrap_err();
+        assert_eq!(
+            reply(&t),
+            "You must have the technician role to execute this command"
+        );
+
+        t.dispatch(message_update(message(
+            admin.try_into().unwrap(),
+            None,
+            admin,
+            "/grant_role technician 100",
+        )))
+        .await
+        .unwrap();
+        assert_eq!(reply(&t), "Role granted.");
+        t.dispatch_to(&handler, fix(100)).await.unwrap();
+        assert_eq!(reply(&t), "Fixed.");
+
+        // Admins have all roles.
+        t.dispatch_to(&handler, fix(admin)).await.unwrap();
+        assert_eq!(reply(&t), "Fixed.");
+
+        let help =
+            (crate::modules::ModuleCommands::of::<RoleCommands>().help)();
+        assert!(help.contains("/fix [technician]"), "{help}");
+
+        t.stop().await;
     }
 }
@@ -1,20 +1,126 @@
+use std::collections::HashSet;
 use std::fmt::Debug;
 use std::marker::PhantomData;
 
+use diesel::backend::Backend;
+use diesel::migration::MigrationSource;
 use diesel::result::Error::DeserializationError;
-use diesel::{
-    ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl,
-    SqliteConnection,
-};
+use diesel::upsert::excluded;
+use diesel::{ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl};
 use diesel_derive_newtype::DieselNewType;
+use diesel_migrations::{
+    embed_migrations, EmbeddedMigrations, MigrationHarness,
+};
+use itertools::Itertools;
 use salvo_oapi::ToSchema;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use teloxide::types::{ChatId, MessageId, Recipient, ThreadId, UserId};
 
+pub use self::pool::{Pool, PooledConnection};
 use crate::utils::GENERAL_THREAD_ID;
 use crate::{models, schema};
 
+mod pool;
+
+/// Database connection of the bot. PostgreSQL is used instead of SQLite if
+/// the bot is built with the `postgres` feature.
+#[cfg(not(feature = "postgres"))]
+pub type DbConnection = diesel::SqliteConnection;
+#[cfg(feature = "postgres")]
+pub type DbConnection = diesel::PgConnection;
+
+/// SQLite migrations from the `migrations` directory, embedded into the
+/// binary.
+pub const SQLITE_MIGRATIONS: EmbeddedMigrations =
+    embed_migrations!("migrations");
+
+/// PostgreSQL migrations from the `migrations_pg` directory. They have the
+/// same names as the SQLite ones, and should result in the same schema.
+#[cfg(feature = "postgres")]
+pub const PG_MIGRATIONS: EmbeddedMigrations =
+    embed_migrations!("migrations_pg");
+
+/// Migrations for [`DbConnection`].
+#[cfg(not(feature = "postgres"))]
+pub const MIGRATIONS: EmbeddedMigrations = SQLITE_MIGRATIONS;
+#[cfg(feature = "postgres")]
+pub const MIGRATIONS: EmbeddedMigrations = PG_MIGRATIONS;
+
+/// Run `f` in a transaction. On SQLite, the write lock is taken upfront, so
+/// that the transaction can't fail with `SQLITE_BUSY` halfway.
+#[cfg(not(feature = "postgres"))]
+pub fn write_transaction<T, E>(
+    conn: &mut DbConnection,
+    f: impl FnOnce(&mut DbConnection) -> Result<T, E>,
+) -> Result<T, E>
+where
+    E: From<diesel::result::Error>,
+{
+    conn.immediate_transaction(f)
+}
+
+/// Run `f` in a transaction.
+#[cfg(feature = "postgres")]
+pub fn write_transaction<T, E>(
+    conn: &mut DbConnection,
+    f: impl FnOnce(&mut DbConnection) -> Result<T, E>,
+) -> Result<T, E>
+where
+    E: From<diesel::result::Error>,
+{
+    diesel::Connection::transaction(conn, f)
+}
+
+/// Apply pending migrations and return their names. If `dry_run` is set, only
+/// return the names without applying anything.
+///
+/// Fails if the database has applied migrations unknown to this binary, i.e.
+/// it was migrated by a newer version of the bot.
+pub fn migrate(
+    conn: &mut DbConnection,
+    dry_run: bool,
+) -> anyhow::Result<Vec<String>> {
+    migrate_with(conn, MIGRATIONS, dry_run)
+}
+
+/// Like [`migrate`], but for a connection of any backend.
+pub fn migrate_with<DB: Backend>(
+    conn: &mut impl MigrationHarness<DB>,
+    migrations: EmbeddedMigrations,
+    dry_run: bool,
+) -> anyhow::Result<Vec<String>> {
+    let known = MigrationSource::<DB>::migrations(&migrations)
+        .map_err(anyhow::Error::msg)?
+        .iter()
+        .map(|m| m.name().version().to_string())
+        .collect::<HashSet<_>>();
+    let unknown = conn
+        .applied_migrations()
+        .map_err(anyhow::Error::msg)?
+        .into_iter()
+        .map(|v| v.to_string())
+        .filter(|v| !known.contains(v))
+        .collect_vec();
+    if !unknown.is_empty() {
+        anyhow::bail!(
+            "The database has migrations unknown to this binary ({}). \
+            Was it migrated by a newer version?",
+            unknown.join(", "),
+        );
+    }
+
+    let pending =
+        conn.pending_migrations(migrations).map_err(anyhow::Error::msg)?;
+    let names = pending.iter().map(|m| m.name().to_string()).collect_vec();
+    if !dry_run {
+        for migration in pending {
+            conn.run_migration(&*migration).map_err(anyhow::Error::msg)?;
+        }
+    }
+    Ok(names)
+}
+
 /// A definition for a typed value stored in the database table `options`.
 pub struct ConfigOptionDef<T: Serialize + DeserializeOwned> {
     key_name: &'static str,
@@ -40,14 +146,15 @@ impl<T: Serialize + DeserializeOwned> ConfigOptionDef<T> {
     /// Returns `Ok(None)` if the option is not set or deserialization fails.
     pub fn get(
         &self,
-        conn: &mut SqliteConnection,
+        conn: &mut DbConnection,
     ) -> diesel::QueryResult<Option<T>> {
         let value: Option<String> = schema::options::table
             .filter(schema::options::name.eq(self.key_name))
             .first::<models::ConfigOption>(conn)
             .optional()?
             .map(|option| option.value);
-        match serde_json::from_str::<T>(&value.unwrap_or_default()) {
+        let Some(value) = value else { return Ok(None) };
+        match serde_json::from_str::<T>(&value) {
             Ok(value) => Ok(Some(value)),
             Err(e) => {
                 log::error!(
@@ -62,26 +169,26 @@ impl<T: Serialize + DeserializeOwned> ConfigOptionDef<T> {
     /// Set the value of this option in the database.
     pub fn set(
         &self,
-        conn: &mut SqliteConnection,
+        conn: &mut DbConnection,
         value: &T,
     ) -> diesel::QueryResult<()> {
         let value = serde_json::to_string(value)
             .map_err(|e| DeserializationError(Box::new(e)))?;
-        diesel::replace_into(schema::options::table)
+        diesel::insert_into(schema::options::table)
             .values(models::ConfigOption {
                 name: self.key_name.to_string(),
                 value,
             })
+            .on_conflict(schema::options::name)
+            .do_update()
+            .set(schema::options::value.eq(excluded(schema::options::value)))
             .execute(conn)
             .map(|_| ())
     }
 
     /// Unset the value of this option in the database.
     #[allow(dead_code)]
-    pub fn unset(
-        &self,
-        conn: &mut SqliteConnection,
-    ) -> diesel::QueryResult<()> {
+    pub fn unset(&self, conn: &mut DbConnection) -> diesel::QueryResult<()> {
         diesel::delete(
             schema::options::table
                 .filter(schema::options::name.eq(self.key_name)),
@@ -176,3 +283,42 @@ impl From<DbThreadId> for ThreadId {
         Self(MessageId(id.0))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use diesel::connection::SimpleConnection as _;
+    use diesel::Connection as _;
+
+    use super::*;
+
+    #[test]
+    fn test_migrate() {
+        let mut conn =
+            DbConnection::establish(&crate::testing::test_database_url())
+                .unwrap();
+
+        let pending = migrate(&mut conn, true).unwrap();
+        assert_eq!(
+            pending,
+            [
+                "2023-09-24-190127_init",
+                "2026-10-16-120000_api_tokens",
+                "2026-10-17-120000_user_roles",
+                "2026-10-18-120000_chat_languages",
+                "2026-10-19-120000_tg_messages",
+                "2026-10-20-120000_tg_message_stats",
+            ]
+        );
+        assert_eq!(migrate(&mut conn, true).unwrap(), pending);
+
+        assert_eq!(migrate(&mut conn, false).unwrap(), pending);
+        assert!(migrate(&mut conn, false).unwrap().is_empty());
+
+        conn.batch_execute(
+            "INSERT INTO __diesel_schema_migrations (version) \
+            VALUES ('29991231000000')",
+        )
+        .unwrap();
+        assert!(migrate(&mut conn, false).is_err());
+    }
+}
@@ -0,0 +1,169 @@
+//! A pool of database connections shared by the bot and the web server.
+
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Condvar, Mutex};
+
+use anyhow::{Context as _, Result};
+use diesel::Connection as _;
+
+use super::DbConnection;
+
+/// How long a SQLite connection waits for a lock held by another connection
+/// before failing with `SQLITE_BUSY`, in milliseconds.
+#[cfg(not(feature = "postgres"))]
+const BUSY_TIMEOUT_MS: u32 = 5000;
+
+/// A fixed set of connections to the same database. SQLite connections are
+/// opened in WAL mode, so readers don't block the writer and vice versa.
+///
+/// Cloning a pool is cheap, clones share the connections.
+#[derive(Clone)]
+#[allow(clippy::module_name_repetitions)] // Re-exported in the super module.
+pub struct Pool(Arc<Inner>);
+
+struct Inner {
+    url: String,
+    idle: Mutex<Vec<DbConnection>>,
+    released: Condvar,
+}
+
+/// A connection taken from a [`Pool`], returned to it on drop.
+pub struct PooledConnection {
+    conn: Option<DbConnection>,
+    pool: Arc<Inner>,
+}
+
+impl Pool {
+    /// Open `size` connections to the database.
+    pub fn open(url: &str, size: usize) -> Result<Self> {
+        let idle =
+            (0..size.max(1)).map(|_| establish(url)).collect::<Result<_>>()?;
+        Ok(Self(Arc::new(Inner {
+            url: url.to_string(),
+            idle: Mutex::new(idle),
+            released: Condvar::new(),
+        })))
+    }
+
+    /// Take a connection, waiting until one is released if all of them are
+    /// in use. In async code, use [`Pool::run`] instead.
+    pub fn get(&self) -> PooledConnection {
+        let mut idle = self.0.idle.lock().unwrap();
+        let conn = loop {
+            if let Some(conn) = idle.pop() {
+                break conn;
+            }
+            idle = self.0.released.wait(idle).unwrap();
+        };
+        drop(idle);
+        PooledConnection { conn: Some(conn), pool: Arc::clone(&self.0) }
+    }
+
+    /// Run `f` with a connection on the blocking thread pool, so that waiting
+    /// for the connection and the query itself don't stall other tasks.
+    pub async fn run<T, F>(&self, f: F) -> T
+    where
+        F: FnOnce(&mut DbConnection) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let pool = self.clone();
+        match tokio::task::spawn_blocking(move || f(&mut pool.get())).await {
+            Ok(result) => result,
+            Err(e) => std::panic::resume_unwind(e.into_panic()),
+        }
+    }
+}
+
+fn establish(url: &str) -> Result<DbConnection> {
+    #[allow(unused_mut)] // Only configured for SQLite.
+    let mut conn = DbConnection::establish(url)
+        .context("Failed to connect to the database")?;
+    #[cfg(not(feature = "postgres"))]
+    diesel::connection::SimpleConnection::batch_execute(
+        &mut conn,
+        &format!(
+            "PRAGMA journal_mode = WAL;
+             PRAGMA synchronous = NORMAL;
+             PRAGMA busy_timeout = {BUSY_TIMEOUT_MS};"
+        ),
+    )
+    .context("Failed to configure database connection")?;
+    Ok(conn)
+}
+
+impl Deref for PooledConnection {
+    type Target = DbConnection;
+
+    fn deref(&self) -> &Self::Target {
+        self.conn.as_ref().expect("connection is taken")
+    }
+}
+
+impl DerefMut for PooledConnection {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.conn.as_mut().expect("connection is taken")
+    }
+}
+
+impl Drop for PooledConnection {
+    fn drop(&mut self) {
+        let Some(mut conn) = self.conn.take() else { return };
+        if std::thread::panicking() {
+            // The connection might be left in the middle of a transaction.
+            match establish(&self.pool.url) {
+                Ok(new_conn) => conn = new_conn,
+                Err(e) => log::error!("Failed to reopen connection: {e:?}"),
+            }
+        }
+        self.pool.idle.lock().unwrap().push(conn);
+        self.pool.released.notify_one();
+    }
+}
+
+// SQLite only, since it checks the WAL mode.
+#[cfg(all(test, not(feature = "postgres")))]
+mod tests {
+    use diesel::connection::SimpleConnection as _;
+    use diesel::RunQueryDsl as _;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_pool() {
+        let dir = std::env::temp_dir()
+            .join(format!("botka-test-pool-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let url = dir.join("test.db").display().to_string();
+        let pool = Pool::open(&url, 2).unwrap();
+
+        let mut conn = pool.get();
+        conn.batch_execute("CREATE TABLE t (x INTEGER)").unwrap();
+        // Another connection is available while the first one is taken.
+        let count = pool
+            .run(|conn| {
+                diesel::sql_query("INSERT INTO t VALUES (1)").execute(conn)
+            })
+            .await
+            .unwrap();
+        assert_eq!(count, 1);
+        drop(conn);
+
+        let journal_mode = pool
+            .run(|conn| {
+                #[derive(diesel::QueryableByName)]
+                struct Row {
+                    #[diesel(sql_type = diesel::sql_types::Text)]
+                    journal_mode: String,
+                }
+                diesel::sql_query("PRAGMA journal_mode")
+                    .get_result::<Row>(conn)
+                    .map(|r| r.journal_mode)
+            })
+            .await
+            .unwrap();
+        assert_eq!(journal_mode, "wal");
+
+        drop(pool);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
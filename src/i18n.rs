@@ -0,0 +1,275 @@
+//! Translations of bot replies.
+//!
+//! Messages are stored in `locales/<lang>.ftl` files in the [Fluent] format,
+//! and are looked up with the [`tr!`] macro. English is used for messages
+//! missing in other locales, but `test_locales` checks that there are none.
+//!
+//! The language of a reply is picked by [`lang_for`]:
+//! 1. The language set with `/language` for the chat. For private chats, it
+//!    is the language set by the user.
+//! 2. In group chats, the language set by the user in the private chat.
+//! 3. The language of the user's Telegram client.
+//! 4. English.
+//!
+//! Messages not addressed to a particular user (e.g. pinned lists) use
+//! [`chat_lang`], i.e. only the first step.
+//!
+//! [Fluent]: https://projectfluent.org/
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use diesel::prelude::*;
+use fluent_bundle::concurrent::FluentBundle;
+use fluent_bundle::{FluentArgs, FluentResource};
+use teloxide::types::{CallbackQuery, ChatId, Message, User};
+
+use crate::common::BotEnv;
+use crate::db::{DbChatId, DbConnection};
+use crate::schema;
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub enum Lang {
+    #[default]
+    En,
+    Ru,
+    Be,
+}
+
+impl Lang {
+    pub const ALL: [Self; 3] = [Self::En, Self::Ru, Self::Be];
+
+    pub const fn code(self) -> &'static str {
+        match self {
+            Self::En => "en",
+            Self::Ru => "ru",
+            Self::Be => "be",
+        }
+    }
+
+    /// The name of the language in itself.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::En => "English",
+            Self::Ru => "Русский",
+            Self::Be => "Беларуская",
+        }
+    }
+
+    /// Parse an IETF language tag, e.g. `ru` or `be-BY`.
+    pub fn from_code(code: &str) -> Option<Self> {
+        let primary = code.split(['-', '_']).next()?;
+        Self::ALL.into_iter().find(|l| l.code().eq_ignore_ascii_case(primary))
+    }
+
+    const fn source(self) -> &'static str {
+        match self {
+            Self::En => include_str!("../locales/en.ftl"),
+            Self::Ru => include_str!("../locales/ru.ftl"),
+            Self::Be => include_str!("../locales/be.ftl"),
+        }
+    }
+}
+
+type Bundle = FluentBundle<FluentResource>;
+
+fn bundles() -> &'static HashMap<Lang, Bundle> {
+    static BUNDLES: OnceLock<HashMap<Lang, Bundle>> = OnceLock::new();
+    BUNDLES.get_or_init(|| {
+        Lang::ALL.into_iter().map(|lang| (lang, make_bundle(lang))).collect()
+    })
+}
+
+fn make_bundle(lang: Lang) -> Bundle {
+    let resource = FluentResource::try_new(lang.source().to_string())
+        .unwrap_or_else(|(resource, errors)| {
+            log::error!("Failed to parse {} locale: {errors:?}", lang.code());
+            resource
+        });
+    let mut bundle = Bundle::new_concurrent(vec![lang
+        .code()
+        .parse()
+        .expect("invalid language code")]);
+    // Unicode isolation marks around arguments confuse Telegram clients.
+    bundle.set_use_isolating(false);
+    if let Err(errors) = bundle.add_resource(resource) {
+        log::error!("Failed to load {} locale: {errors:?}", lang.code());
+    }
+    bundle
+}
+
+/// Translate a message. Use the [`tr!`] macro instead of calling this
+/// directly, so that `test_locales` can find the message key.
+pub fn translate(
+    lang: Lang,
+    key: &str,
+    args: Option<&FluentArgs<'_>>,
+) -> String {
+    for lang in [lang, Lang::En] {
+        let bundle = &bundles()[&lang];
+        let Some(pattern) = bundle.get_message(key).and_then(|m| m.value())
+        else {
+            continue;
+        };
+        let mut errors = Vec::new();
+        let text = bundle.format_pattern(pattern, args, &mut errors);
+        if !errors.is_empty() {
+            log::error!("Failed to format {key:?} in {lang:?}: {errors:?}");
+        }
+        return text.into_owned();
+    }
+    log::error!("Unknown message {key:?}");
+    key.to_string()
+}
+
+/// Translate a message, e.g. `tr!(lang, "needs-user-needs", user = name)`.
+macro_rules! tr {
+    ($lang:expr, $key:literal $(,)?) => {
+        $crate::i18n::translate($lang, $key, None)
+    };
+    ($lang:expr, $key:literal, $($name:ident = $value:expr),+ $(,)?) => {{
+        let mut args = ::fluent_bundle::FluentArgs::new();
+        $( args.set(stringify!($name), $value); )+
+        $crate::i18n::translate($lang, $key, Some(&args))
+    }};
+}
+pub(crate) use tr;
+
+/// The language set for the chat with `/language`, if any.
+fn stored_lang(conn: &mut DbConnection, chat: ChatId) -> Option<Lang> {
+    let code: Option<String> = schema::chat_languages::table
+        .filter(schema::chat_languages::chat_id.eq(DbChatId::from(chat)))
+        .select(schema::chat_languages::lang)
+        .first(conn)
+        .optional()
+        .ok()
+        .flatten();
+    code.as_deref().and_then(Lang::from_code)
+}
+
+/// Set or reset the language of the chat.
+pub async fn set_chat_lang(
+    env: &BotEnv,
+    chat: ChatId,
+    lang: Option<Lang>,
+) -> QueryResult<()> {
+    let chat_id = DbChatId::from(chat);
+    env.db
+        .run(move |conn| match lang {
+            Some(lang) => diesel::insert_into(schema::chat_languages::table)
+                .values((
+                    schema::chat_languages::chat_id.eq(chat_id),
+                    schema::chat_languages::lang.eq(lang.code()),
+                ))
+                .on_conflict(schema::chat_languages::chat_id)
+                .do_update()
+                .set(schema::chat_languages::lang.eq(lang.code()))
+                .execute(conn),
+            None => diesel::delete(schema::chat_languages::table)
+                .filter(schema::chat_languages::chat_id.eq(chat_id))
+                .execute(conn),
+        })
+        .await?;
+    Ok(())
+}
+
+/// Language of messages in the chat not addressed to a particular user.
+pub async fn chat_lang(env: &BotEnv, chat: ChatId) -> Lang {
+    env.db.run(move |conn| stored_lang(conn, chat)).await.unwrap_or_default()
+}
+
+/// Language of replies to the user in the chat.
+pub async fn lang_for(env: &BotEnv, chat: ChatId, user: Option<&User>) -> Lang {
+    let user_chat = user.map(|user| ChatId::from(user.id));
+    let client_lang =
+        user.and_then(|user| Lang::from_code(user.language_code.as_deref()?));
+    env.db
+        .run(move |conn| {
+            stored_lang(conn, chat).or_else(|| stored_lang(conn, user_chat?))
+        })
+        .await
+        .or(client_lang)
+        .unwrap_or_default()
+}
+
+/// Language of replies to the message.
+pub async fn msg_lang(env: &BotEnv, msg: &Message) -> Lang {
+    lang_for(env, msg.chat.id, msg.from.as_ref()).await
+}
+
+/// Language of answers to the callback query.
+pub async fn callback_lang(env: &BotEnv, callback: &CallbackQuery) -> Lang {
+    let chat = callback
+        .message
+        .as_ref()
+        .map_or_else(|| ChatId::from(callback.from.id), |m| m.chat.id);
+    lang_for(env, chat, Some(&callback.from)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+    use std::path::Path;
+
+    use super::*;
+
+    fn message_ids(lang: Lang) -> BTreeSet<String> {
+        if let Err((_, errors)) =
+            FluentResource::try_new(lang.source().to_string())
+        {
+            panic!("Failed to parse {lang:?} locale: {errors:?}");
+        }
+        let re = regex::Regex::new(r"(?m)^([a-z][a-z0-9-]*) *=").unwrap();
+        re.captures_iter(lang.source()).map(|c| c[1].to_string()).collect()
+    }
+
+    fn used_keys(dir: &Path, re: &regex::Regex, keys: &mut BTreeSet<String>) {
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                used_keys(&path, re, keys);
+            } else if path.extension().is_some_and(|e| e == "rs") {
+                let source = std::fs::read_to_string(&path).unwrap();
+                keys.extend(
+                    re.captures_iter(&source).map(|c| c[1].to_string()),
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_locales() {
+        let en = message_ids(Lang::En);
+        for lang in Lang::ALL {
+            assert_eq!(message_ids(lang), en, "{lang:?} keys differ from en");
+        }
+
+        let re = regex::Regex::new(
+            r#"(?:^|[^a-zA-Z0-9_])tr!\([^"]*?"([a-z0-9-]+)""#,
+        )
+        .unwrap();
+        let mut used = BTreeSet::new();
+        used_keys(
+            &Path::new(env!("CARGO_MANIFEST_DIR")).join("src"),
+            &re,
+            &mut used,
+        );
+        assert!(used.contains("needs-empty"), "{used:?}");
+        let missing = used.difference(&en).collect::<Vec<_>>();
+        assert!(missing.is_empty(), "Keys missing in locales: {missing:?}");
+    }
+
+    #[test]
+    fn test_translate() {
+        assert_eq!(
+            tr!(Lang::En, "command-rate-limited", seconds = 5),
+            "Too many requests, try again in 5 seconds"
+        );
+        assert_eq!(
+            tr!(Lang::Ru, "poll-progress", voted = 2, pending = 5, users = "x"),
+            "Проголосовали 2 пользователя, не проголосовали 5 пользователей: x."
+        );
+        assert_eq!(Lang::from_code("be-BY"), Some(Lang::Be));
+        assert_eq!(Lang::from_code("uk"), None);
+    }
+}
@@ -23,40 +23,75 @@
 
 use std::ffi::{OsStr, OsString};
 use std::fs::File;
-use std::io::{BufRead, BufReader};
-use std::sync::{Arc, Mutex, OnceLock};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
 
 use anyhow::{Context as _, Result};
+use arc_swap::ArcSwap;
 use argh::FromArgs;
-use diesel::sqlite::SqliteConnection;
 use diesel::Connection;
 use metrics_exporter_prometheus::PrometheusBuilder;
-use modules::vortex_of_doom::vortex_of_doom;
 use tap::Pipe as _;
-use teloxide::dispatching::{Dispatcher, UpdateFilterExt};
-use teloxide::payloads::AnswerCallbackQuerySetters;
+use teloxide::dispatching::Dispatcher;
+use teloxide::error_handlers::LoggingErrorHandler;
+use teloxide::payloads::SetWebhookSetters;
 use teloxide::requests::Requester;
-use teloxide::types::{CallbackQuery, Message, Update};
+use teloxide::types::{AllowedUpdate, Update};
 use teloxide::Bot;
+use tokio::signal::unix::{signal, SignalKind};
 use tokio::task::JoinSet;
 use tokio_util::sync::CancellationToken;
-use utils::{ldap, HandlerExt as _};
+use utils::{ldap, ResultExt as _};
 
+mod check_config;
 mod common;
 mod config;
 mod db;
+mod i18n;
 mod metrics;
+#[cfg(feature = "postgres")]
+mod migrate_db;
 mod models;
 mod modules;
+mod replay;
 mod schema;
+#[cfg(test)]
+mod testing;
+mod trace_log;
+mod trace_redaction;
 mod tracing_proxy;
 mod utils;
 mod web_srv;
 
 static VERSION: OnceLock<String> = OnceLock::new();
 
-static DB_FILENAME: &str = "db.sqlite3";
-static TRACE_FILENAME: &str = "trace.jsonl";
+/// Update kinds requested in webhook mode. Unlike polling, the webhook is set
+/// before the dispatcher knows what its handlers need, so all kinds are
+/// requested.
+const ALL_ALLOWED_UPDATES: [AllowedUpdate; 14] = [
+    AllowedUpdate::Message,
+    AllowedUpdate::EditedMessage,
+    AllowedUpdate::ChannelPost,
+    AllowedUpdate::EditedChannelPost,
+    AllowedUpdate::InlineQuery,
+    AllowedUpdate::ChosenInlineResult,
+    AllowedUpdate::CallbackQuery,
+    AllowedUpdate::ShippingQuery,
+    AllowedUpdate::PreCheckoutQuery,
+    AllowedUpdate::Poll,
+    AllowedUpdate::PollAnswer,
+    AllowedUpdate::MyChatMember,
+    AllowedUpdate::ChatMember,
+    AllowedUpdate::ChatJoinRequest,
+];
+
+/// Number of database connections shared by handlers, tasks and the web
+/// server.
+const DB_POOL_SIZE: usize = 4;
+
+/// How long to wait for handlers and tasks to finish on shutdown.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
 
 fn version() -> &'static str {
     VERSION.get().expect("VERSION is not set")
@@ -77,6 +112,12 @@ struct Args {
 enum SubCommand {
     Bot(SubCommandBot),
     Scrape(SubCommandScrape),
+    Replay(SubCommandReplay),
+    Migrate(SubCommandMigrate),
+    Restore(SubCommandRestore),
+    #[cfg(feature = "postgres")]
+    MigrateDb(migrate_db::SubCommandMigrateDb),
+    CheckConfig(SubCommandCheckConfig),
 }
 
 /// run the bot
@@ -86,23 +127,107 @@ struct SubCommandBot {
     /// config file
     #[argh(positional)]
     config_file: OsString,
+
+    /// data directory, overrides `paths.data_dir` from the config
+    #[argh(option)]
+    data_dir: Option<PathBuf>,
+
+    /// db file, overrides `paths.db` from the config
+    #[argh(option)]
+    db: Option<PathBuf>,
+
+    /// trace file, overrides `paths.trace` from the config
+    #[argh(option)]
+    trace: Option<PathBuf>,
 }
 
 /// scrape the log
 #[derive(FromArgs, PartialEq, Debug)]
 #[argh(subcommand, name = "scrape")]
 struct SubCommandScrape {
-    /// db file
+    /// db file, or URL if built with the postgres feature
     #[argh(positional)]
     db_file: String,
 
-    /// log file
+    /// log file, rotated and compressed segments are read as well
     #[argh(positional)]
     log_file: OsString,
 
     /// list of residential_chats
     #[argh(positional)]
     residential_chats: Vec<i64>,
+
+    /// chat to archive messages of, like `telegram.chats.archive`, could be
+    /// repeated
+    #[argh(option)]
+    archive: Vec<i64>,
+}
+
+/// replay the log through the bot handlers, without network access
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(subcommand, name = "replay")]
+struct SubCommandReplay {
+    /// config file
+    #[argh(positional)]
+    config_file: OsString,
+
+    /// db file, or URL if built with the postgres feature (will be modified,
+    /// so use a copy)
+    #[argh(positional)]
+    db_file: String,
+
+    /// trace file written by the bot, rotated segments are read as well
+    #[argh(positional)]
+    trace: OsString,
+}
+
+/// apply pending database migrations
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(subcommand, name = "migrate")]
+struct SubCommandMigrate {
+    /// db file, or URL if built with the postgres feature
+    #[argh(positional)]
+    db_file: String,
+
+    /// only list pending migrations, do not apply them
+    #[argh(switch)]
+    dry_run: bool,
+}
+
+/// restore the database from a backup made by the bot, which should be
+/// stopped
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(subcommand, name = "restore")]
+struct SubCommandRestore {
+    /// backup file, compressed or not
+    #[argh(positional)]
+    backup_file: PathBuf,
+
+    /// db file to replace, it is kept next to the restored one
+    #[argh(positional)]
+    db_file: PathBuf,
+}
+
+/// check the config file
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(subcommand, name = "check-config")]
+struct SubCommandCheckConfig {
+    /// config file
+    #[argh(positional)]
+    config_file: OsString,
+
+    /// also check via Telegram that the bot is in the configured chats and
+    /// has the admin rights modules need
+    #[argh(switch)]
+    online: bool,
+
+    /// telegram Bot API base URL for online checks
+    #[argh(option, default = "default_api_url()")]
+    api_url: reqwest::Url,
+}
+
+fn default_api_url() -> reqwest::Url {
+    reqwest::Url::parse("https://api.telegram.org").unwrap()
 }
 
 #[tokio::main]
@@ -119,9 +244,28 @@ async fn main() -> Result<()> {
         .unwrap();
     log::info!("Version {}", version());
     match args.subcommand {
-        SubCommand::Bot(c) => run_bot(&c.config_file).await?,
+        SubCommand::Bot(c) => run_bot(c).await?,
         SubCommand::Scrape(c) => {
-            scrape_log(&c.db_file, &c.log_file, &c.residential_chats)?;
+            scrape_log(
+                &c.db_file,
+                &c.log_file,
+                &c.residential_chats,
+                &c.archive,
+            )?;
+        }
+        SubCommand::Replay(c) => {
+            replay::run(&c.config_file, &c.db_file, &c.trace).await?;
+        }
+        SubCommand::Migrate(c) => migrate(&c.db_file, c.dry_run)?,
+        SubCommand::Restore(c) => {
+            modules::backup::restore(&c.backup_file, &c.db_file)?;
+        }
+        #[cfg(feature = "postgres")]
+        SubCommand::MigrateDb(c) => {
+            migrate_db::run(&c.sqlite_file, &c.postgres_url)?;
+        }
+        SubCommand::CheckConfig(c) => {
+            check_config::run(&c.config_file, c.online, c.api_url).await?;
         }
     }
     Ok(())
@@ -129,17 +273,25 @@ async fn main() -> Result<()> {
 
 #[allow(clippy::too_many_lines)]
 #[allow(clippy::redundant_pub_crate)]
-async fn run_bot(config_fpath: &OsStr) -> Result<()> {
+async fn run_bot(args: SubCommandBot) -> Result<()> {
     let prometheus = PrometheusBuilder::new().install_recorder()?;
     metrics::register_metrics();
-    modules::borrowed_items::register_metrics();
+    let registry = Arc::new(modules::Registry::default());
+    registry.register_metrics();
 
-    let config: Arc<crate::config::Config> = Arc::new(
-        File::open(config_fpath)
-            .context("Failed to open config file")?
-            .pipe(serde_yaml::from_reader)
-            .context("Failed to parse config file")?,
-    );
+    let mut config = load_config(&args.config_file)?;
+    if let Some(data_dir) = args.data_dir {
+        config.paths.data_dir = data_dir;
+    }
+    if let Some(db) = args.db {
+        config.paths.db = db;
+    }
+    if let Some(trace) = args.trace {
+        config.paths.trace = trace;
+    }
+    std::fs::create_dir_all(&config.paths.data_dir)
+        .context("Failed to create data directory")?;
+    let config = Arc::new(config);
 
     if config.telegram.passive_mode {
         log::info!("Running in passive mode");
@@ -149,126 +301,180 @@ async fn run_bot(config_fpath: &OsStr) -> Result<()> {
         .danger_accept_invalid_certs(true)
         .build()?;
 
-    let ldap_client =
-        tokio::sync::Mutex::new(ldap::connect(&config.services.ldap).await?);
+    // Without LDAP, its commands reply that it's not available.
+    let ldap_client = match &config.services.ldap {
+        Some(ldap_config) => ldap::connect(ldap_config)
+            .await
+            .log_ok(module_path!(), "Failed to connect to LDAP"),
+        None => None,
+    };
+    let ldap_client = tokio::sync::Mutex::new(ldap_client);
+
+    let db = db::Pool::open(&config.database_url()?, DB_POOL_SIZE)?;
+    apply_migrations(&mut db.get())?;
 
     let bot_env = Arc::new(common::BotEnv {
-        conn: Mutex::new(SqliteConnection::establish(&format!(
-            "sqlite://{DB_FILENAME}"
-        ))?),
+        db,
         reqwest_client: reqwest_client.clone(),
         openai_client: async_openai::Client::with_config(
             async_openai::config::OpenAIConfig::new()
-                .with_api_key(config.services.openai.api_key.clone()),
+                .with_api_key(config.services.openai_api_key()),
         ),
-        config: Arc::<config::Config>::clone(&config),
-        config_path: config_fpath.into(),
+        config: ArcSwap::new(Arc::clone(&config)),
+        config_path: args.config_file.into(),
+        config_reloaded: tokio::sync::Notify::new(),
         ldap_client,
+        rate_limiter: utils::RateLimiter::new(),
     });
+    registry.load_state(&bot_env);
+
+    let trace = Arc::new(tracing_proxy::Trace::open(&config)?);
+    let proxy_addr = tracing_proxy::start(Arc::clone(&trace)).await?;
+    let bot = Bot::new(&config.telegram.token).set_api_url(proxy_addr);
+    let me = bot.get_me().await.context("Failed to get bot info")?;
+
+    let (webhook, webhook_listener) = match &config.telegram.webhook {
+        Some(webhook_config) => {
+            bot.set_webhook(webhook_config.url.clone())
+                .secret_token(webhook_config.secret_token.clone())
+                .allowed_updates(ALL_ALLOWED_UPDATES)
+                .await
+                .context("Failed to set webhook")?;
+            let (webhook, listener) = web_srv::webhook(
+                webhook_config.secret_token.clone(),
+                Arc::clone(&trace),
+            );
+            (Some(webhook), Some(listener))
+        }
+        None => (None, None),
+    };
 
-    let proxy_addr = tracing_proxy::start().await?;
-    let bot = Bot::new(&bot_env.config.telegram.token).set_api_url(proxy_addr);
-
-    let mac_monitoring_state = modules::mac_monitoring::state();
-
-    let mut dispatcher = Dispatcher::builder(
-        bot.clone(),
-        dptree::entry()
-            // should be the first handler
-            .inspect(modules::tg_scraper::inspect_update)
-            .inspect(modules::resident_tracker::inspect_update)
-            .branch(
-                Update::filter_message()
-                    .filter(|msg: Message, env: Arc<common::BotEnv>| {
-                        !msg.chat.is_channel()
-                            && !env.config.telegram.passive_mode
-                    })
-                    .inspect_err(modules::rename_closed_topics::inspect_message)
-                    .inspect_err(modules::forward_topic_pins::inspect_message)
-                    .branch(modules::basic::command_handler())
-                    .branch(modules::dashboard::command_handler())
-                    .branch(modules::userctl::command_handler())
-                    .branch(modules::polls::message_handler())
-                    .branch(modules::borrowed_items::command_handler())
-                    .branch(modules::needs::message_handler())
-                    .branch(modules::ask_to_visit::message_handler())
-                    .branch(modules::welcome::message_handler())
-                    .branch(modules::camera::command_handler())
-                    .branch(modules::ldap::command_handler())
-                    .endpoint(drop_endpoint),
-            )
-            .branch(
-                Update::filter_callback_query()
-                    .branch(modules::needs::callback_handler())
-                    .branch(modules::polls::callback_handler())
-                    .branch(modules::borrowed_items::callback_handler())
-                    .endpoint(drop_callback_query),
-            )
-            .branch(modules::polls::poll_answer_handler())
-            .endpoint(drop_endpoint),
-    )
-    .dependencies(dptree::deps![
-        modules::forward_topic_pins::state(),
-        modules::welcome::state(),
-        Arc::clone(&mac_monitoring_state),
-        Arc::clone(&bot_env)
-    ])
-    .build();
+    let mut dispatcher =
+        Dispatcher::builder(bot.clone(), registry.handler_tree())
+            .dependencies(registry.dependencies(Arc::clone(&bot_env)))
+            .build();
     let bot_shutdown_token = dispatcher.shutdown_token().clone();
     let mut set = JoinSet::new();
-    set.spawn(async move { dispatcher.dispatch().await });
+    set.spawn(async move {
+        if let Some(listener) = webhook_listener {
+            dispatcher
+                .dispatch_with_listener(
+                    listener,
+                    LoggingErrorHandler::with_custom_text(
+                        "An error from the webhook listener",
+                    ),
+                )
+                .await;
+        } else {
+            // Removes the webhook, if any, before polling.
+            dispatcher.dispatch().await;
+        }
+    });
 
     let cancel = CancellationToken::new();
 
-    if !bot_env.config.telegram.passive_mode {
-        set.spawn(modules::updates::task(
-            Arc::clone(&bot_env),
-            bot.clone(),
-            cancel.clone(),
-        ));
-    }
-
-    set.spawn(web_srv::run(
-        SqliteConnection::establish(&format!("sqlite://{DB_FILENAME}"))?,
-        Arc::clone(&bot_env.config),
-        prometheus,
+    registry.spawn_tasks(&mut set, &bot, &bot_env, &cancel);
+    set.spawn(tracing_proxy::redaction_task(
+        Arc::clone(&trace),
+        Arc::clone(&bot_env),
         cancel.clone(),
     ));
-
-    set.spawn(crate::modules::mac_monitoring::watch_loop(
-        Arc::clone(&bot_env),
-        mac_monitoring_state,
+    set.spawn(modules::command_menu::task(
         bot.clone(),
+        Arc::clone(&bot_env),
+        Arc::clone(&registry),
+        cancel.clone(),
     ));
 
-    set.spawn(vortex_of_doom(
+    let web_srv_stop = CancellationToken::new();
+    let web_srv = tokio::spawn(web_srv::run(
+        Arc::clone(&bot_env),
         bot.clone(),
-        reqwest_client.clone(),
-        Arc::clone(&config),
+        prometheus,
+        me.username().to_string(),
+        webhook,
+        cancel.clone(),
+        web_srv_stop.clone(),
     ));
 
-    run_signal_handler(bot_shutdown_token.clone(), cancel.clone());
+    run_reload_signal_handler(Arc::clone(&bot_env));
 
-    let first_ctrl_c = tokio::signal::ctrl_c();
-    tokio::select! {
-        _ = first_ctrl_c => {
-            let second_ctrl_c = tokio::signal::ctrl_c();
-            let wait = tokio::time::sleep(std::time::Duration::from_secs(5));
-            log::warn!("Waiting 5 seconds for tasks to finish.");
-            log::warn!("You can press ^C to cancel this.");
-            tokio::select! {
-                _ = second_ctrl_c => {
-                    set.abort_all();
-                }
-                () = wait => {
-                    set.abort_all();
-                }
-            };
+    shutdown_signal().await;
+    log::info!(
+        "Shutting down, waiting up to {} seconds for tasks to finish",
+        SHUTDOWN_TIMEOUT.as_secs(),
+    );
+    log::info!("Send the signal again to exit immediately");
+    cancel.cancel();
+    if bot_shutdown_token.shutdown().is_err() {
+        log::warn!("The dispatcher isn't running");
+    }
+    let finished = tokio::select! {
+        () = async { while set.join_next().await.is_some() {} } => true,
+        () = tokio::time::sleep(SHUTDOWN_TIMEOUT) => {
+            log::warn!("Timed out waiting for tasks, aborting them");
+            false
+        }
+        () = shutdown_signal() => {
+            log::warn!("Aborting tasks");
+            false
         }
     };
+    if !finished {
+        set.abort_all();
+        while set.join_next().await.is_some() {}
+    }
+
+    // Handlers and tasks are stopped, so the state won't change anymore.
+    registry.save_state(&bot_env);
+
+    web_srv_stop.cancel();
+    web_srv.await.ok();
+    log::info!("Shutdown complete");
+
+    Ok(())
+}
 
-    while (set.join_next().await).is_some() {}
+/// Parse the config file and resolve secrets, without further checks.
+fn parse_config(config_fpath: &OsStr) -> Result<config::Config> {
+    let mut config: config::Config = File::open(config_fpath)
+        .context("Failed to open config file")?
+        .pipe(serde_yaml::from_reader)
+        .context("Failed to parse config file")?;
+    config.resolve_secrets()?;
+    Ok(config)
+}
+
+/// Parse the config file and run static checks on it, see [`check_config`].
+fn load_config(config_fpath: &OsStr) -> Result<config::Config> {
+    let config = parse_config(config_fpath)?;
+    check_config::ensure_valid(&config)?;
+    Ok(config)
+}
 
+/// Apply pending migrations before using the database.
+fn apply_migrations(conn: &mut db::DbConnection) -> Result<()> {
+    for name in db::migrate(conn, false)
+        .context("Failed to apply database migrations")?
+    {
+        log::info!("Applied migration {name}");
+    }
+    Ok(())
+}
+
+fn migrate(db_fpath: &str, dry_run: bool) -> Result<()> {
+    let mut conn = db::DbConnection::establish(db_fpath)?;
+    let names = db::migrate(&mut conn, dry_run)?;
+    if names.is_empty() {
+        log::info!("No pending migrations");
+    }
+    for name in names {
+        if dry_run {
+            log::info!("Pending migration {name}");
+        } else {
+            log::info!("Applied migration {name}");
+        }
+    }
     Ok(())
 }
 
@@ -276,85 +482,73 @@ fn scrape_log(
     db_fpath: &str,
     log_fpath: &OsStr,
     residential_chats: &[i64],
+    archive: &[i64],
 ) -> Result<()> {
-    let mut conn = SqliteConnection::establish(db_fpath)?;
-    let mut log_file = File::open(log_fpath)?;
-    let mut buf_reader = BufReader::new(&mut log_file);
-    let mut line = String::new();
-
-    conn.exclusive_transaction(|conn| {
-        while buf_reader.read_line(&mut line)? > 0 {
+    let mut conn = db::DbConnection::establish(db_fpath)?;
+    let lines = trace_log::read_lines(Path::new(log_fpath))?;
+    let residential_chats = residential_chats
+        .iter()
+        .map(|&i| teloxide::types::ChatId(i))
+        .collect::<Vec<_>>();
+    let archive =
+        archive.iter().map(|&i| teloxide::types::ChatId(i)).collect::<Vec<_>>();
+
+    db::write_transaction(&mut conn, |conn| {
+        for line in lines {
+            let line = line?;
             if line.starts_with(r#"{"__f0bot":""#) {
                 // Ignore requests/responses for now
-                line.clear();
                 continue;
             }
             let update: Update = serde_json::from_str(&line)?;
-            modules::tg_scraper::scrape(conn, &update)?;
+            modules::tg_scraper::scrape(conn, &update, &archive)?;
             modules::resident_tracker::scrape(
                 conn,
                 &update,
-                &residential_chats
-                    .iter()
-                    .map(|&i| teloxide::types::ChatId(i))
-                    .collect::<Vec<_>>(),
+                &residential_chats,
             )?;
-            line.clear();
         }
         Result::<_, anyhow::Error>::Ok(())
     })?;
     Ok(())
 }
 
-async fn drop_callback_query(
-    bot: Bot,
-    callback_query: CallbackQuery,
-) -> Result<()> {
-    log::warn!(
-        "Unexpected callback query: {:?}",
-        serde_json::to_string(&callback_query).unwrap()
-    );
-    bot.answer_callback_query(&callback_query.id)
-        .text("Error: unexpected callback query")
-        .await?;
-    Ok(())
-}
-
-async fn drop_endpoint() -> Result<()> {
-    Ok(())
-}
-
-fn run_signal_handler(
-    bot_shutdown_token: teloxide::dispatching::ShutdownToken,
-    cancel: CancellationToken,
-) {
+/// Reload the config on SIGHUP.
+fn run_reload_signal_handler(env: Arc<common::BotEnv>) {
     tokio::spawn(async move {
-        loop {
-            tokio::signal::ctrl_c().await.expect("Failed to listen for SIGINT");
-            cancel.cancel();
-            match bot_shutdown_token.shutdown() {
-                #[allow(
-                    clippy::redundant_pub_crate,
-                    // reason = "https://github.com/rust-lang/rust-clippy/issues/10636"
-                )]
-                Ok(f) => {
-                    log::info!(
-                        "^C received, trying to shutdown the dispatcher..."
-                    );
-                    tokio::select! {
-                        () = f => {
-                            log::info!("dispatcher is shutdown...");
-                        }
-                        _ = tokio::signal::ctrl_c() => {
-                            log::info!("Got another ^C, exiting immediately");
-                            std::process::exit(0);
+        let mut hangup =
+            signal(SignalKind::hangup()).expect("Failed to listen for SIGHUP");
+        while hangup.recv().await.is_some() {
+            log::info!("SIGHUP received, reloading config");
+            match env.reload_config() {
+                Ok(changes) if changes.is_empty() => {
+                    log::info!("Config reloaded, no changes");
+                }
+                Ok(changes) => {
+                    for change in changes {
+                        if change.requires_restart() {
+                            log::warn!(
+                                "Config changed: {change} (restart required)"
+                            );
+                        } else {
+                            log::info!("Config changed: {change}");
                         }
                     }
                 }
-                Err(_) => {
-                    log::info!("^C received, the dispatcher isn't running, ignoring the signal");
-                }
+                Err(e) => log::error!("Failed to reload config: {e:?}"),
             }
         }
     });
 }
+
+/// Wait for SIGINT or SIGTERM.
+async fn shutdown_signal() {
+    let mut interrupt =
+        signal(SignalKind::interrupt()).expect("Failed to listen for SIGINT");
+    let mut terminate =
+        signal(SignalKind::terminate()).expect("Failed to listen for SIGTERM");
+    tokio::select! {
+        _ = interrupt.recv() => log::info!("SIGINT received"),
+        _ = terminate.recv() => log::info!("SIGTERM received"),
+    }
+}
@@ -1,6 +1,10 @@
-use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl, SqliteConnection};
+use std::path::Path;
+
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
 use teloxide::types::UserId;
 
+use crate::db::DbConnection;
+
 #[allow(clippy::module_name_repetitions)] // For conistency with other modules.
 pub fn register_metrics() {
     // Descriptions of labeled metrics
@@ -16,6 +20,14 @@ pub fn register_metrics() {
         "botka_user_online_status",
         "User online (in space) status."
     );
+    metrics::describe_counter!(
+        "botka_trace_write_errors_total",
+        "Number of failed writes to the trace file."
+    );
+    metrics::describe_counter!(
+        "botka_commands_rejected_total",
+        "Number of rejected command invocations, by command and reason."
+    );
 
     // Constant metrics
 
@@ -43,7 +55,7 @@ pub fn register_metrics() {
 
 /// Refresh some metrics before dumping them.
 #[allow(clippy::cast_precision_loss)] // Rounding errors are fine here.
-pub fn refresh(conn: &mut SqliteConnection) {
+pub fn refresh(conn: &mut DbConnection, db_path: &Path) {
     // botka_residents
     use crate::schema::residents::dsl as r;
     let resident_count = r::residents
@@ -55,16 +67,32 @@ pub fn refresh(conn: &mut SqliteConnection) {
     metrics::gauge!("botka_residents", resident_count);
 
     // botka_db_size_bytes
-    let db_size = std::fs::metadata(crate::DB_FILENAME)
-        .map(|m| m.len())
-        .unwrap_or_default() as f64;
+    let db_size = db_size(conn, db_path) as f64;
     metrics::describe_gauge!(
         "botka_db_size_bytes",
-        "Size of the database file in bytes."
+        "Size of the database in bytes."
     );
     metrics::gauge!("botka_db_size_bytes", db_size);
 }
 
+#[cfg(not(feature = "postgres"))]
+fn db_size(_conn: &mut DbConnection, db_path: &Path) -> u64 {
+    std::fs::metadata(db_path).map(|m| m.len()).unwrap_or_default()
+}
+
+#[cfg(feature = "postgres")]
+fn db_size(conn: &mut DbConnection, _db_path: &Path) -> u64 {
+    #[derive(diesel::QueryableByName)]
+    struct Size {
+        #[diesel(sql_type = diesel::sql_types::BigInt)]
+        size: i64,
+    }
+    diesel::sql_query("SELECT pg_database_size(current_database()) AS size")
+        .get_result::<Size>(conn)
+        .map(|s| s.size.try_into().unwrap_or_default())
+        .unwrap_or_default()
+}
+
 pub fn update_service(name: &'static str, success: bool) {
     metrics::gauge!(
         "botka_service_access_success",
@@ -0,0 +1,265 @@
+//! Copying an SQLite database into PostgreSQL, for switching an existing
+//! installation to the `postgres` feature.
+
+use anyhow::{Context as _, Result};
+use argh::FromArgs;
+use diesel::{
+    Connection as _, ExpressionMethods as _, PgConnection, QueryDsl as _,
+    RunQueryDsl as _, SqliteConnection,
+};
+
+use crate::db::{migrate_with, PG_MIGRATIONS, SQLITE_MIGRATIONS};
+use crate::schema;
+
+/// Rows per `INSERT` statement, well below the PostgreSQL limit of 65535
+/// bind parameters.
+const CHUNK_SIZE: usize = 1000;
+
+/// copy a SQLite database into an empty PostgreSQL one
+#[derive(FromArgs, PartialEq, Eq, Debug)]
+#[argh(subcommand, name = "migrate-db")]
+#[allow(clippy::module_name_repetitions)]
+pub struct SubCommandMigrateDb {
+    /// db file to copy from
+    #[argh(positional)]
+    pub sqlite_file: String,
+
+    /// postgres URL to copy to
+    #[argh(positional)]
+    pub postgres_url: String,
+}
+
+/// For each table, check that it's empty in PostgreSQL, then copy all rows
+/// from SQLite.
+macro_rules! copy_tables {
+    ($sqlite:expr, $pg:expr, $(
+        $table:ident { $($column:ident: $type:ty),* $(,)? }
+    ),* $(,)?) => {{
+        $(
+            let existing: i64 = schema::$table::table.count().get_result($pg)?;
+            if existing != 0 {
+                anyhow::bail!(
+                    "Table {} in PostgreSQL is not empty",
+                    stringify!($table),
+                );
+            }
+        )*
+        $(
+            let rows = schema::$table::table
+                .select(($(schema::$table::$column,)*))
+                .load::<($($type,)*)>($sqlite)
+                .with_context(|| {
+                    format!("Failed to read {}", stringify!($table))
+                })?;
+            for chunk in rows.chunks(CHUNK_SIZE) {
+                diesel::insert_into(schema::$table::table)
+                    .values(
+                        chunk
+                            .iter()
+                            .map(|($($column,)*)| {
+                                ($(schema::$table::$column.eq($column),)*)
+                            })
+                            .collect::<Vec<_>>(),
+                    )
+                    .execute($pg)
+                    .with_context(|| {
+                        format!("Failed to write {}", stringify!($table))
+                    })?;
+            }
+            log::info!("Copied {} rows of {}", rows.len(), stringify!($table));
+        )*
+    }};
+}
+
+/// Tables with a `SERIAL` primary key, whose sequences should continue after
+/// the copied rows.
+const SERIAL_TABLES: &[&str] =
+    &["api_tokens", "needed_items", "residents", "tg_messages"];
+
+/// Apply migrations to both databases and copy all data from SQLite to
+/// PostgreSQL in a single transaction.
+#[allow(clippy::too_many_lines)] // Mostly the list of tables.
+pub fn run(sqlite_file: &str, postgres_url: &str) -> Result<()> {
+    let mut sqlite = SqliteConnection::establish(sqlite_file)
+        .context("Failed to open the SQLite database")?;
+    let mut pg = PgConnection::establish(postgres_url)
+        .context("Failed to connect to PostgreSQL")?;
+    for name in migrate_with(&mut sqlite, SQLITE_MIGRATIONS, false)
+        .context("Failed to migrate the SQLite database")?
+    {
+        log::info!("Applied SQLite migration {name}");
+    }
+    for name in migrate_with(&mut pg, PG_MIGRATIONS, false)
+        .context("Failed to migrate the PostgreSQL database")?
+    {
+        log::info!("Applied PostgreSQL migration {name}");
+    }
+
+    pg.transaction(|pg| {
+        copy_tables!(&mut sqlite, pg,
+            api_tokens {
+                rowid: i32,
+                user_id: i64,
+                name: String,
+                token_hash: String,
+                created_at: chrono::NaiveDateTime,
+                last_used_at: Option<chrono::NaiveDateTime>,
+            },
+            borrowed_items {
+                chat_id: i64,
+                thread_id: i32,
+                user_message_id: i32,
+                bot_message_id: i32,
+                user_id: i64,
+                items: String,
+            },
+            chat_languages { chat_id: i64, lang: String },
+            dashboard_messages {
+                chat_id: i64,
+                thread_id: i32,
+                message_id: i32,
+                text: String,
+            },
+            needed_items {
+                rowid: i32,
+                request_chat_id: i64,
+                request_message_id: i32,
+                request_user_id: i64,
+                pinned_chat_id: i64,
+                pinned_message_id: i32,
+                buyer_user_id: Option<i64>,
+                item: String,
+            },
+            options { name: String, value: String },
+            residents {
+                rowid: i32,
+                tg_id: i64,
+                begin_date: chrono::NaiveDateTime,
+                end_date: Option<chrono::NaiveDateTime>,
+            },
+            tg_chat_topics {
+                chat_id: i64,
+                topic_id: i32,
+                closed: Option<bool>,
+                name: Option<String>,
+                icon_color: Option<i32>,
+                icon_emoji: Option<String>,
+                id_closed: i32,
+                id_name: i32,
+                id_icon_emoji: i32,
+            },
+            tg_chats {
+                id: i64,
+                kind: String,
+                username: Option<String>,
+                title: Option<String>,
+            },
+            tg_message_stats {
+                chat_id: i64,
+                thread_id: i32,
+                user_id: i64,
+                hour: chrono::NaiveDateTime,
+                messages: i32,
+            },
+            tg_message_stats_cursors { chat_id: i64, message_id: i32 },
+            tg_messages {
+                rowid: i32,
+                chat_id: i64,
+                message_id: i32,
+                thread_id: i32,
+                user_id: Option<i64>,
+                date: chrono::NaiveDateTime,
+                text: String,
+            },
+            tg_users {
+                id: i64,
+                username: Option<String>,
+                first_name: String,
+                last_name: Option<String>,
+            },
+            tg_users_in_chats {
+                chat_id: i64,
+                user_id: i64,
+                chat_member: Option<String>,
+                seen: bool,
+            },
+            tracked_polls {
+                tg_poll_id: String,
+                creator_id: i64,
+                info_chat_id: i64,
+                info_message_id: i32,
+                voted_users: String,
+            },
+            user_macs { tg_id: i64, mac: String },
+            user_roles {
+                user_id: i64,
+                role: String,
+                granted_by: i64,
+                granted_at: chrono::NaiveDateTime,
+            },
+        );
+        for table in SERIAL_TABLES {
+            diesel::sql_query(format!(
+                "SELECT setval(pg_get_serial_sequence('{table}', 'rowid'), \
+                COALESCE(MAX(rowid), 0) + 1, false) FROM {table}"
+            ))
+            .execute(pg)?;
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use diesel::connection::SimpleConnection as _;
+
+    use super::*;
+
+    #[test]
+    fn test_migrate_db() {
+        let dir = std::env::temp_dir()
+            .join(format!("botka-test-migrate-db-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let sqlite_file = dir.join("db.sqlite3").display().to_string();
+        let mut sqlite = SqliteConnection::establish(&sqlite_file).unwrap();
+        migrate_with(&mut sqlite, SQLITE_MIGRATIONS, false).unwrap();
+        sqlite
+            .batch_execute(
+                "INSERT INTO tg_users VALUES (1, 'user1', 'User', NULL);
+                 INSERT INTO residents VALUES
+                   (5, 1, '2024-01-01 00:00:00', NULL);
+                 INSERT INTO options VALUES ('key', '\"value\"');",
+            )
+            .unwrap();
+        drop(sqlite);
+
+        let pg_url = crate::testing::test_database_url();
+        run(&sqlite_file, &pg_url).unwrap();
+
+        let mut pg = PgConnection::establish(&pg_url).unwrap();
+        let users: Vec<(i64, Option<String>)> = schema::tg_users::table
+            .select((schema::tg_users::id, schema::tg_users::username))
+            .load(&mut pg)
+            .unwrap();
+        assert_eq!(users, [(1, Some("user1".to_string()))]);
+        // New rows don't collide with the copied ones.
+        let rowid: i32 = diesel::insert_into(schema::residents::table)
+            .values((
+                schema::residents::tg_id.eq(2),
+                schema::residents::begin_date.eq(diesel::dsl::now),
+            ))
+            .returning(schema::residents::rowid)
+            .get_result(&mut pg)
+            .unwrap();
+        assert_eq!(rowid, 6);
+
+        // The target must be empty.
+        let error = run(&sqlite_file, &pg_url).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Table options in PostgreSQL is not empty"
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
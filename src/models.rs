@@ -1,5 +1,6 @@
 //! Database and Serde models.
 
+use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
 
 use diesel::prelude::*;
@@ -59,6 +60,17 @@ pub struct NewTgUserInChat {
     pub seen: bool,
 }
 
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::tg_messages)]
+pub struct NewTgMessage<'a> {
+    pub chat_id: DbChatId,
+    pub message_id: DbMessageId,
+    pub thread_id: DbThreadId,
+    pub user_id: Option<DbUserId>,
+    pub date: chrono::NaiveDateTime,
+    pub text: &'a str,
+}
+
 #[derive(Clone, Debug, Queryable, Selectable)]
 #[diesel(table_name = crate::schema::tg_chat_topics)]
 pub struct TgChatTopic {
@@ -84,6 +96,34 @@ pub struct Resident {
     pub end_date: Option<chrono::NaiveDateTime>,
 }
 
+#[derive(Clone, Debug, Queryable, Selectable)]
+#[diesel(table_name = crate::schema::api_tokens)]
+pub struct ApiToken {
+    pub rowid: i32,
+    pub user_id: DbUserId,
+    pub name: String,
+    pub created_at: chrono::NaiveDateTime,
+    pub last_used_at: Option<chrono::NaiveDateTime>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::api_tokens)]
+pub struct NewApiToken<'a> {
+    pub user_id: DbUserId,
+    pub name: &'a str,
+    pub token_hash: &'a str,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable, Queryable, Selectable)]
+#[diesel(table_name = crate::schema::user_roles)]
+pub struct UserRole {
+    pub user_id: DbUserId,
+    pub role: String,
+    pub granted_by: DbUserId,
+    pub granted_at: chrono::NaiveDateTime,
+}
+
 #[derive(Insertable, Queryable, Selectable)]
 #[diesel(table_name = crate::schema::user_macs)]
 pub struct UserMac {
@@ -168,6 +208,9 @@ pub struct NeedsLastPin {
 }
 config_option_def!(wikijs_update_state, crate::utils::WikiJsUpdateState);
 config_option_def!(needs_last_pin, NeedsLastPin);
+config_option_def!(welcomed_users, HashSet<UserId>);
+config_option_def!(mac_monitoring_online, HashSet<UserId>);
+config_option_def!(command_menu_published, HashMap<String, String>);
 
 // Serde models
 
@@ -179,3 +222,60 @@ pub struct DataResident {
     pub first_name: String,
     pub last_name: Option<String>,
 }
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct DataNeededItem {
+    pub id: i32,
+    pub item: String,
+    #[salvo(schema(value_type = DbUserId))]
+    pub requested_by: UserId,
+    #[salvo(schema(value_type = Option<DbUserId>))]
+    pub bought_by: Option<UserId>,
+}
+
+impl From<NeededItem> for DataNeededItem {
+    fn from(item: NeededItem) -> Self {
+        Self {
+            id: item.rowid,
+            item: item.item,
+            requested_by: item.request_user_id.into(),
+            bought_by: item.buyer_user_id.map(Into::into),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct DataChatStats {
+    pub chat_id: DbChatId,
+    pub title: Option<String>,
+    pub messages: i64,
+    /// Topics of the chat, the most active first. Messages outside of topics
+    /// are counted in the General topic, with id 1.
+    pub topics: Vec<DataTopicStats>,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct DataTopicStats {
+    pub thread_id: DbThreadId,
+    pub name: Option<String>,
+    pub messages: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct DataPosterStats {
+    #[salvo(schema(value_type = DbUserId))]
+    pub id: UserId,
+    pub username: Option<String>,
+    pub first_name: String,
+    pub last_name: Option<String>,
+    pub messages: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct DataTopicGrowth {
+    /// A day when new topics got their first message.
+    pub date: chrono::NaiveDate,
+    pub new_topics: i64,
+    /// Number of topics including the new ones.
+    pub total_topics: i64,
+}
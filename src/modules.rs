@@ -1,19 +1,348 @@
 //! Modules that define the bot's functionality.
+//!
+//! Each module implements [`BotModule`] and is listed in [`Registry`], which
+//! builds the handler tree, handler dependencies, `/help` text, bot commands
+//! and background tasks from them.
 
+pub mod api_tokens;
 pub mod ask_to_visit;
+pub mod backup;
 pub mod basic;
 pub mod borrowed_items;
 pub mod camera;
+pub mod command_menu;
 pub mod dashboard;
 pub mod forward_topic_pins;
+pub mod language;
 pub mod ldap;
 pub mod mac_monitoring;
 pub mod needs;
 pub mod polls;
 pub mod rename_closed_topics;
 pub mod resident_tracker;
+pub mod roles;
+pub mod search;
+pub mod stats;
 pub mod tg_scraper;
 pub mod updates;
 pub mod userctl;
 pub mod vortex_of_doom;
 pub mod welcome;
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use dptree::di::DependencyMap;
+use futures::future::BoxFuture;
+use teloxide::dispatching::UpdateFilterExt;
+use teloxide::payloads::AnswerCallbackQuerySetters;
+use teloxide::requests::Requester;
+use teloxide::types::{BotCommand, CallbackQuery, Message, Update};
+use teloxide::utils::command::BotCommands;
+use teloxide::Bot;
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+
+use crate::common::{
+    BotCommandsExtTrait, BotEnv, CommandAccessRules, UpdateHandler,
+};
+use crate::i18n::{tr, Lang};
+use crate::utils::format_to;
+
+/// A part of the bot's functionality. All hooks are optional.
+pub trait BotModule: Send + Sync {
+    /// Module name, used in logs.
+    fn name(&self) -> &'static str;
+
+    /// Called for every update before any handler. The handler should pass
+    /// the update through, e.g. be built with [`dptree::inspect`].
+    fn inspect_update(&self) -> Option<UpdateHandler> {
+        None
+    }
+
+    /// Like [`BotModule::inspect_update`], but only for messages handled by
+    /// the bot, i.e. not in channels and not in passive mode.
+    fn inspect_message(&self) -> Option<UpdateHandler> {
+        None
+    }
+
+    fn message_handler(&self) -> Option<UpdateHandler> {
+        None
+    }
+
+    fn callback_handler(&self) -> Option<UpdateHandler> {
+        None
+    }
+
+    /// Handler for other kinds of updates, e.g. poll answers.
+    fn update_handler(&self) -> Option<UpdateHandler> {
+        None
+    }
+
+    /// Add the module state to the handler dependencies.
+    fn dependencies(&self, _deps: &mut DependencyMap) {}
+
+    /// Describe the module metrics. Called once on startup.
+    fn register_metrics(&self) {}
+
+    /// Commands of the module, shown in `/help` and in the Telegram menu.
+    fn commands(&self) -> Option<ModuleCommands> {
+        None
+    }
+
+    /// Restore in-memory state saved by [`BotModule::save_state`]. Called
+    /// once on startup, before any update is handled.
+    fn load_state(&self, _env: &BotEnv) {}
+
+    /// Persist in-memory state. Called once on shutdown, after all tasks and
+    /// handlers have stopped.
+    fn save_state(&self, _env: &BotEnv) {}
+
+    /// Background task, spawned on startup. The task should return soon
+    /// after `cancel` is cancelled.
+    fn task(
+        &self,
+        _bot: Bot,
+        _env: Arc<BotEnv>,
+        _cancel: CancellationToken,
+    ) -> Option<BoxFuture<'static, ()>> {
+        None
+    }
+}
+
+/// Commands enum of a module, with the type erased.
+#[derive(Clone, Copy)]
+pub struct ModuleCommands {
+    pub help: fn() -> String,
+    pub bot_commands: fn() -> Vec<BotCommand>,
+    pub rules: &'static [CommandAccessRules],
+}
+
+impl ModuleCommands {
+    pub fn of<T: BotCommands + BotCommandsExtTrait>() -> Self {
+        Self {
+            help: commands_help::<T>,
+            bot_commands: T::bot_commands,
+            rules: T::COMMAND_RULES,
+        }
+    }
+}
+
+/// All modules of the bot.
+pub struct Registry(Vec<Box<dyn BotModule>>);
+
+impl Default for Registry {
+    fn default() -> Self {
+        Self(vec![
+            // should be the first
+            Box::new(tg_scraper::Module),
+            Box::new(resident_tracker::Module),
+            Box::new(rename_closed_topics::Module),
+            Box::new(forward_topic_pins::Module::default()),
+            Box::new(basic::Module),
+            Box::new(language::Module),
+            Box::new(dashboard::Module),
+            Box::new(userctl::Module),
+            Box::new(roles::Module),
+            Box::new(search::Module),
+            Box::new(stats::Module),
+            Box::new(polls::Module),
+            Box::new(borrowed_items::Module),
+            Box::new(needs::Module),
+            Box::new(ask_to_visit::Module),
+            Box::new(welcome::Module::default()),
+            Box::new(camera::Module),
+            Box::new(ldap::Module),
+            Box::new(api_tokens::Module),
+            Box::new(mac_monitoring::Module::default()),
+            Box::new(updates::Module),
+            Box::new(vortex_of_doom::Module),
+            Box::new(backup::Module),
+        ])
+    }
+}
+
+impl Registry {
+    pub fn handler_tree(&self) -> UpdateHandler {
+        let mut tree = dptree::entry();
+        let mut messages = Update::filter_message().filter(
+            |msg: Message, env: Arc<BotEnv>| {
+                !msg.chat.is_channel() && !env.config().telegram.passive_mode
+            },
+        );
+        let mut callbacks = Update::filter_callback_query();
+        let mut other = Vec::new();
+        for module in &self.0 {
+            if let Some(handler) = module.inspect_update() {
+                tree = tree.chain(handler);
+            }
+            if let Some(handler) = module.inspect_message() {
+                messages = messages.chain(handler);
+            }
+        }
+        for module in &self.0 {
+            if let Some(handler) = module.message_handler() {
+                messages = messages.branch(handler);
+            }
+            if let Some(handler) = module.callback_handler() {
+                callbacks = callbacks.branch(handler);
+            }
+            other.extend(module.update_handler());
+        }
+        tree = tree
+            .branch(messages.endpoint(drop_endpoint))
+            .branch(callbacks.endpoint(drop_callback_query));
+        for handler in other {
+            tree = tree.branch(handler);
+        }
+        tree.endpoint(drop_endpoint)
+    }
+
+    /// Dependencies required by [`Registry::handler_tree`], except for ones
+    /// added by the dispatcher, i.e. [`Bot`] and [`teloxide::types::Me`].
+    pub fn dependencies(self: &Arc<Self>, env: Arc<BotEnv>) -> DependencyMap {
+        let mut deps = dptree::deps![env, Arc::clone(self)];
+        for module in &self.0 {
+            module.dependencies(&mut deps);
+        }
+        deps
+    }
+
+    pub fn register_metrics(&self) {
+        for module in &self.0 {
+            module.register_metrics();
+        }
+    }
+
+    pub fn load_state(&self, env: &BotEnv) {
+        for module in &self.0 {
+            module.load_state(env);
+        }
+    }
+
+    pub fn save_state(&self, env: &BotEnv) {
+        for module in &self.0 {
+            log::debug!("Saving {} state", module.name());
+            module.save_state(env);
+        }
+    }
+
+    pub fn spawn_tasks(
+        &self,
+        set: &mut JoinSet<()>,
+        bot: &Bot,
+        env: &Arc<BotEnv>,
+        cancel: &CancellationToken,
+    ) {
+        for module in &self.0 {
+            if let Some(task) =
+                module.task(bot.clone(), Arc::clone(env), cancel.clone())
+            {
+                log::debug!("Starting {} task", module.name());
+                set.spawn(task);
+            }
+        }
+    }
+
+    fn commands(&self) -> impl Iterator<Item = ModuleCommands> + '_ {
+        self.0.iter().filter_map(|m| m.commands())
+    }
+
+    /// Text of the `/help` command. Command descriptions are not
+    /// translated.
+    pub fn help(&self, lang: Lang) -> String {
+        let mut text = tr!(lang, "help-header");
+        text.push_str("\n\n");
+        for commands in self.commands() {
+            text.push_str(&(commands.help)());
+        }
+        text.push('\n');
+        text.push_str(&tr!(lang, "help-footer"));
+        text
+    }
+
+    /// Commands with access rules matching the predicate.
+    pub fn commands_where(
+        &self,
+        predicate: impl Fn(&CommandAccessRules) -> bool,
+    ) -> Vec<BotCommand> {
+        self.commands()
+            .flat_map(|c| std::iter::zip((c.bot_commands)(), c.rules))
+            .filter(|(_, rules)| predicate(rules))
+            .map(|(command, _)| command)
+            .collect()
+    }
+}
+
+fn commands_help<T: BotCommands + BotCommandsExtTrait>() -> String {
+    let descriptions = T::descriptions().to_string();
+    let global_description =
+        descriptions.find("\n\n/").map(|i| &descriptions[..i]);
+
+    let mut result = String::new();
+    if let Some(global_description) = global_description {
+        result.push_str(global_description);
+        result.push('\n');
+    }
+    for (cmd, rules) in std::iter::zip(&T::bot_commands(), T::COMMAND_RULES) {
+        result.push_str(&cmd.command);
+        result.push_str(match (rules.admin, rules.resident) {
+            (true, _) => "**",
+            (false, true) => "*",
+            (false, false) => "",
+        });
+        if let Some(role) = rules.role {
+            format_to!(result, " [{role}]");
+        }
+        result.push_str(
+            match (rules.in_private, rules.in_group, rules.in_resident_chat) {
+                (true, true, _) => "",
+                (true, false, _) => " (in private)",
+                (false, true, false) => " (not in private)",
+                (_, _, true) => " (in resident chat)",
+                (false, false, _) => " (disabled?)",
+            },
+        );
+        result.push_str(" — ");
+        result.push_str(&cmd.description);
+        result.push('\n');
+    }
+
+    result
+}
+
+async fn drop_callback_query(
+    bot: Bot,
+    callback_query: CallbackQuery,
+) -> Result<()> {
+    log::warn!(
+        "Unexpected callback query: {:?}",
+        serde_json::to_string(&callback_query).unwrap()
+    );
+    bot.answer_callback_query(&callback_query.id)
+        .text("Error: unexpected callback query")
+        .await?;
+    Ok(())
+}
+
+async fn drop_endpoint() -> Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_commands() {
+        let registry = Registry::default();
+        let help = registry.help(Lang::En);
+        assert!(help.contains("/debug_update_dashboard"), "{help}");
+        assert!(help.contains("/reload_config**"), "{help}");
+
+        let commands = registry.commands_where(|r| !r.admin);
+        let has = |name: &str| commands.iter().any(|c| c.command == name);
+        assert!(has("/help") && has("/needs") && has("/api_token"));
+        assert!(!has("/reload_config") && !has("/debug_update_dashboard"));
+    }
+}
@@ -0,0 +1,231 @@
+//! `/api_token` command to manage tokens for the HTTP API.
+//!
+//! Tokens are shown to the user only once, when created. Only SHA-256 hashes
+//! of tokens are stored in the database.
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use diesel::prelude::*;
+use macro_rules_attribute::derive;
+use passwords::PasswordGenerator;
+use sha2::{Digest, Sha256};
+use teloxide::macros::BotCommands;
+use teloxide::prelude::*;
+use teloxide::types::ParseMode;
+use teloxide::utils::html::escape;
+
+use super::{BotModule, ModuleCommands};
+use crate::common::{filter_command, BotCommandsExt, BotEnv, UpdateHandler};
+use crate::db::{DbConnection, DbUserId};
+use crate::utils::{format_to, BotExt};
+use crate::{models, schema, trace_redaction};
+
+const TOKEN_GENERATOR: PasswordGenerator = PasswordGenerator {
+    length: 32,
+    numbers: true,
+    lowercase_letters: true,
+    uppercase_letters: true,
+    symbols: false,
+    spaces: false,
+    exclude_similar_characters: false,
+    strict: true,
+};
+
+const USAGE: &str = "Usage:
+<code>/api_token</code> — list your tokens
+<code>/api_token new NAME</code> — create a new token
+<code>/api_token revoke ID</code> — revoke a token";
+
+#[derive(Clone, BotCommands, BotCommandsExt!)]
+#[command(rename_rule = "snake_case")]
+pub enum Commands {
+    #[command(description = "manage HTTP API tokens.")]
+    #[custom(in_group = false, resident = true)]
+    ApiToken(String),
+}
+
+pub struct Module;
+
+impl BotModule for Module {
+    fn name(&self) -> &'static str {
+        "api_tokens"
+    }
+
+    fn message_handler(&self) -> Option<UpdateHandler> {
+        Some(command_handler())
+    }
+
+    fn commands(&self) -> Option<ModuleCommands> {
+        Some(ModuleCommands::of::<Commands>())
+    }
+}
+
+fn command_handler() -> UpdateHandler {
+    filter_command::<Commands>().endpoint(cmd_api_token)
+}
+
+async fn cmd_api_token(
+    bot: Bot,
+    env: Arc<BotEnv>,
+    msg: Message,
+    Commands::ApiToken(args): Commands,
+) -> Result<()> {
+    let Some(from) = &msg.from else { return Ok(()) };
+    let user_id = DbUserId::from(from.id);
+    let args = args.trim();
+    let (subcommand, arg) =
+        args.split_once(char::is_whitespace).unwrap_or((args, ""));
+    let arg = arg.trim();
+
+    let text = match subcommand {
+        "" | "list" => list_tokens(&env, user_id)?,
+        "new" if !arg.is_empty() => {
+            let token = TOKEN_GENERATOR
+                .generate_one()
+                .map_err(|e| anyhow::anyhow!(e))?;
+            let _hidden = trace_redaction::hide_secret(&token);
+            diesel::insert_into(schema::api_tokens::table)
+                .values(models::NewApiToken {
+                    user_id,
+                    name: arg,
+                    token_hash: &hash_token(&token),
+                    created_at: chrono::Utc::now().naive_utc(),
+                })
+                .execute(&mut *env.conn())?;
+            bot.reply_message(
+                &msg,
+                format!(
+                    "Your new API token <b>{}</b>:\n<code>{token}</code>\n\n\
+                    It won't be shown again. Pass it as \
+                    <code>Authorization: Bearer TOKEN</code> header.",
+                    escape(arg),
+                ),
+            )
+            .parse_mode(ParseMode::Html)
+            .await?;
+            return Ok(());
+        }
+        "revoke" => match arg.parse::<i32>() {
+            Ok(rowid) => {
+                let deleted = diesel::delete(schema::api_tokens::table)
+                    .filter(schema::api_tokens::rowid.eq(rowid))
+                    .filter(schema::api_tokens::user_id.eq(user_id))
+                    .execute(&mut *env.conn())?;
+                if deleted == 0 {
+                    "No such token.".to_string()
+                } else {
+                    "Token revoked.".to_string()
+                }
+            }
+            Err(_) => USAGE.to_string(),
+        },
+        _ => USAGE.to_string(),
+    };
+
+    bot.reply_message(&msg, text).parse_mode(ParseMode::Html).await?;
+    Ok(())
+}
+
+fn list_tokens(env: &BotEnv, user_id: DbUserId) -> Result<String> {
+    let tokens: Vec<models::ApiToken> = schema::api_tokens::table
+        .filter(schema::api_tokens::user_id.eq(user_id))
+        .order(schema::api_tokens::rowid.asc())
+        .select(models::ApiToken::as_select())
+        .load(&mut *env.conn())?;
+    if tokens.is_empty() {
+        return Ok(format!("You have no API tokens.\n\n{USAGE}"));
+    }
+    let mut text = "Your API tokens:\n".to_string();
+    for token in tokens {
+        format_to!(
+            text,
+            "{}. <b>{}</b>, created {}, last used {}\n",
+            token.rowid,
+            escape(&token.name),
+            token.created_at.format("%Y-%m-%d"),
+            token.last_used_at.map_or_else(
+                || "never".to_string(),
+                |t| t.format("%Y-%m-%d").to_string()
+            ),
+        );
+    }
+    text.push('\n');
+    text.push_str(USAGE);
+    Ok(text)
+}
+
+fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Find the owner of the token, and record its usage. Residency of the owner
+/// is not checked.
+pub fn resolve_token(
+    conn: &mut DbConnection,
+    token: &str,
+) -> QueryResult<Option<UserId>> {
+    let token: Option<models::ApiToken> = schema::api_tokens::table
+        .filter(schema::api_tokens::token_hash.eq(hash_token(token)))
+        .select(models::ApiToken::as_select())
+        .first(conn)
+        .optional()?;
+    let Some(token) = token else { return Ok(None) };
+    diesel::update(schema::api_tokens::table)
+        .filter(schema::api_tokens::rowid.eq(token.rowid))
+        .set(
+            schema::api_tokens::last_used_at.eq(chrono::Utc::now().naive_utc()),
+        )
+        .execute(conn)?;
+    Ok(Some(token.user_id.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{message, message_update, TestBot};
+
+    #[tokio::test]
+    async fn test_api_token() {
+        let t = TestBot::new().await;
+        t.add_resident(100);
+
+        t.dispatch(message_update(message(100, None, 100, "/api_token new x")))
+            .await
+            .unwrap();
+        let calls = t.api.take_calls();
+        let text = calls[0].body["text"].as_str().unwrap();
+        let token = text.split("<code>").nth(1).unwrap();
+        let token = token.split("</code>").next().unwrap();
+        assert_eq!(
+            resolve_token(&mut t.env.conn(), token).unwrap(),
+            Some(UserId(100))
+        );
+        assert_eq!(resolve_token(&mut t.env.conn(), "x").unwrap(), None);
+
+        // Other users can't revoke the token
+        t.add_resident(200);
+        t.dispatch(message_update(message(
+            200,
+            None,
+            200,
+            "/api_token revoke 1",
+        )))
+        .await
+        .unwrap();
+        assert_eq!(t.api.take_calls()[0].body["text"], "No such token.");
+
+        t.dispatch(message_update(message(
+            100,
+            None,
+            100,
+            "/api_token revoke 1",
+        )))
+        .await
+        .unwrap();
+        assert_eq!(t.api.take_calls()[0].body["text"], "Token revoked.");
+        assert_eq!(resolve_token(&mut t.env.conn(), token).unwrap(), None);
+
+        t.stop().await;
+    }
+}
@@ -8,15 +8,29 @@ use teloxide::Bot;
 use tokio::sync::RwLock;
 
 use super::mac_monitoring::State;
+use super::BotModule;
 use crate::common::{BotEnv, UpdateHandler};
 use crate::db::DbUserId;
 use crate::schema;
 use crate::utils::ResultExt;
 
-pub fn message_handler() -> UpdateHandler {
+pub struct Module;
+
+impl BotModule for Module {
+    fn name(&self) -> &'static str {
+        "ask_to_visit"
+    }
+
+    fn message_handler(&self) -> Option<UpdateHandler> {
+        Some(message_handler())
+    }
+}
+
+fn message_handler() -> UpdateHandler {
     dptree::entry().branch(
         dptree::filter(|env: Arc<BotEnv>, msg: Message| {
-            env.config.telegram.chats.ask_to_visit.has_message(&msg)
+            (env.config().telegram.chats.ask_to_visit)
+                .is_some_and(|chat| chat.has_message(&msg))
         })
         .endpoint(handle_message),
     )
@@ -0,0 +1,397 @@
+//! Scheduled backups of the SQLite database, and restoring from them with the
+//! `restore` subcommand.
+
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use anyhow::{Context as _, Result};
+use chrono::Utc;
+use cron::Schedule;
+use diesel::sql_types::Text;
+use diesel::{Connection as _, RunQueryDsl as _, SqliteConnection};
+use futures::future::BoxFuture;
+use itertools::Itertools as _;
+use teloxide::payloads::SendDocumentSetters;
+use teloxide::requests::Requester;
+use teloxide::types::InputFile;
+use teloxide::Bot;
+use tokio::time::sleep;
+use tokio_util::sync::CancellationToken;
+
+use super::BotModule;
+use crate::common::BotEnv;
+use crate::config::{Backup, Paths};
+use crate::db::{migrate_with, Pool, SQLITE_MIGRATIONS};
+use crate::i18n::{chat_lang, tr};
+use crate::utils::ResultExt;
+
+/// Bot API limit for uploaded files. Larger backups are only kept locally.
+const MAX_UPLOAD_SIZE: u64 = 50 * 1024 * 1024;
+
+pub struct Module;
+
+impl BotModule for Module {
+    fn name(&self) -> &'static str {
+        "backup"
+    }
+
+    fn task(
+        &self,
+        bot: Bot,
+        env: Arc<BotEnv>,
+        cancel: CancellationToken,
+    ) -> Option<BoxFuture<'static, ()>> {
+        // Snapshots rely on SQLite, `check-config` warns about this.
+        if cfg!(feature = "postgres") {
+            return None;
+        }
+        Some(Box::pin(backup_loop(bot, env, cancel)))
+    }
+}
+
+async fn backup_loop(bot: Bot, env: Arc<BotEnv>, cancel: CancellationToken) {
+    loop {
+        let reloaded = env.config_reloaded.notified();
+        tokio::pin!(reloaded);
+        reloaded.as_mut().enable();
+
+        let config = env.config();
+        let scheduled = config.backup.as_ref().and_then(|backup| {
+            let next_run = Schedule::from_str(&backup.schedule)
+                .log_ok(module_path!(), "Failed to parse backup schedule")?
+                .upcoming(Utc)
+                .next()?;
+            Some((backup, next_run))
+        });
+        let Some((backup, next_run)) = scheduled else {
+            tokio::select! {
+                () = cancel.cancelled() => return,
+                () = reloaded => continue,
+            }
+        };
+        log::debug!("Next backup at {next_run}");
+
+        let wait = (next_run - Utc::now()).to_std().unwrap_or_default();
+        tokio::select! {
+            () = cancel.cancelled() => return,
+            () = sleep(wait) => (),
+            () = reloaded => continue,
+        }
+
+        run_backup(&bot, &env, backup, &config.paths)
+            .await
+            .log_error(module_path!(), "Backup failed");
+    }
+}
+
+/// Create a backup, delete old ones, and send the new one to the chat.
+async fn run_backup(
+    bot: &Bot,
+    env: &BotEnv,
+    backup: &Backup,
+    paths: &Paths,
+) -> Result<()> {
+    let db = env.db.clone();
+    let db_file = paths.db_file();
+    let dir = paths.data_dir.join(&backup.dir);
+    let keep = backup.keep;
+    let path = tokio::task::spawn_blocking(move || {
+        let path = create(&db, &db_file, &dir)?;
+        prune(&db_file, &dir, keep)?;
+        anyhow::Ok(path)
+    })
+    .await??;
+    log::info!("Created backup {}", path.display());
+
+    let Some(chat) = backup.chat else { return Ok(()) };
+    let size = std::fs::metadata(&path)?.len();
+    if size > MAX_UPLOAD_SIZE {
+        log::warn!("Backup is too large to send to Telegram: {size} bytes");
+        return Ok(());
+    }
+    let name = file_name(&path);
+    bot.send_document(chat, InputFile::file(&path))
+        .caption(tr!(chat_lang(env, chat).await, "backup-caption", name = name))
+        .await?;
+    Ok(())
+}
+
+/// Take a consistent snapshot of the database and compress it into `dir`.
+/// Returns the path of the backup.
+fn create(db: &Pool, db_file: &Path, dir: &Path) -> Result<PathBuf> {
+    std::fs::create_dir_all(dir).with_context(|| {
+        format!("Failed to create backup directory {}", dir.display())
+    })?;
+    let snapshot = dir.join(format!(
+        "{}.{}.{}",
+        file_stem(db_file),
+        Utc::now().format("%Y%m%dT%H%M%S%3f"),
+        file_extension(db_file),
+    ));
+    let target = with_suffix(&snapshot, ".zst");
+
+    // Unlike copying the file, this sees the state of a single transaction,
+    // and doesn't block writers in WAL mode.
+    diesel::sql_query("VACUUM INTO ?")
+        .bind::<Text, _>(snapshot.to_str().context("Non-UTF-8 backup path")?)
+        .execute(&mut *db.get())
+        .context("Failed to take a snapshot of the database")?;
+    let result = compress(&snapshot, &target);
+    std::fs::remove_file(&snapshot)?;
+    result?;
+    Ok(target)
+}
+
+fn compress(source: &Path, target: &Path) -> Result<()> {
+    let result = File::open(source).and_then(|mut input| {
+        let mut encoder = zstd::stream::Encoder::new(File::create(target)?, 0)?;
+        std::io::copy(&mut input, &mut encoder)?;
+        encoder.finish()?.sync_all()
+    });
+    if result.is_err() {
+        std::fs::remove_file(target).ok();
+    }
+    result.with_context(|| format!("Failed to compress {}", source.display()))
+}
+
+/// Delete all but the `keep` newest backups of `db_file` in `dir`. The newest
+/// one is always kept, since it's the one just created.
+fn prune(db_file: &Path, dir: &Path, keep: usize) -> Result<()> {
+    let backups = list(db_file, dir)?;
+    for old in &backups[..backups.len().saturating_sub(keep.max(1))] {
+        log::info!("Removing old backup {}", old.display());
+        std::fs::remove_file(old)?;
+    }
+    Ok(())
+}
+
+/// Backups of `db_file` in `dir`, oldest first.
+fn list(db_file: &Path, dir: &Path) -> Result<Vec<PathBuf>> {
+    let prefix = format!("{}.", file_stem(db_file));
+    let suffix = format!(".{}.zst", file_extension(db_file));
+
+    let mut backups = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let Some(name) = name.to_str() else { continue };
+        let is_backup = name
+            .strip_prefix(&prefix)
+            .and_then(|s| s.strip_suffix(&suffix))
+            .is_some_and(|ts| {
+                !ts.is_empty() && ts.chars().all(|c| c.is_ascii_alphanumeric())
+            });
+        if is_backup {
+            backups.push(entry.path());
+        }
+    }
+    // Timestamps are fixed-width, so lexicographic order is chronological.
+    backups.sort();
+    Ok(backups)
+}
+
+/// Replace `db_file` with a backup, after checking that the backup is an
+/// intact database of this or an older version of the bot. The replaced
+/// database is kept next to it. The bot should be stopped.
+pub fn restore(backup: &Path, db_file: &Path) -> Result<()> {
+    let restored = with_suffix(db_file, ".restoring");
+    decompress(backup, &restored)?;
+    if let Err(e) = validate(&restored) {
+        std::fs::remove_file(&restored).ok();
+        return Err(
+            e.context(format!("{} is not a valid backup", backup.display()))
+        );
+    }
+
+    if db_file.exists() {
+        let replaced = with_suffix(
+            db_file,
+            &format!(".replaced-{}", Utc::now().format("%Y%m%dT%H%M%S")),
+        );
+        // Move the WAL along, otherwise SQLite would apply it to the
+        // restored database.
+        for suffix in ["", "-wal", "-shm"] {
+            let from = with_suffix(db_file, suffix);
+            if from.exists() {
+                let to = with_suffix(&replaced, suffix);
+                std::fs::rename(&from, &to).with_context(|| {
+                    format!("Failed to move {}", from.display())
+                })?;
+            }
+        }
+        log::info!("Moved the current database to {}", replaced.display());
+    }
+    std::fs::rename(&restored, db_file)?;
+    log::info!("Restored {} from {}", db_file.display(), backup.display());
+    Ok(())
+}
+
+fn decompress(backup: &Path, target: &Path) -> Result<()> {
+    let compressed = backup.extension().is_some_and(|e| e == "zst");
+    let result = File::open(backup).and_then(|mut input| {
+        let mut output = File::create(target)?;
+        if compressed {
+            zstd::stream::copy_decode(input, &mut output)?;
+        } else {
+            std::io::copy(&mut input, &mut output)?;
+        }
+        output.sync_all()
+    });
+    if result.is_err() {
+        std::fs::remove_file(target).ok();
+    }
+    result.with_context(|| format!("Failed to read {}", backup.display()))
+}
+
+/// Check that `path` is an intact database of the bot, and that it was not
+/// migrated by a newer version.
+fn validate(path: &Path) -> Result<()> {
+    #[derive(diesel::QueryableByName)]
+    struct Row {
+        #[diesel(sql_type = Text)]
+        integrity_check: String,
+    }
+
+    let mut conn = SqliteConnection::establish(
+        path.to_str().context("Non-UTF-8 database path")?,
+    )?;
+    let rows = diesel::sql_query("PRAGMA integrity_check")
+        .load::<Row>(&mut conn)
+        .context("Failed to check integrity")?;
+    if rows.len() != 1 || rows[0].integrity_check != "ok" {
+        anyhow::bail!(
+            "Integrity check failed: {}",
+            rows.iter().map(|r| &r.integrity_check).join("; "),
+        );
+    }
+    diesel::sql_query("SELECT 1 FROM __diesel_schema_migrations")
+        .execute(&mut conn)
+        .context("Not a database of the bot")?;
+    migrate_with(&mut conn, SQLITE_MIGRATIONS, true)?;
+    Ok(())
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().unwrap_or_default().to_string_lossy().into_owned()
+}
+
+fn file_stem(path: &Path) -> String {
+    path.file_stem().unwrap_or_default().to_string_lossy().into_owned()
+}
+
+fn file_extension(path: &Path) -> String {
+    path.extension().unwrap_or_default().to_string_lossy().into_owned()
+}
+
+// SQLite only, like the module itself.
+#[cfg(all(test, not(feature = "postgres")))]
+mod tests {
+    use teloxide::types::ChatId;
+
+    use super::*;
+    use crate::models::welcomed_users;
+    use crate::testing::TestBot;
+
+    #[tokio::test]
+    async fn test_backup_and_restore() {
+        let dir = std::env::temp_dir()
+            .join(format!("botka-test-backup-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let paths = Paths {
+            data_dir: dir.clone(),
+            db: "db.sqlite3".into(),
+            trace: "trace.jsonl".into(),
+        };
+        let backup = Backup {
+            schedule: "0 0 3 * * * *".to_string(),
+            dir: "backups".into(),
+            keep: 2,
+            chat: Some(ChatId(-1_009_876_543_210)),
+        };
+
+        let t = TestBot::new().await;
+        welcomed_users
+            .set(&mut t.env.conn(), &[teloxide::types::UserId(1)].into())
+            .unwrap();
+        for _ in 0..3 {
+            run_backup(&t.bot, &t.env, &backup, &paths).await.unwrap();
+        }
+        let calls = t.api.take_calls();
+        assert_eq!(calls.len(), 3);
+        assert!(calls.iter().all(|c| c.method == "sendDocument"));
+        t.stop().await;
+
+        let backups = list(&paths.db_file(), &dir.join("backups")).unwrap();
+        assert_eq!(backups.len(), 2);
+
+        // Restore over an existing database, which is kept.
+        std::fs::write(paths.db_file(), b"old").unwrap();
+        restore(&backups[1], &paths.db_file()).unwrap();
+        let mut conn =
+            SqliteConnection::establish(paths.db_file().to_str().unwrap())
+                .unwrap();
+        let users = welcomed_users.get(&mut conn).unwrap().unwrap();
+        assert_eq!(users.len(), 1);
+        drop(conn);
+        let replaced = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| file_name(&e.unwrap().path()))
+            .filter(|name| name.starts_with("db.sqlite3.replaced-"))
+            .collect_vec();
+        assert_eq!(replaced.len(), 1);
+
+        // Invalid backups are rejected and the database is left intact.
+        let garbage = dir.join("garbage.sqlite3");
+        std::fs::write(&garbage, b"not a database").unwrap();
+        assert!(restore(&garbage, &paths.db_file()).is_err());
+        assert!(!with_suffix(&paths.db_file(), ".restoring").exists());
+        let empty = dir.join("empty.sqlite3");
+        std::fs::write(&empty, b"").unwrap();
+        let error = restore(&empty, &paths.db_file()).unwrap_err();
+        assert_eq!(
+            error.root_cause().to_string(),
+            "no such table: __diesel_schema_migrations",
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_backup_keep_zero() {
+        let dir = std::env::temp_dir()
+            .join(format!("botka-test-backup-keep-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let paths = Paths {
+            data_dir: dir.clone(),
+            db: "db.sqlite3".into(),
+            trace: "trace.jsonl".into(),
+        };
+        let backup = Backup {
+            schedule: "0 0 3 * * * *".to_string(),
+            dir: "backups".into(),
+            keep: 0,
+            chat: Some(ChatId(-1_009_876_543_210)),
+        };
+
+        let t = TestBot::new().await;
+        for _ in 0..2 {
+            run_backup(&t.bot, &t.env, &backup, &paths).await.unwrap();
+        }
+        let calls = t.api.take_calls();
+        assert_eq!(calls.len(), 2);
+        t.stop().await;
+
+        let backups = list(&paths.db_file(), &dir.join("backups")).unwrap();
+        assert_eq!(backups.len(), 1);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
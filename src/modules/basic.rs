@@ -18,11 +18,13 @@ use teloxide::utils::html;
 use tokio::sync::RwLock;
 
 use super::mac_monitoring::State;
+use super::{BotModule, ModuleCommands, Registry};
 use crate::common::{
-    filter_command, format_users, BotCommandsExt, BotCommandsExtTrait, BotEnv,
-    TopicEmojis, UpdateHandler,
+    filter_command, format_users, BotCommandsExt, BotEnv, TopicEmojis,
+    UpdateHandler,
 };
 use crate::db::{DbChatId, DbUserId};
+use crate::i18n::{msg_lang, tr, Lang};
 use crate::utils::{write_message_link, BotExt};
 use crate::{models, schema};
 
@@ -52,9 +54,29 @@ pub enum Commands {
 
     #[command(description = "show bot version.")]
     Version,
+
+    #[command(description = "reload config file.")]
+    #[custom(admin = true)]
+    ReloadConfig,
+}
+
+pub struct Module;
+
+impl BotModule for Module {
+    fn name(&self) -> &'static str {
+        "basic"
+    }
+
+    fn message_handler(&self) -> Option<UpdateHandler> {
+        Some(command_handler())
+    }
+
+    fn commands(&self) -> Option<ModuleCommands> {
+        Some(ModuleCommands::of::<Commands>())
+    }
 }
 
-pub fn command_handler() -> UpdateHandler {
+fn command_handler() -> UpdateHandler {
     filter_command::<Commands>().endpoint(start)
 }
 
@@ -63,16 +85,17 @@ async fn start<'a>(
     env: Arc<BotEnv>,
     msg: Message,
     mac_monitoring_state: Arc<RwLock<State>>,
+    registry: Arc<Registry>,
     command: Commands,
 ) -> Result<()> {
     match command {
-        Commands::Help => cmd_help(bot, msg).await?,
+        Commands::Help => cmd_help(bot, env, msg, registry).await?,
         Commands::Residents => cmd_list_residents(bot, env, msg).await?,
         Commands::ResidentsAdminTable => {
             cmd_residents_admin_table(bot, env, msg).await?;
         }
         Commands::ResidentsTimeline => {
-            cmd_show_residents_timeline(bot, msg).await?;
+            cmd_show_residents_timeline(bot, env, msg).await?;
         }
         Commands::Status => {
             cmd_status(bot, env, msg, mac_monitoring_state).await?;
@@ -81,58 +104,64 @@ async fn start<'a>(
             bot.reply_message(&msg, crate::version()).await?;
         }
         Commands::Topics => cmd_topics(bot, env, msg).await?,
+        Commands::ReloadConfig => cmd_reload_config(bot, env, msg).await?,
     }
     Ok(())
 }
 
-async fn cmd_help(bot: Bot, msg: Message) -> Result<()> {
-    let mut text = String::new();
-    text.push_str("Available commands:\n\n");
-    text.push_str(&commands_help::<crate::modules::basic::Commands>());
-    text.push_str(&commands_help::<crate::modules::needs::Commands>());
-    text.push_str(&commands_help::<crate::modules::userctl::Commands>());
-    text.push_str(&commands_help::<crate::modules::camera::Commands>());
-    text.push_str(&commands_help::<crate::modules::ldap::Commands>());
-    text.push_str("\nCommands marked with * are available only to residents.");
-    // "..., and with ** are available only to bot technicians."
+async fn cmd_reload_config(
+    bot: Bot,
+    env: Arc<BotEnv>,
+    msg: Message,
+) -> Result<()> {
+    let lang = msg_lang(&env, &msg).await;
+    let text = match env.reload_config() {
+        Ok(changes) if changes.is_empty() => {
+            tr!(lang, "reload-config-unchanged")
+        }
+        Ok(changes) => {
+            let mut text = tr!(lang, "reload-config-changes");
+            text.push('\n');
+            for change in &changes {
+                log::info!("Config changed: {change}");
+                write!(
+                    text,
+                    "• <code>{}</code>",
+                    html::escape(&change.to_string()),
+                )?;
+                if change.requires_restart() {
+                    write!(
+                        text,
+                        " ({})",
+                        tr!(lang, "reload-config-restart-required")
+                    )?;
+                }
+                text.push('\n');
+            }
+            text
+        }
+        Err(e) => format!(
+            "{}\n<pre>{}</pre>",
+            tr!(lang, "reload-config-failed"),
+            html::escape(&format!("{e:#}")),
+        ),
+    };
     bot.reply_message(&msg, text)
         .parse_mode(teloxide::types::ParseMode::Html)
         .await?;
     Ok(())
 }
 
-fn commands_help<T: BotCommands + BotCommandsExtTrait>() -> String {
-    let descriptions = T::descriptions().to_string();
-    let global_description =
-        descriptions.find("\n\n/").map(|i| &descriptions[..i]);
-
-    let mut result = String::new();
-    if let Some(global_description) = global_description {
-        result.push_str(global_description);
-        result.push('\n');
-    }
-    for (cmd, rules) in std::iter::zip(&T::bot_commands(), T::COMMAND_RULES) {
-        result.push_str(&cmd.command);
-        result.push_str(match (rules.admin, rules.resident) {
-            (true, _) => "**",
-            (false, true) => "*",
-            (false, false) => "",
-        });
-        result.push_str(
-            match (rules.in_private, rules.in_group, rules.in_resident_chat) {
-                (true, true, _) => "",
-                (true, false, _) => " (in private)",
-                (false, true, false) => " (not in private)",
-                (_, _, true) => " (in resident chat)",
-                (false, false, _) => " (disabled?)",
-            },
-        );
-        result.push_str(" — ");
-        result.push_str(&cmd.description);
-        result.push('\n');
-    }
-
-    result
+async fn cmd_help(
+    bot: Bot,
+    env: Arc<BotEnv>,
+    msg: Message,
+    registry: Arc<Registry>,
+) -> Result<()> {
+    bot.reply_message(&msg, registry.help(msg_lang(&env, &msg).await))
+        .parse_mode(teloxide::types::ParseMode::Html)
+        .await?;
+    Ok(())
 }
 
 async fn cmd_list_residents<'a>(
@@ -140,24 +169,26 @@ async fn cmd_list_residents<'a>(
     env: Arc<BotEnv>,
     msg: Message,
 ) -> Result<()> {
-    let residents: Vec<(DbUserId, Option<models::TgUser>)> =
-        schema::residents::table
-            .filter(schema::residents::end_date.is_null())
-            .left_join(
-                schema::tg_users::table
-                    .on(schema::residents::tg_id.eq(schema::tg_users::id)),
-            )
-            .select((
-                schema::residents::tg_id,
-                schema::tg_users::all_columns.nullable(),
-            ))
-            .order(schema::residents::begin_date.desc())
-            .load(&mut *env.conn())?;
-    let mut text = String::new();
-
-    text.push_str("Residents: ");
-    format_users(&mut text, residents.iter().map(|(r, u)| (*r, u)));
-    text.push('.');
+    let residents: Vec<(DbUserId, Option<models::TgUser>)> = env
+        .db
+        .run(|conn| {
+            schema::residents::table
+                .filter(schema::residents::end_date.is_null())
+                .left_join(
+                    schema::tg_users::table
+                        .on(schema::residents::tg_id.eq(schema::tg_users::id)),
+                )
+                .select((
+                    schema::residents::tg_id,
+                    schema::tg_users::all_columns.nullable(),
+                ))
+                .order(schema::residents::begin_date.desc())
+                .load(conn)
+        })
+        .await?;
+    let mut users = String::new();
+    format_users(&mut users, residents.iter().map(|(r, u)| (*r, u)));
+    let text = tr!(msg_lang(&env, &msg).await, "residents-list", users = users);
     bot.reply_message(&msg, text)
         .parse_mode(teloxide::types::ParseMode::Html)
         .disable_web_page_preview(true)
@@ -181,9 +212,14 @@ async fn cmd_residents_admin_table(
     bot.send_chat_action(msg.chat.id, teloxide::types::ChatAction::Typing)
         .await?;
 
-    let table = Command::new(script_path).arg(&env.config_path).output()?;
+    let table = Command::new(script_path)
+        .arg(&env.config_path)
+        .arg(env.config().paths.db_file())
+        .output()?;
     if !table.status.success() {
-        bot.reply_message(&msg, "Failed to generate table.").await?;
+        let text =
+            tr!(msg_lang(&env, &msg).await, "residents-admin-table-failed");
+        bot.reply_message(&msg, text).await?;
         log::error!(
             "Failed to generate table: {}",
             String::from_utf8_lossy(&table.stderr)
@@ -197,13 +233,19 @@ async fn cmd_residents_admin_table(
     Ok(())
 }
 
-async fn cmd_show_residents_timeline(bot: Bot, msg: Message) -> Result<()> {
+async fn cmd_show_residents_timeline(
+    bot: Bot,
+    env: Arc<BotEnv>,
+    msg: Message,
+) -> Result<()> {
+    let lang = msg_lang(&env, &msg).await;
     let svg = Command::new("f0-residents-timeline")
         .arg("-sqlite")
-        .arg(crate::DB_FILENAME)
+        .arg(env.config().paths.db_file())
         .output()?;
     if !svg.status.success() || !svg.stdout.starts_with(b"<svg") {
-        bot.reply_message(&msg, "Failed to generate timeline (svg).").await?;
+        let text = tr!(lang, "residents-timeline-failed", format = "svg");
+        bot.reply_message(&msg, text).await?;
         return Ok(());
     }
     let mut png = Command::new("convert")
@@ -215,7 +257,8 @@ async fn cmd_show_residents_timeline(bot: Bot, msg: Message) -> Result<()> {
     png.stdin.take().unwrap().write_all(&svg.stdout)?;
     let png = png.wait_with_output()?;
     if !png.status.success() || !png.stdout.starts_with(b"\x89PNG") {
-        bot.reply_message(&msg, "Failed to generate timeline (png).").await?;
+        let text = tr!(lang, "residents-timeline-failed", format = "png");
+        bot.reply_message(&msg, text).await?;
         return Ok(());
     }
     bot.reply_photo(&msg, InputFile::memory(png.stdout)).await?;
@@ -228,25 +271,27 @@ async fn cmd_status(
     msg: Message,
     state: Arc<RwLock<State>>,
 ) -> Result<()> {
+    let lang = msg_lang(&env, &msg).await;
     let mut text = String::new();
 
-    if let Some(active_users) = (*state.read().await).active_users() {
-        let data: Vec<models::TgUser> = schema::tg_users::table
-            .filter(
-                schema::tg_users::id
-                    .eq_any(active_users.iter().map(|id| DbUserId::from(*id))),
-            )
-            .select(schema::tg_users::all_columns)
-            .load(&mut *env.conn())?;
+    let active_users = (*state.read().await)
+        .active_users()
+        .map(|users| users.iter().map(|&id| DbUserId::from(id)).collect_vec());
+    if let Some(ids) = active_users {
+        let data: Vec<models::TgUser> = env
+            .db
+            .run(move |conn| {
+                schema::tg_users::table
+                    .filter(schema::tg_users::id.eq_any(ids))
+                    .select(schema::tg_users::all_columns)
+                    .load(conn)
+            })
+            .await?;
 
-        writeln!(&mut text, "Currently in space: ").unwrap();
+        writeln!(&mut text, "{}", tr!(lang, "status-in-space")).unwrap();
         format_users(&mut text, data.iter().map(|u| (u.id, u)));
     } else {
-        writeln!(
-            &mut text,
-            "No data collected yet. Probably Mikrotik password is incorrect."
-        )
-        .unwrap();
+        writeln!(&mut text, "{}", tr!(lang, "status-no-data")).unwrap();
     }
 
     bot.reply_message(&msg, text)
@@ -259,24 +304,32 @@ async fn cmd_status(
 
 async fn cmd_topics(bot: Bot, env: Arc<BotEnv>, msg: Message) -> Result<()> {
     let Some(user) = &msg.from else { return Ok(()) };
-
-    let user_chats = schema::tg_users_in_chats::table
-        .filter(schema::tg_users_in_chats::user_id.eq(DbUserId::from(user.id)))
-        .select(schema::tg_users_in_chats::chat_id)
-        .load::<DbChatId>(&mut *env.conn())?;
+    let lang = msg_lang(&env, &msg).await;
+
+    let user_id = DbUserId::from(user.id);
+    let (user_chats, topics) = env
+        .db
+        .run(move |conn| -> QueryResult<_> {
+            let user_chats = schema::tg_users_in_chats::table
+                .filter(schema::tg_users_in_chats::user_id.eq(user_id))
+                .select(schema::tg_users_in_chats::chat_id)
+                .load::<DbChatId>(conn)?;
+            let topics: Vec<models::TgChatTopic> =
+                schema::tg_chat_topics::table
+                    .filter(schema::tg_chat_topics::chat_id.eq_any(&user_chats))
+                    .select(schema::tg_chat_topics::all_columns)
+                    .load(conn)?;
+            Ok((user_chats, topics))
+        })
+        .await?;
 
     if user_chats.is_empty() {
-        bot.reply_message(&msg, "You are not in any tracked chats.").await?;
+        bot.reply_message(&msg, tr!(lang, "topics-no-chats")).await?;
         return Ok(());
     }
 
-    let topics: Vec<models::TgChatTopic> = schema::tg_chat_topics::table
-        .filter(schema::tg_chat_topics::chat_id.eq_any(user_chats))
-        .select(schema::tg_chat_topics::all_columns)
-        .load(&mut *env.conn())?;
-
     if topics.is_empty() {
-        bot.reply_message(&msg, "No topics in your chats.").await?;
+        bot.reply_message(&msg, tr!(lang, "topics-no-topics")).await?;
         return Ok(());
     }
 
@@ -289,9 +342,14 @@ async fn cmd_topics(bot: Bot, env: Arc<BotEnv>, msg: Message) -> Result<()> {
 
     let mut text = String::new();
     for (chat_id, topics) in chats {
-        let chat: models::TgChat = schema::tg_chats::table
-            .filter(schema::tg_chats::id.eq(chat_id))
-            .first(&mut *env.conn())?;
+        let chat: models::TgChat = env
+            .db
+            .run(move |conn| {
+                schema::tg_chats::table
+                    .filter(schema::tg_chats::id.eq(chat_id))
+                    .first(conn)
+            })
+            .await?;
         writeln!(
             &mut text,
             "<b>{}</b>",
@@ -300,7 +358,7 @@ async fn cmd_topics(bot: Bot, env: Arc<BotEnv>, msg: Message) -> Result<()> {
         .unwrap();
 
         for topic in topics {
-            render_topic_link(&mut text, &topic_emojis, topic);
+            render_topic_link(&mut text, &topic_emojis, topic, lang);
         }
         text.push('\n');
     }
@@ -320,6 +378,7 @@ fn render_topic_link(
     out: &mut String,
     emojis: &TopicEmojis,
     topic: &models::TgChatTopic,
+    lang: Lang,
 ) {
     write_message_link(out, topic.chat_id, ThreadId::from(topic.topic_id).0);
     out.push_str(emojis.get(topic));
@@ -327,7 +386,8 @@ fn render_topic_link(
     if let Some(name) = &topic.name {
         out.push_str(&html::escape(name));
     } else {
-        write!(out, "Topic #{}", ThreadId::from(topic.topic_id)).unwrap();
+        let id = ThreadId::from(topic.topic_id).0 .0;
+        out.push_str(&tr!(lang, "topics-unnamed", id = id));
     }
     out.push_str("</a>\n");
 }
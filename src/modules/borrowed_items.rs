@@ -22,20 +22,47 @@ use teloxide::types::{
 };
 use teloxide::utils::html;
 
+use super::BotModule;
 use crate::common::{BotEnv, UpdateHandler};
+use crate::i18n::{callback_lang, msg_lang, tr, Lang};
 use crate::utils::Sqlizer;
 use crate::{models, schema};
 
-pub fn command_handler() -> UpdateHandler {
+pub struct Module;
+
+impl BotModule for Module {
+    fn name(&self) -> &'static str {
+        "borrowed_items"
+    }
+
+    fn message_handler(&self) -> Option<UpdateHandler> {
+        Some(message_handler())
+    }
+
+    fn callback_handler(&self) -> Option<UpdateHandler> {
+        Some(callback_handler())
+    }
+
+    fn register_metrics(&self) {
+        register_metrics();
+    }
+}
+
+fn message_handler() -> UpdateHandler {
     dptree::filter(filter_messages_in_topic).endpoint(handle_message)
 }
 
-pub fn callback_handler() -> UpdateHandler {
+fn callback_handler() -> UpdateHandler {
     dptree::filter_map(filter_callbacks).endpoint(handle_callback)
 }
 
 fn filter_messages_in_topic(env: Arc<BotEnv>, msg: Message) -> bool {
-    env.config.telegram.chats.borrowed_items.iter().any(|c| c.has_message(&msg))
+    env.config()
+        .telegram
+        .chats
+        .borrowed_items
+        .iter()
+        .any(|c| c.has_message(&msg))
 }
 
 async fn handle_message(
@@ -60,8 +87,9 @@ async fn handle_message(
         .map(|i| models::BorrowedItem { name: i, returned: None })
         .collect_vec();
 
+    let lang = msg_lang(&env, &msg).await;
     let bot_message = bot
-        .send_message(msg.chat.id, make_text(user, &items))
+        .send_message(msg.chat.id, make_text(user, &items, lang))
         .message_thread_id(msg.thread_id.unwrap())
         .parse_mode(ParseMode::Html)
         .reply_markup(ReplyMarkup::InlineKeyboard(make_keyboard(
@@ -128,6 +156,7 @@ async fn handle_callback(
     cd: CallbackData,
     callback: CallbackQuery,
 ) -> Result<()> {
+    let lang = callback_lang(&env, &callback).await;
     let resp = env.transaction(|conn| {
         let mut bi: models::BorrowedItems = schema::borrowed_items::table
             .filter(schema::borrowed_items::chat_id.eq(cd.chat_id.0))
@@ -168,13 +197,13 @@ async fn handle_callback(
     match resp {
         Ok(CallbackResponse::NotYourMessage) => {
             bot.answer_callback_query(callback.id)
-                .text("This is not your message.")
+                .text(tr!(lang, "borrowed-not-your-message"))
                 .await?;
             Ok(())
         }
         Ok(CallbackResponse::AlreadyReturned) => {
             bot.answer_callback_query(callback.id)
-                .text("This item is already returned.")
+                .text(tr!(lang, "borrowed-already-returned"))
                 .await?;
             Ok(())
         }
@@ -185,7 +214,7 @@ async fn handle_callback(
                 .edit_message_text(
                     cd.chat_id,
                     bi.bot_message_id.into(),
-                    make_text(&callback.from, &bi.items),
+                    make_text(&callback.from, &bi.items, lang),
                 )
                 .parse_mode(ParseMode::Html);
             if !all_returned {
@@ -205,7 +234,7 @@ async fn handle_callback(
         }
         Err(e) => {
             bot.answer_callback_query(callback.id)
-                .text("Internal error")
+                .text(tr!(lang, "borrowed-internal-error"))
                 .await?;
             Err(e.into())
         }
@@ -223,10 +252,11 @@ async fn classify(
     env: Arc<BotEnv>,
     text: &str,
 ) -> Result<ClassificationResult> {
-    if env.config.services.openai.disable {
-        classify_dumb(text)
-    } else {
+    let openai = env.config().services.openai.as_ref().map(|o| !o.disable);
+    if openai == Some(true) {
         classify_openai(env, text).await
+    } else {
+        classify_dumb(text)
     }
 }
 
@@ -245,7 +275,7 @@ fn classify_dumb(text: &str) -> Result<ClassificationResult> {
 const MODEL: &str = "gpt-4";
 const METRIC_NAME: &str = "botka_openai_used_tokens_total";
 
-pub fn register_metrics() {
+fn register_metrics() {
     metrics::register_counter!(
         METRIC_NAME,
         "model" => MODEL,
@@ -346,34 +376,42 @@ fn textify_message(msg: &Message) -> Option<String> {
     Some(result)
 }
 
-fn make_text(user: &User, items: &[models::BorrowedItem]) -> String {
-    let mut text = String::new();
-    let mut prev_date: Option<DateTime<_>> = None;
+fn make_text(
+    user: &User,
+    items: &[models::BorrowedItem],
+    lang: Lang,
+) -> String {
+    // Items returned within 10 minutes of the first one in a group
+    let mut groups: Vec<(DateTime<_>, Vec<String>)> = Vec::new();
     for (name, returned) in items
         .iter()
         .filter_map(|i| Some((i.name.as_str(), i.returned?)))
         .sorted_by_key(|(_, r)| *r)
     {
-        match prev_date {
-            Some(p) if returned - p < chrono::Duration::minutes(10) => {
-                text.push_str(", ");
-            }
-            _ => {
-                if !text.is_empty() {
-                    text.push('\n');
-                }
-                text.push_str(&returned.format("%Y-%m-%d %H:%M").to_string());
-                text.push_str(": returned ");
-                prev_date = Some(returned);
+        match groups.last_mut() {
+            Some((first, names))
+                if returned - *first < chrono::Duration::minutes(10) =>
+            {
+                names.push(html::escape(name));
             }
+            _ => groups.push((returned, vec![html::escape(name)])),
         }
-        text.push_str(&html::escape(name));
     }
-    if text.is_empty() {
-        text.push_str(&html::user_mention(user.id, &user.full_name()));
-        text.push_str(", press a button to mark an item as returned.");
+    if groups.is_empty() {
+        let user = html::user_mention(user.id, &user.full_name());
+        return tr!(lang, "borrowed-press-button", user = user);
     }
-    text
+    groups
+        .into_iter()
+        .map(|(date, names)| {
+            tr!(
+                lang,
+                "borrowed-returned",
+                date = date.format("%Y-%m-%d %H:%M").to_string(),
+                items = names.join(", "),
+            )
+        })
+        .join("\n")
 }
 
 fn make_keyboard(
@@ -435,6 +473,10 @@ If a message does not contain any information about taking or returning items, r
 mod tests {
     use super::*;
     use crate::models::BorrowedItem;
+    use crate::testing::{
+        callback_query_update, message, message_update, TestBot,
+        BORROWED_ITEMS_THREAD, BOT_USER_ID, CHAT,
+    };
 
     #[test]
     fn test_make_text() {
@@ -456,17 +498,64 @@ mod tests {
         assert_eq!(
             make_text(
                 &user,
-                &[item("hammer", Some(0)), item("screwdriver", Some(1))]
+                &[item("hammer", Some(0)), item("screwdriver", Some(1))],
+                Lang::En,
             ),
             "1970-01-01 00:00: returned hammer, screwdriver"
         );
         assert_eq!(
             make_text(
                 &user,
-                &[item("hammer", Some(0)), item("screwdriver", Some(60))]
+                &[item("hammer", Some(0)), item("screwdriver", Some(60))],
+                Lang::En,
             ),
             "1970-01-01 00:00: returned hammer\n\
             1970-01-01 01:00: returned screwdriver"
         );
     }
+
+    #[tokio::test]
+    async fn test_return_items() {
+        let t = TestBot::new().await;
+
+        let msg =
+            message(CHAT, Some(BORROWED_ITEMS_THREAD), 100, "took hammer saw");
+        let user_message_id = msg["message_id"].clone();
+        t.dispatch(message_update(msg)).await.unwrap();
+        assert_eq!(t.api.take_methods(), ["sendMessage", "pinChatMessage"]);
+
+        let bot_message =
+            message(CHAT, Some(BORROWED_ITEMS_THREAD), BOT_USER_ID, "");
+        let press = |user, idx| {
+            callback_query_update(
+                user,
+                bot_message.clone(),
+                &format!("b:{CHAT}:{user_message_id}:{idx}"),
+            )
+        };
+
+        t.dispatch(press(200, 0)).await.unwrap();
+        let calls = t.api.take_calls();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].body["text"], "This is not your message.");
+
+        t.dispatch(press(100, 0)).await.unwrap();
+        assert_eq!(
+            t.api.take_methods(),
+            ["answerCallbackQuery", "editMessageText"]
+        );
+
+        t.dispatch(press(100, 0)).await.unwrap();
+        let calls = t.api.take_calls();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].body["text"], "This item is already returned.");
+
+        t.dispatch(press(100, 1)).await.unwrap();
+        assert_eq!(
+            t.api.take_methods(),
+            ["answerCallbackQuery", "editMessageText", "unpinChatMessage"]
+        );
+
+        t.stop().await;
+    }
 }
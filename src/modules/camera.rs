@@ -7,6 +7,7 @@ use macro_rules_attribute::derive;
 use teloxide::prelude::*;
 use teloxide::utils::command::BotCommands;
 
+use super::{BotModule, ModuleCommands};
 use crate::common::{filter_command, BotCommandsExt, BotEnv, UpdateHandler};
 use crate::utils::{read_camera_image, BotExt};
 
@@ -21,7 +22,23 @@ pub enum Commands {
     Hlam,
 }
 
-pub fn command_handler() -> UpdateHandler {
+pub struct Module;
+
+impl BotModule for Module {
+    fn name(&self) -> &'static str {
+        "camera"
+    }
+
+    fn message_handler(&self) -> Option<UpdateHandler> {
+        Some(command_handler())
+    }
+
+    fn commands(&self) -> Option<ModuleCommands> {
+        Some(ModuleCommands::of::<Commands>())
+    }
+}
+
+fn command_handler() -> UpdateHandler {
     filter_command::<Commands>().endpoint(camera)
 }
 
@@ -31,9 +48,14 @@ async fn camera(
     msg: Message,
     command: Commands,
 ) -> Result<()> {
+    let config = env.config();
     let camera_config = match command {
-        Commands::Racovina => &env.config.services.racovina_cam,
-        Commands::Hlam => &env.config.services.vortex_of_doom_cam,
+        Commands::Racovina => &config.services.racovina_cam,
+        Commands::Hlam => &config.services.vortex_of_doom_cam,
+    };
+    let Some(camera_config) = camera_config else {
+        bot.reply_message(&msg, "This camera is not configured.").await?;
+        return Ok(());
     };
 
     let image = match read_camera_image(
@@ -0,0 +1,398 @@
+//! Command lists of the Telegram "/" menu, published with `setMyCommands`
+//! for each scope according to [`CommandAccessRules`] of the commands:
+//! - All private chats and all group chats get commands available to anyone.
+//! - Residential chats also get commands for residents and for resident chats.
+//! - Private chats of residents and bot admins also get commands for residents
+//!   and admins.
+//! - Bot admins and users with roles (see [`crate::common::has_role`]) also
+//!   get commands for their roles, both in private and residential chats.
+//!
+//! Lists are published on startup and after the config is reloaded. The
+//! lists of a user are refreshed when their residency or roles change. Lists
+//! that are unchanged since they were last published are skipped, so that
+//! restarts don't make a call per resident.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use diesel::prelude::*;
+use itertools::Itertools as _;
+use sha2::{Digest as _, Sha256};
+use teloxide::payloads::{DeleteMyCommandsSetters, SetMyCommandsSetters};
+use teloxide::requests::Requester;
+use teloxide::types::{BotCommand, BotCommandScope, ChatId, Recipient, UserId};
+use teloxide::Bot;
+use tokio_util::sync::CancellationToken;
+
+use super::Registry;
+use crate::common::{is_resident, BotEnv, CommandAccessRules};
+use crate::db::DbUserId;
+use crate::utils::ResultExt as _;
+use crate::{models, schema};
+
+/// Pause after each call, to stay well within the API rate limits.
+const CALL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Held while publishing, so that concurrent refreshes don't overwrite the
+/// digests saved by each other.
+static PUBLISHING: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// Who sees a command list, and where.
+#[derive(Clone)]
+#[allow(clippy::struct_excessive_bools)]
+struct Audience {
+    private: bool,
+    resident_chat: bool,
+    resident: bool,
+    admin: bool,
+    roles: Vec<String>,
+}
+
+const ANYONE: Audience = Audience {
+    private: false,
+    resident_chat: false,
+    resident: false,
+    admin: false,
+    roles: Vec::new(),
+};
+
+impl Audience {
+    fn shows(&self, rules: &CommandAccessRules) -> bool {
+        let place = if self.private {
+            rules.in_private && !rules.in_resident_chat
+        } else {
+            rules.in_group && (!rules.in_resident_chat || self.resident_chat)
+        };
+        place
+            && (!rules.admin || self.admin)
+            && (!rules.resident || self.resident)
+            && rules
+                .role
+                .iter()
+                .all(|role| self.admin || self.roles.iter().any(|r| r == role))
+    }
+
+    fn commands(&self, registry: &Registry) -> Vec<BotCommand> {
+        registry.commands_where(|r| self.shows(r))
+    }
+}
+
+/// Publish command lists, and refresh them after the config is reloaded.
+pub async fn task(
+    bot: Bot,
+    env: Arc<BotEnv>,
+    registry: Arc<Registry>,
+    cancel: CancellationToken,
+) {
+    loop {
+        let reloaded = env.config_reloaded.notified();
+        tokio::pin!(reloaded);
+        reloaded.as_mut().enable();
+
+        if !env.config().telegram.passive_mode {
+            publish(&bot, &env, &registry)
+                .await
+                .log_error(module_path!(), "Failed to publish command lists");
+        }
+
+        tokio::select! {
+            () = cancel.cancelled() => break,
+            () = reloaded => (),
+        }
+    }
+}
+
+/// Digests of the lists published to each scope, stored in
+/// [`models::command_menu_published`]. Keyed by the scope in JSON.
+struct Published(HashMap<String, String>);
+
+impl Published {
+    async fn load(env: &BotEnv) -> Self {
+        let digests = env
+            .db
+            .run(|conn| models::command_menu_published.get(conn))
+            .await
+            .log_ok(module_path!(), "Failed to load published command lists")
+            .flatten()
+            .unwrap_or_default();
+        Self(digests)
+    }
+
+    async fn save(self, env: &BotEnv) {
+        env.db
+            .run(move |conn| models::command_menu_published.set(conn, &self.0))
+            .await
+            .log_error(
+                module_path!(),
+                "Failed to save published command lists",
+            );
+    }
+
+    fn is_current(
+        &self,
+        scope: &BotCommandScope,
+        commands: Option<&Vec<BotCommand>>,
+    ) -> bool {
+        self.0.get(&scope_key(scope)) == Some(&digest(commands))
+    }
+
+    fn set(
+        &mut self,
+        scope: &BotCommandScope,
+        commands: Option<&Vec<BotCommand>>,
+    ) {
+        self.0.insert(scope_key(scope), digest(commands));
+    }
+}
+
+fn scope_key(scope: &BotCommandScope) -> String {
+    serde_json::to_string(scope).unwrap_or_default()
+}
+
+fn digest(commands: Option<&Vec<BotCommand>>) -> String {
+    let json = serde_json::to_vec(&commands).unwrap_or_default();
+    hex::encode(Sha256::digest(json))
+}
+
+/// Publish command lists of all scopes.
+pub async fn publish(
+    bot: &Bot,
+    env: &BotEnv,
+    registry: &Registry,
+) -> Result<()> {
+    let _publishing = PUBLISHING.lock().await;
+    let mut published = Published::load(env).await;
+    let result = publish_all(bot, env, registry, &mut published).await;
+    published.save(env).await;
+    result
+}
+
+async fn publish_all(
+    bot: &Bot,
+    env: &BotEnv,
+    registry: &Registry,
+    published: &mut Published,
+) -> Result<()> {
+    let config = env.config();
+    let residents = Audience { resident: true, ..ANYONE };
+    let mut scopes = vec![
+        (
+            BotCommandScope::AllPrivateChats,
+            Audience { private: true, ..ANYONE },
+        ),
+        (BotCommandScope::AllGroupChats, ANYONE),
+    ];
+    for &chat in &config.telegram.chats.residential {
+        scopes.push((
+            BotCommandScope::Chat { chat_id: Recipient::Id(chat) },
+            Audience { resident_chat: true, ..residents.clone() },
+        ));
+    }
+    for (scope, audience) in scopes {
+        let commands = audience.commands(registry);
+        if published.is_current(&scope, Some(&commands)) {
+            continue;
+        }
+        bot.set_my_commands(commands.clone()).scope(scope.clone()).await?;
+        published.set(&scope, Some(&commands));
+        tokio::time::sleep(CALL_INTERVAL).await;
+    }
+
+    let (residents, with_roles) = env
+        .db
+        .run(|conn| -> QueryResult<_> {
+            let residents: Vec<DbUserId> = schema::residents::table
+                .filter(schema::residents::end_date.is_null())
+                .select(schema::residents::tg_id)
+                .load(conn)?;
+            let with_roles: Vec<DbUserId> = schema::user_roles::table
+                .select(schema::user_roles::user_id)
+                .distinct()
+                .load(conn)?;
+            Ok((residents, with_roles))
+        })
+        .await?;
+    // Lists in residential chats are only needed for users with extra
+    // commands, so skip them for other residents to save API calls.
+    let special = with_roles
+        .into_iter()
+        .map(UserId::from)
+        .chain(config.telegram.admins.iter().copied())
+        .unique()
+        .collect_vec();
+    for &user_id in &special {
+        refresh_user_lists(bot, env, registry, published, user_id).await;
+    }
+    for user_id in residents.into_iter().map(UserId::from) {
+        if !special.contains(&user_id) {
+            let audience = user_audience(env, user_id).await;
+            refresh_private(bot, registry, published, user_id, &audience).await;
+        }
+    }
+    Ok(())
+}
+
+/// Update command lists of the user, e.g. after their residency or roles
+/// have changed.
+pub async fn refresh_user(
+    bot: &Bot,
+    env: &BotEnv,
+    registry: &Registry,
+    user_id: UserId,
+) {
+    let _publishing = PUBLISHING.lock().await;
+    let mut published = Published::load(env).await;
+    refresh_user_lists(bot, env, registry, &mut published, user_id).await;
+    published.save(env).await;
+}
+
+async fn refresh_user_lists(
+    bot: &Bot,
+    env: &BotEnv,
+    registry: &Registry,
+    published: &mut Published,
+    user_id: UserId,
+) {
+    let audience = user_audience(env, user_id).await;
+    refresh_private(bot, registry, published, user_id, &audience).await;
+
+    let special = audience.admin || !audience.roles.is_empty();
+    let in_chat = Audience { resident_chat: true, resident: true, ..audience };
+    for &chat in &env.config().telegram.chats.residential {
+        let scope = BotCommandScope::ChatMember {
+            chat_id: Recipient::Id(chat),
+            user_id,
+        };
+        let commands = special.then(|| in_chat.commands(registry));
+        set_commands(bot, published, scope, commands).await;
+    }
+}
+
+/// Audience of the user in group chats. Only roles from the database are
+/// considered, LDAP is not queried.
+async fn user_audience(env: &BotEnv, user_id: UserId) -> Audience {
+    let (roles, resident) = env
+        .db
+        .run(move |conn| {
+            let roles = schema::user_roles::table
+                .filter(schema::user_roles::user_id.eq(DbUserId::from(user_id)))
+                .select(schema::user_roles::role)
+                .load(conn)
+                .log_ok(module_path!(), "Failed to load user roles")
+                .unwrap_or_default();
+            (roles, is_resident(conn, user_id))
+        })
+        .await;
+    Audience {
+        resident,
+        admin: env.config().telegram.admins.contains(&user_id),
+        roles,
+        ..ANYONE
+    }
+}
+
+async fn refresh_private(
+    bot: &Bot,
+    registry: &Registry,
+    published: &mut Published,
+    user_id: UserId,
+    audience: &Audience,
+) {
+    let audience = Audience { private: true, ..audience.clone() };
+    let scope =
+        BotCommandScope::Chat { chat_id: Recipient::Id(ChatId::from(user_id)) };
+    let special =
+        audience.resident || audience.admin || !audience.roles.is_empty();
+    let commands = special.then(|| audience.commands(registry));
+    set_commands(bot, published, scope, commands).await;
+}
+
+/// Set commands of the scope, or delete them to fall back to a broader
+/// scope. Does nothing if the same commands were already published.
+async fn set_commands(
+    bot: &Bot,
+    published: &mut Published,
+    scope: BotCommandScope,
+    commands: Option<Vec<BotCommand>>,
+) {
+    if published.is_current(&scope, commands.as_ref()) {
+        return;
+    }
+    let result = match &commands {
+        Some(commands) => {
+            bot.set_my_commands(commands.clone()).scope(scope.clone()).await
+        }
+        None => bot.delete_my_commands().scope(scope.clone()).await,
+    };
+    match result {
+        Ok(_) => published.set(&scope, commands.as_ref()),
+        // E.g. the user has never started a chat with the bot. Retried on
+        // the next publish.
+        Err(e) => log::debug!("Failed to set commands for {scope:?}: {e}"),
+    }
+    tokio::time::sleep(CALL_INTERVAL).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TestBot;
+
+    #[tokio::test]
+    async fn test_publish() {
+        let t = TestBot::new().await;
+        t.add_resident(100);
+        let registry = Registry::default();
+        publish(&t.bot, &t.env, &registry).await.unwrap();
+
+        let calls = t.api.take_calls();
+        let commands = |scope: serde_json::Value| {
+            let call = calls
+                .iter()
+                .find(|c| c.body["scope"] == scope)
+                .unwrap_or_else(|| panic!("no call for {scope}"));
+            assert_eq!(call.method, "setMyCommands");
+            call.body["commands"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|c| c["command"].as_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+        let private =
+            commands(serde_json::json!({"type": "all_private_chats"}));
+        assert!(private.contains(&"/help".to_string()));
+        assert!(!private.contains(&"/needs".to_string()));
+        assert!(!private.contains(&"/racovina".to_string()));
+
+        let resident =
+            commands(serde_json::json!({"type": "chat", "chat_id": 100}));
+        assert!(resident.contains(&"/needs".to_string()));
+        assert!(resident.contains(&"/api_token".to_string()));
+        assert!(!resident.contains(&"/reload_config".to_string()));
+
+        let chat = crate::testing::test_config().telegram.chats.residential[0];
+        let admin = crate::testing::test_config().telegram.admins[0];
+        let admin_in_chat = commands(serde_json::json!({
+            "type": "chat_member",
+            "chat_id": chat.0,
+            "user_id": admin.0,
+        }));
+        assert!(admin_in_chat.contains(&"/racovina".to_string()));
+        assert!(admin_in_chat.contains(&"/reload_config".to_string()));
+        assert!(!admin_in_chat.contains(&"/api_token".to_string()));
+
+        // Unchanged lists are not published again, e.g. after a restart.
+        publish(&t.bot, &t.env, &registry).await.unwrap();
+        assert!(t.api.take_calls().is_empty());
+        t.add_resident(101);
+        publish(&t.bot, &t.env, &registry).await.unwrap();
+        let calls = t.api.take_calls();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].body["scope"]["chat_id"], 101);
+
+        t.stop().await;
+    }
+}
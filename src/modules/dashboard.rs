@@ -6,6 +6,7 @@ use macro_rules_attribute::derive;
 use teloxide::macros::BotCommands;
 use teloxide::prelude::*;
 
+use super::{BotModule, ModuleCommands};
 use crate::common::{filter_command, BotCommandsExt, BotEnv, UpdateHandler};
 use crate::utils::{get_wikijs_page, parse_tg_thread_link, BotExt as _};
 
@@ -15,11 +16,29 @@ mod raw;
 #[command(rename_rule = "snake_case")]
 pub enum Commands {
     #[custom(admin = true)]
-    #[command()]
+    #[command(
+        description = "post dashboard messages to a thread, for debugging."
+    )]
     DebugUpdateDashboard(String),
 }
 
-pub fn command_handler() -> UpdateHandler {
+pub struct Module;
+
+impl BotModule for Module {
+    fn name(&self) -> &'static str {
+        "dashboard"
+    }
+
+    fn message_handler(&self) -> Option<UpdateHandler> {
+        Some(command_handler())
+    }
+
+    fn commands(&self) -> Option<ModuleCommands> {
+        Some(ModuleCommands::of::<Commands>())
+    }
+}
+
+fn command_handler() -> UpdateHandler {
     filter_command::<Commands>().endpoint(handle_command)
 }
 
@@ -40,24 +59,23 @@ async fn handle_command(
     Ok(())
 }
 
+/// Update the dashboard from Wiki.js. Does nothing if the dashboard or
+/// Wiki.js is not configured.
 pub async fn update(bot: &Bot, env: &Arc<BotEnv>) -> Result<()> {
-    let page = get_wikijs_page(
-        &env.config.services.wikijs.url,
-        &env.config.services.wikijs.token,
-        &env.config.services.wikijs.dashboard_page,
-    )
-    .await?;
+    let config = env.config();
+    let (Some(wikijs), Some(dashboard)) =
+        (&config.services.wikijs, config.telegram.chats.dashboard)
+    else {
+        return Ok(());
+    };
+    let page =
+        get_wikijs_page(&wikijs.url, &wikijs.token, &wikijs.dashboard_page)
+            .await?;
 
     let page = crate::modules::welcome::extract_message(&page)
         .context("Failed to extract message from Wiki.js page")?;
 
-    raw::update(
-        bot,
-        Arc::clone(env),
-        env.config.telegram.chats.dashboard,
-        &[page],
-    )
-    .await?;
+    raw::update(bot, Arc::clone(env), dashboard, &[page]).await?;
 
     Ok(())
 }
@@ -42,7 +42,7 @@ async fn update_once(
             .filter(d::thread_id.eq(DbThreadId::from(thread.thread)))
             .order(d::message_id.asc())
             .select((d::message_id, d::text))
-            .load(&mut *env.conn.lock().unwrap())?
+            .load(&mut *env.conn())?
     };
 
     let (unordered, ordered) = old_messages
@@ -94,7 +94,7 @@ async fn reset(
             .filter(d::chat_id.eq(DbChatId::from(thread.chat)))
             .filter(d::thread_id.eq(DbThreadId::from(thread.thread)))
             .select(d::message_id)
-            .load(&mut *env.conn.lock().unwrap())?
+            .load(&mut *env.conn())?
     };
 
     let has_errors = old_messages
@@ -114,7 +114,7 @@ async fn reset(
                         )
                         .filter(d::message_id.eq(it)),
                 )
-                .execute(&mut *env.conn.lock().unwrap())
+                .execute(&mut *env.conn())
                 .err();
                 if let Some(ref err) = err {
                     log::error!("Failed to delete dashboard item: {err}");
@@ -162,14 +162,17 @@ async fn handle_pair(
             }
 
             use crate::schema::dashboard_messages::dsl as d;
-            diesel::replace_into(d::dashboard_messages)
+            diesel::insert_into(d::dashboard_messages)
                 .values(NewDashboardMessage {
                     chat_id: DbChatId::from(thread.chat),
                     thread_id: DbThreadId::from(thread.thread),
                     message_id: *msg_id,
                     text: new_text,
                 })
-                .execute(&mut *env.conn.lock().unwrap())?;
+                .on_conflict((d::chat_id, d::thread_id, d::message_id))
+                .do_update()
+                .set(d::text.eq(new_text))
+                .execute(&mut *env.conn())?;
         }
         EitherOrBoth::Left((msg_id, _)) => {
             match bot
@@ -189,7 +192,7 @@ async fn handle_pair(
                     .filter(d::thread_id.eq(DbThreadId::from(thread.thread)))
                     .filter(d::message_id.eq(msg_id)),
             )
-            .execute(&mut *env.conn.lock().unwrap())?;
+            .execute(&mut *env.conn())?;
         }
         EitherOrBoth::Right(new_text) => {
             let msg = bot
@@ -200,14 +203,17 @@ async fn handle_pair(
                 .disable_notification(true)
                 .await?;
             use crate::schema::dashboard_messages::dsl as d;
-            diesel::replace_into(d::dashboard_messages)
+            diesel::insert_into(d::dashboard_messages)
                 .values(NewDashboardMessage {
                     chat_id: DbChatId::from(thread.chat),
                     thread_id: DbThreadId::from(thread.thread),
                     message_id: DbMessageId::from(msg.id),
                     text: new_text,
                 })
-                .execute(&mut *env.conn.lock().unwrap())?;
+                .on_conflict((d::chat_id, d::thread_id, d::message_id))
+                .do_update()
+                .set(d::text.eq(new_text))
+                .execute(&mut *env.conn())?;
         }
     }
     Ok(false)
@@ -10,6 +10,7 @@ use std::sync::{Arc, Mutex};
 
 use anyhow::Result;
 use diesel::prelude::*;
+use dptree::di::DependencyMap;
 use reqwest::Url;
 use teloxide::prelude::*;
 use teloxide::types::{
@@ -18,20 +19,36 @@ use teloxide::types::{
 use teloxide::utils::html;
 use teloxide::{ApiError, RequestError};
 
-use crate::common::{BotEnv, TopicEmojis};
+use super::BotModule;
+use crate::common::{BotEnv, TopicEmojis, UpdateHandler};
 use crate::db::{DbChatId, DbThreadId};
 use crate::models;
-use crate::utils::{format_to, ChatIdExt as _, MessageExt as _};
+use crate::utils::{format_to, inspect_err, ChatIdExt as _, MessageExt as _};
 
 /// State contains a set of newly created topics.
 #[derive(Clone, Debug, Default)]
 pub struct State(HashSet<(ChatId, ThreadId)>);
 
-pub fn state() -> Arc<Mutex<State>> {
-    Arc::new(Mutex::new(State::default()))
+#[derive(Default)]
+pub struct Module {
+    state: Arc<Mutex<State>>,
+}
+
+impl BotModule for Module {
+    fn name(&self) -> &'static str {
+        "forward_topic_pins"
+    }
+
+    fn inspect_message(&self) -> Option<UpdateHandler> {
+        Some(inspect_err(inspect_message))
+    }
+
+    fn dependencies(&self, deps: &mut DependencyMap) {
+        deps.insert(Arc::clone(&self.state));
+    }
 }
 
-pub async fn inspect_message<'a>(
+async fn inspect_message<'a>(
     bot: Bot,
     env: Arc<BotEnv>,
     state: Arc<Mutex<State>>,
@@ -62,7 +79,8 @@ async fn forward_message(
     msg: &Message,
     is_pin: bool,
 ) -> Result<()> {
-    let forward_to = env.config.telegram.chats.forward_pins.iter().find(|f| {
+    let config = env.config();
+    let forward_to = config.telegram.chats.forward_pins.iter().find(|f| {
         f.from == msg.chat.id
             && msg.thread_id.map_or(true, |t| !f.ignore_threads.contains(&t))
     });
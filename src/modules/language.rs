@@ -0,0 +1,145 @@
+//! `/language` command to choose the language of bot replies, see
+//! [`crate::i18n`].
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use itertools::Itertools as _;
+use macro_rules_attribute::derive;
+use teloxide::macros::BotCommands;
+use teloxide::prelude::*;
+use teloxide::types::ParseMode;
+
+use super::{BotModule, ModuleCommands};
+use crate::common::{filter_command, BotCommandsExt, BotEnv, UpdateHandler};
+use crate::i18n::{self, msg_lang, tr, Lang};
+use crate::utils::BotExt;
+
+#[derive(Clone, BotCommands, BotCommandsExt!)]
+#[command(rename_rule = "snake_case")]
+pub enum Commands {
+    #[command(
+        description = "choose the language of replies, in this chat or for you."
+    )]
+    Language(String),
+}
+
+pub struct Module;
+
+impl BotModule for Module {
+    fn name(&self) -> &'static str {
+        "language"
+    }
+
+    fn message_handler(&self) -> Option<UpdateHandler> {
+        Some(command_handler())
+    }
+
+    fn commands(&self) -> Option<ModuleCommands> {
+        Some(ModuleCommands::of::<Commands>())
+    }
+}
+
+fn command_handler() -> UpdateHandler {
+    filter_command::<Commands>().endpoint(cmd_language)
+}
+
+async fn cmd_language(
+    bot: Bot,
+    env: Arc<BotEnv>,
+    msg: Message,
+    Commands::Language(arg): Commands,
+) -> Result<()> {
+    let Some(from) = &msg.from else { return Ok(()) };
+    let arg = arg.trim();
+    let lang = msg_lang(&env, &msg).await;
+
+    let new_lang = match arg {
+        "" => {
+            let text = format!(
+                "{}\n{}",
+                tr!(lang, "language-current", lang = lang.name()),
+                usage(lang),
+            );
+            bot.reply_message(&msg, text).parse_mode(ParseMode::Html).await?;
+            return Ok(());
+        }
+        "auto" => None,
+        code => {
+            let Some(new_lang) = Lang::from_code(code) else {
+                let text = format!(
+                    "{}\n{}",
+                    tr!(lang, "language-unknown"),
+                    usage(lang)
+                );
+                bot.reply_message(&msg, text)
+                    .parse_mode(ParseMode::Html)
+                    .await?;
+                return Ok(());
+            };
+            Some(new_lang)
+        }
+    };
+
+    if !msg.chat.is_private()
+        && !env.config().telegram.admins.contains(&from.id)
+        && !bot.get_chat_member(msg.chat.id, from.id).await?.is_privileged()
+    {
+        bot.reply_message(&msg, tr!(lang, "language-admins-only")).await?;
+        return Ok(());
+    }
+
+    i18n::set_chat_lang(&env, msg.chat.id, new_lang).await?;
+    let text = match new_lang {
+        Some(new_lang) => {
+            tr!(new_lang, "language-set", lang = new_lang.name())
+        }
+        None => tr!(msg_lang(&env, &msg).await, "language-reset"),
+    };
+    bot.reply_message(&msg, text).await?;
+    Ok(())
+}
+
+fn usage(lang: Lang) -> String {
+    let codes = Lang::ALL
+        .iter()
+        .map(|l| format!("<code>{}</code> ({})", l.code(), l.name()))
+        .join(", ");
+    tr!(lang, "language-usage", codes = codes)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::{message, message_update, TestBot, CHAT};
+
+    #[tokio::test]
+    async fn test_language() {
+        let t = TestBot::new().await;
+
+        t.dispatch(message_update(message(100, None, 100, "/language ru")))
+            .await
+            .unwrap();
+        assert_eq!(
+            t.api.take_calls()[0].body["text"],
+            "Язык изменён на Русский."
+        );
+
+        // Private chat settings apply to the user in group chats
+        t.add_resident(100);
+        t.dispatch(message_update(message(CHAT, None, 100, "/needs")))
+            .await
+            .unwrap();
+        let calls = t.api.take_calls();
+        assert_eq!(calls[0].body["text"], "Ничего не нужно.");
+
+        t.dispatch(message_update(message(100, None, 100, "/language auto")))
+            .await
+            .unwrap();
+        assert_eq!(
+            t.api.take_calls()[0].body["text"],
+            "Language setting is reset."
+        );
+
+        t.stop().await;
+    }
+}
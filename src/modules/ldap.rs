@@ -4,13 +4,17 @@ use std::sync::Arc;
 
 use anyhow::Result;
 use argh::FromArgs;
+use ldap_rs::LdapClient;
 use macro_rules_attribute::derive;
 use passwords::PasswordGenerator;
 use teloxide::prelude::*;
 use teloxide::utils::command::BotCommands;
 
+use super::{BotModule, ModuleCommands};
 use crate::common::{filter_command, BotCommandsExt, BotEnv, UpdateHandler};
+use crate::i18n::{msg_lang, tr, Lang};
 use crate::utils::{ldap, BotExt};
+use crate::{config, trace_redaction};
 
 const PASSWORD_GENERATOR: PasswordGenerator = PasswordGenerator {
     length: 24,
@@ -69,7 +73,23 @@ struct LdapUpdateArgs {
     display_name: Option<String>,
 }
 
-pub fn command_handler() -> UpdateHandler {
+pub struct Module;
+
+impl BotModule for Module {
+    fn name(&self) -> &'static str {
+        "ldap"
+    }
+
+    fn message_handler(&self) -> Option<UpdateHandler> {
+        Some(command_handler())
+    }
+
+    fn commands(&self) -> Option<ModuleCommands> {
+        Some(ModuleCommands::of::<Commands>())
+    }
+}
+
+fn command_handler() -> UpdateHandler {
     filter_command::<Commands>().endpoint(start)
 }
 
@@ -79,21 +99,36 @@ async fn start<'a>(
     msg: Message,
     command: Commands,
 ) -> Result<()> {
+    let config = env.config();
+    let lang = msg_lang(&env, &msg).await;
+    let Some(ldap_config) = &config.services.ldap else {
+        bot.reply_message(&msg, tr!(lang, "ldap-not-configured")).await?;
+        return Ok(());
+    };
+    let Ok(mut ldap_conn) = env.ldap_client().await else {
+        bot.reply_message(&msg, tr!(lang, "ldap-not-available")).await?;
+        return Ok(());
+    };
+    let ldap_conn = &mut *ldap_conn;
     match command {
         Commands::LdapRegister(args) => {
-            ldap_register(bot, env, msg, &args).await?;
+            ldap_register(bot, ldap_conn, ldap_config, msg, &args, lang)
+                .await?;
         }
         Commands::LdapResetPassword => {
-            ldap_reset_password(bot, env, msg).await?;
+            ldap_reset_password(bot, ldap_conn, ldap_config, msg, lang).await?;
         }
-        Commands::LdapUpdate(args) => ldap_update(bot, env, msg, &args).await?,
-        // Commands::LdapGroups => ldap_groups(bot, env, msg).await?,
+        Commands::LdapUpdate(args) => {
+            ldap_update(bot, ldap_conn, ldap_config, msg, &args, lang).await?;
+        } // Commands::LdapGroups => {
+          //     ldap_groups(bot, ldap_conn, ldap_config, msg, lang).await?;
+          // }
     }
     Ok(())
 }
 
-async fn ldap_not_found(bot: Bot, msg: Message) -> Result<()> {
-    bot.reply_message(&msg, "You are not in the LDAP database. You need to do /ldap_register first.").await?;
+async fn ldap_not_found(bot: Bot, msg: Message, lang: Lang) -> Result<()> {
+    bot.reply_message(&msg, tr!(lang, "ldap-not-registered")).await?;
     Ok(())
 }
 
@@ -106,9 +141,11 @@ fn user_full_name(user: &teloxide::types::User) -> String {
 
 async fn ldap_register(
     bot: Bot,
-    env: Arc<BotEnv>,
+    ldap_conn: &mut LdapClient,
+    ldap_config: &config::Ldap,
     msg: Message,
     args: &str,
+    lang: Lang,
 ) -> Result<()> {
     let args = args.split_whitespace().collect::<Vec<_>>();
     let args = match LdapRegisterArgs::from_args(&["/ldap_register"], &args) {
@@ -119,60 +156,56 @@ async fn ldap_register(
         }
     };
 
-    let mut ldap_conn = env.ldap_client().await;
-
     let user =
         msg.from.as_ref().ok_or_else(|| anyhow::anyhow!("No user ID"))?;
 
-    if ldap::get_user(&mut ldap_conn, &env.config.services.ldap, user.id)
-        .await?
-        .is_some()
-    {
-        bot.reply_message(
-            &msg,
-            "You are already registered in the LDAP database.",
-        )
-        .await?;
+    if ldap::get_user(ldap_conn, ldap_config, user.id).await?.is_some() {
+        bot.reply_message(&msg, tr!(lang, "ldap-already-registered")).await?;
         return Ok(());
     }
 
     let Some(username) = args.username.or_else(|| user.username.clone()) else {
-        bot.reply_message(&msg, "No username provided or found.").await?;
+        bot.reply_message(&msg, tr!(lang, "ldap-no-username")).await?;
         return Ok(());
     };
 
     let mut ldap_user = ldap::User::new_from_telegram(
-        &env.config.services.ldap,
+        ldap_config,
         user.id,
         &username,
         &args.mail,
         Some(user_full_name(user)),
     );
     let password = PASSWORD_GENERATOR.generate_one().unwrap();
+    let _hidden = trace_redaction::hide_secret(&password);
     ldap_user.update_password(ldap::Sha512PasswordHash::new(), &password);
 
-    ldap::add_user(&mut ldap_conn, &env.config.services.ldap, &ldap_user)
-        .await?;
+    ldap::add_user(ldap_conn, ldap_config, &ldap_user).await?;
     ldap::add_user_to_group(
-        &mut ldap_conn,
-        &env.config.services.ldap,
+        ldap_conn,
+        ldap_config,
         &ldap_user,
-        &env.config.services.ldap.attributes.resident_group,
+        &ldap_config.attributes.resident_group,
     )
     .await?;
 
-    bot.reply_message(&msg, format!("You have been registered in the LDAP database with password: <code>{password}</code>."))
+    bot.reply_message(
+        &msg,
+        tr!(lang, "ldap-registered", password = password.as_str()),
+    )
     .parse_mode(teloxide::types::ParseMode::Html)
-        .await?;
+    .await?;
 
     Ok(())
 }
 
 async fn ldap_update(
     bot: Bot,
-    env: Arc<BotEnv>,
+    ldap_conn: &mut LdapClient,
+    ldap_config: &config::Ldap,
     msg: Message,
     args: &str,
+    lang: Lang,
 ) -> Result<()> {
     let args = args.split_whitespace().collect::<Vec<_>>();
     let args = match LdapUpdateArgs::from_args(&["/ldap_update"], &args) {
@@ -183,14 +216,12 @@ async fn ldap_update(
         }
     };
 
-    let mut ldap_conn = env.ldap_client().await;
     let user_id =
         msg.from.as_ref().ok_or_else(|| anyhow::anyhow!("No user ID"))?.id;
     let Some(mut user) =
-        ldap::get_user(&mut ldap_conn, &env.config.services.ldap, user_id)
-            .await?
+        ldap::get_user(ldap_conn, ldap_config, user_id).await?
     else {
-        ldap_not_found(bot, msg).await?;
+        ldap_not_found(bot, msg, lang).await?;
         return Ok(());
     };
 
@@ -204,59 +235,62 @@ async fn ldap_update(
         user.display_name = Some(display_name);
     }
 
-    ldap::update_user(&mut ldap_conn, &env.config.services.ldap, &user).await?;
+    ldap::update_user(ldap_conn, ldap_config, &user).await?;
 
-    bot.reply_message(&msg, "Your LDAP settings have been updated.").await?;
+    bot.reply_message(&msg, tr!(lang, "ldap-updated")).await?;
     Ok(())
 }
 
 async fn ldap_reset_password(
     bot: Bot,
-    env: Arc<BotEnv>,
+    ldap_conn: &mut LdapClient,
+    ldap_config: &config::Ldap,
     msg: Message,
+    lang: Lang,
 ) -> Result<()> {
-    let mut ldap_conn = env.ldap_client().await;
     let user_id =
         msg.from.as_ref().ok_or_else(|| anyhow::anyhow!("No user ID"))?.id;
     let Some(mut user) =
-        ldap::get_user(&mut ldap_conn, &env.config.services.ldap, user_id)
-            .await?
+        ldap::get_user(ldap_conn, ldap_config, user_id).await?
     else {
-        ldap_not_found(bot, msg).await?;
+        ldap_not_found(bot, msg, lang).await?;
         return Ok(());
     };
 
     let password = PASSWORD_GENERATOR.generate_one().unwrap();
+    let _hidden = trace_redaction::hide_secret(&password);
 
     user.update_password(ldap::Sha512PasswordHash::new(), &password);
-    ldap::update_user(&mut ldap_conn, &env.config.services.ldap, &user).await?;
+    ldap::update_user(ldap_conn, ldap_config, &user).await?;
 
     bot.reply_message(
         &msg,
-        format!("Your new password is <code>{password}</code>."),
+        tr!(lang, "ldap-new-password", password = password.as_str()),
     )
     .parse_mode(teloxide::types::ParseMode::Html)
     .await?;
     Ok(())
 }
 
-async fn ldap_groups(bot: Bot, env: Arc<BotEnv>, msg: Message) -> Result<()> {
-    let mut ldap_conn = env.ldap_client().await;
+async fn ldap_groups(
+    bot: Bot,
+    ldap_conn: &mut LdapClient,
+    ldap_config: &config::Ldap,
+    msg: Message,
+    lang: Lang,
+) -> Result<()> {
     let user_id =
         msg.from.as_ref().ok_or_else(|| anyhow::anyhow!("No user ID"))?.id;
-    let Some(user) =
-        ldap::get_user(&mut ldap_conn, &env.config.services.ldap, user_id)
-            .await?
+    let Some(user) = ldap::get_user(ldap_conn, ldap_config, user_id).await?
     else {
-        ldap_not_found(bot, msg).await?;
+        ldap_not_found(bot, msg, lang).await?;
         return Ok(());
     };
 
-    let groups =
-        ldap::get_user_groups(&mut ldap_conn, &env.config.services.ldap, &user)
-            .await?;
+    let groups = ldap::get_user_groups(ldap_conn, ldap_config, &user).await?;
 
-    let mut text = "Your LDAP groups:\n".to_string();
+    let mut text = tr!(lang, "ldap-groups");
+    text.push('\n');
     for group in groups {
         text.push_str(&format!("- {group}\n"));
     }
@@ -1,22 +1,26 @@
 use std::collections::{HashMap, HashSet};
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 use std::time::Duration;
 
 use anyhow::Result;
-use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl, SqliteConnection};
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use dptree::di::DependencyMap;
+use futures::future::BoxFuture;
 use teloxide::payloads::SendMessageSetters;
 use teloxide::requests::Requester;
 use teloxide::types::UserId;
 use teloxide::Bot;
 use tokio::sync::RwLock;
 use tokio::time::sleep;
+use tokio_util::sync::CancellationToken;
 
+use super::BotModule;
 use crate::common::{format_users, BotEnv};
 use crate::config::Mikrotik;
-use crate::db::DbUserId;
+use crate::db::{DbUserId, Pool};
 use crate::metrics::update_user_online;
 use crate::utils::mikrotik::get_leases;
-use crate::utils::ThreadIdPair;
+use crate::utils::{ResultExt as _, ThreadIdPair};
 use crate::{models, schema};
 
 /// State contains a set of active MAC addresses.
@@ -29,15 +33,64 @@ impl State {
     }
 }
 
-pub fn state() -> Arc<RwLock<State>> {
-    Arc::new(RwLock::new(State::default()))
+#[derive(Default)]
+pub struct Module {
+    state: Arc<RwLock<State>>,
+}
+
+impl BotModule for Module {
+    fn name(&self) -> &'static str {
+        "mac_monitoring"
+    }
+
+    fn dependencies(&self, deps: &mut DependencyMap) {
+        deps.insert(Arc::clone(&self.state));
+    }
+
+    fn load_state(&self, env: &BotEnv) {
+        let Some(users) = models::mac_monitoring_online
+            .get(&mut env.conn())
+            .log_ok(module_path!(), "Failed to load online users")
+            .flatten()
+        else {
+            return;
+        };
+        for &tg_id in &users {
+            update_user_online(tg_id, true);
+        }
+        // Not locked yet, since tasks are not started.
+        if let Ok(mut state) = self.state.try_write() {
+            state.0 = Some(users);
+        }
+    }
+
+    fn save_state(&self, env: &BotEnv) {
+        let Ok(state) = self.state.try_read() else {
+            log::error!("Failed to save online users: state is locked");
+            return;
+        };
+        if let Some(users) = &state.0 {
+            models::mac_monitoring_online
+                .set(&mut env.conn(), users)
+                .log_error(module_path!(), "Failed to save online users");
+        }
+    }
+
+    fn task(
+        &self,
+        bot: Bot,
+        env: Arc<BotEnv>,
+        cancel: CancellationToken,
+    ) -> Option<BoxFuture<'static, ()>> {
+        Some(Box::pin(watch_loop(env, Arc::clone(&self.state), bot, cancel)))
+    }
 }
 
 async fn mac_monitoring(
     reqwest_client: &reqwest::Client,
     mikrotik_conf: &Mikrotik,
     mac_monitoring_thread: &ThreadIdPair,
-    conn: &Mutex<SqliteConnection>,
+    db: &Pool,
     state: Arc<RwLock<State>>,
     bot: &Bot,
 ) -> Result<()> {
@@ -53,7 +106,7 @@ async fn mac_monitoring(
         let db_result: Vec<DbUserId> = schema::user_macs::table
             .filter(schema::user_macs::mac.eq_any(&active_mac_addrs))
             .select(schema::user_macs::tg_id)
-            .load(&mut *conn.lock().unwrap())?;
+            .load(&mut *db.get())?;
         db_result.into_iter().map(UserId::from).collect()
     };
 
@@ -85,7 +138,7 @@ async fn mac_monitoring(
                 .eq_any(changed_users.iter().map(|id| DbUserId::from(**id))),
         )
         .select(schema::tg_users::all_columns)
-        .load(&mut *conn.lock().unwrap())?;
+        .load(&mut *db.get())?;
     let id_to_user_map: HashMap<&UserId, Option<&models::TgUser>> =
         changed_users
             .into_iter()
@@ -127,21 +180,37 @@ async fn mac_monitoring(
     Ok(())
 }
 
-pub async fn watch_loop(env: Arc<BotEnv>, state: Arc<RwLock<State>>, bot: Bot) {
+async fn watch_loop(
+    env: Arc<BotEnv>,
+    state: Arc<RwLock<State>>,
+    bot: Bot,
+    cancel: CancellationToken,
+) {
     loop {
-        log::debug!("Executing mac_monitoring");
-        if let Err(e) = mac_monitoring(
-            &env.reqwest_client,
-            &env.config.services.mikrotik,
-            &env.config.telegram.chats.mac_monitoring,
-            &env.conn,
-            Arc::clone(&state),
-            &bot,
-        )
-        .await
+        let config = env.config();
+        // Checked on each iteration, so the module could be enabled by
+        // reloading the config.
+        if let (Some(mikrotik), Some(thread)) =
+            (&config.services.mikrotik, &config.telegram.chats.mac_monitoring)
         {
-            log::error!("Failed to get leases: {e}");
-        };
-        sleep(Duration::from_secs(60)).await;
+            log::debug!("Executing mac_monitoring");
+            if let Err(e) = mac_monitoring(
+                &env.reqwest_client,
+                mikrotik,
+                thread,
+                &env.db,
+                Arc::clone(&state),
+                &bot,
+            )
+            .await
+            {
+                log::error!("Failed to get leases: {e}");
+            };
+        }
+        drop(config);
+        tokio::select! {
+            () = cancel.cancelled() => break,
+            () = sleep(Duration::from_secs(60)) => (),
+        }
     }
 }
@@ -24,11 +24,13 @@ use teloxide::types::{
 };
 use teloxide::utils::html;
 
+use super::{BotModule, ModuleCommands};
 use crate::common::{
     filter_command, format_user, BotCommandsExt, BotEnv, UpdateHandler,
 };
 use crate::config::Config;
-use crate::db::DbUserId;
+use crate::db::{DbChatId, DbMessageId, DbUserId};
+use crate::i18n::{callback_lang, chat_lang, msg_lang, tr, Lang};
 use crate::utils::{
     replace_urls_with_titles, write_message_link, BotExt, ResultExt,
     ThreadIdPair,
@@ -47,18 +49,39 @@ pub enum Commands {
     Need(String),
 }
 
-pub fn message_handler() -> UpdateHandler {
+pub struct Module;
+
+impl BotModule for Module {
+    fn name(&self) -> &'static str {
+        "needs"
+    }
+
+    fn message_handler(&self) -> Option<UpdateHandler> {
+        Some(message_handler())
+    }
+
+    fn callback_handler(&self) -> Option<UpdateHandler> {
+        Some(callback_handler())
+    }
+
+    fn commands(&self) -> Option<ModuleCommands> {
+        Some(ModuleCommands::of::<Commands>())
+    }
+}
+
+fn message_handler() -> UpdateHandler {
     dptree::entry()
         .branch(filter_command::<Commands>().endpoint(handle_command))
         .branch(
             dptree::filter(|env: Arc<BotEnv>, msg: Message| {
-                env.config.telegram.chats.needs.has_message(&msg)
+                (env.config().telegram.chats.needs)
+                    .is_some_and(|needs| needs.has_message(&msg))
             })
             .endpoint(handle_message),
         )
 }
 
-pub fn callback_handler() -> UpdateHandler {
+fn callback_handler() -> UpdateHandler {
     dptree::filter_map(filter_callbacks).endpoint(handle_callback)
 }
 
@@ -84,6 +107,11 @@ async fn handle_command(
     msg: Message,
     command: Commands,
 ) -> Result<()> {
+    if env.config().telegram.chats.needs.is_none() {
+        let text = tr!(msg_lang(&env, &msg).await, "needs-not-configured");
+        bot.reply_message(&msg, text).await?;
+        return Ok(());
+    }
     match command {
         Commands::Needs => command_needs(bot, env, msg).await,
         Commands::Need(item) => add_items(&bot, &env, &[&item], &msg).await,
@@ -92,7 +120,7 @@ async fn handle_command(
 
 async fn command_needs(bot: Bot, env: Arc<BotEnv>, msg: Message) -> Result<()> {
     // Delete old pinned message (if it is the needs thread)
-    if let Some(thread_pair) = check_thread_id(&env.config, &msg) {
+    if let Some(thread_pair) = check_thread_id(&env.config(), &msg) {
         let last_pin = models::needs_last_pin.get(&mut env.conn())?;
         if let Some(pin) = last_pin {
             if pin.thread_id_pair == thread_pair {
@@ -107,7 +135,8 @@ async fn command_needs(bot: Bot, env: Arc<BotEnv>, msg: Message) -> Result<()> {
     }
 
     // Send new message
-    let (text, buttons) = command_needs_message_and_buttons(&env)?;
+    let lang = msg_lang(&env, &msg).await;
+    let (text, buttons) = command_needs_message_and_buttons(&env, lang)?;
     let msg = bot
         .reply_message(&msg, text)
         .parse_mode(teloxide::types::ParseMode::Html)
@@ -116,7 +145,7 @@ async fn command_needs(bot: Bot, env: Arc<BotEnv>, msg: Message) -> Result<()> {
         .await?;
 
     // Pin new message (if it is the needs thread)
-    if let Some(thread_id_pair) = check_thread_id(&env.config, &msg) {
+    if let Some(thread_id_pair) = check_thread_id(&env.config(), &msg) {
         bot.pin_chat_message(thread_id_pair.chat, msg.id).await?;
         models::needs_last_pin.set(
             &mut env.conn(),
@@ -136,22 +165,21 @@ async fn add_items(
     let Some(user) = &msg.from else {
         return Ok(());
     };
+    let Some(needs) = env.config().telegram.chats.needs else {
+        return Ok(());
+    };
     if list_items.is_empty() {
         return Ok(());
     }
     let list_items = replace_urls_with_titles(list_items).await;
 
-    let pinned_message = if env.config.telegram.chats.needs.has_message(msg) {
+    let pinned_message = if needs.has_message(msg) {
         Cow::Borrowed(msg)
     } else {
         Cow::Owned(
-            bot.forward_message(
-                env.config.telegram.chats.needs.chat,
-                msg.chat.id,
-                msg.id,
-            )
-            .message_thread_id(env.config.telegram.chats.needs.thread)
-            .await?,
+            bot.forward_message(needs.chat, msg.chat.id, msg.id)
+                .message_thread_id(needs.thread)
+                .await?,
         )
     };
 
@@ -179,11 +207,84 @@ async fn add_items(
     Ok(())
 }
 
+/// Add items on behalf of a user, e.g. via the HTTP API. Since there is no
+/// request message, the items are posted to the needs thread instead.
+/// Returns the added items.
+pub async fn add_items_by_user(
+    bot: &Bot,
+    env: &BotEnv,
+    user_id: UserId,
+    list_items: &[&str],
+) -> Result<Vec<models::NeededItem>> {
+    let Some(needs) = env.config().telegram.chats.needs else {
+        anyhow::bail!("The needs thread is not configured");
+    };
+    if list_items.is_empty() {
+        return Ok(Vec::new());
+    }
+    let list_items = replace_urls_with_titles(list_items).await;
+
+    let user: Option<models::TgUser> = schema::tg_users::table
+        .filter(schema::tg_users::id.eq(DbUserId::from(user_id)))
+        .first(&mut *env.conn())
+        .optional()?;
+    let mut name = String::new();
+    format_user(&mut name, user_id, &user, false);
+    let mut text =
+        tr!(chat_lang(env, needs.chat).await, "needs-user-needs", user = name);
+    text.push('\n');
+    for item in &list_items {
+        writeln!(text, "- {}", html::escape(item)).unwrap();
+    }
+    let msg = bot
+        .send_message(needs.chat, text)
+        .message_thread_id(needs.thread)
+        .parse_mode(teloxide::types::ParseMode::Html)
+        .disable_web_page_preview(true)
+        .await?;
+
+    let items = env.transaction(|conn| {
+        diesel::insert_into(schema::needed_items::table)
+            .values(
+                list_items
+                    .iter()
+                    .map(|item| models::NewNeededItem {
+                        request_chat_id: msg.chat.id.into(),
+                        request_message_id: msg.id.into(),
+                        request_user_id: user_id.into(),
+                        pinned_chat_id: msg.chat.id.into(),
+                        pinned_message_id: msg.id.into(),
+                        buyer_user_id: None,
+                        item,
+                    })
+                    .collect_vec(),
+            )
+            .execute(conn)?;
+        schema::needed_items::table
+            .filter(
+                schema::needed_items::request_chat_id
+                    .eq(DbChatId::from(msg.chat.id)),
+            )
+            .filter(
+                schema::needed_items::request_message_id
+                    .eq(DbMessageId::from(msg.id)),
+            )
+            .order(schema::needed_items::rowid)
+            .load(conn)
+    })?;
+
+    bot.pin_chat_message(msg.chat.id, msg.id).await?;
+
+    update_pinned_needs_message(bot, env, None).await?;
+
+    Ok(items)
+}
+
 /// `Some` for the needs thread, `None` otherwise.
 fn check_thread_id(config: &Config, msg: &Message) -> Option<ThreadIdPair> {
     msg.thread_id
         .map(|thread| ThreadIdPair { chat: msg.chat.id, thread })
-        .filter(|p| p == &config.telegram.chats.needs)
+        .filter(|p| Some(*p) == config.telegram.chats.needs)
 }
 
 /// Update `/needs` message.
@@ -193,7 +294,8 @@ async fn edit_list_message(
     chat: ChatId,
     message: MessageId,
 ) -> Result<()> {
-    let (text, buttons) = command_needs_message_and_buttons(env)?;
+    let lang = chat_lang(env, chat).await;
+    let (text, buttons) = command_needs_message_and_buttons(env, lang)?;
     bot.edit_message_text(chat, message, text)
         .parse_mode(teloxide::types::ParseMode::Html)
         .disable_web_page_preview(true)
@@ -221,6 +323,7 @@ async fn update_pinned_needs_message(
 
 fn command_needs_message_and_buttons(
     env: &BotEnv,
+    lang: Lang,
 ) -> Result<(String, Vec<Vec<InlineKeyboardButton>>)> {
     let items: Vec<(models::NeededItem, Option<models::TgUser>)> =
         schema::needed_items::table
@@ -237,7 +340,7 @@ fn command_needs_message_and_buttons(
             .load(&mut *env.conn())?;
 
     if items.is_empty() {
-        return Ok(("No items needed.".to_string(), Vec::new()));
+        return Ok((tr!(lang, "needs-empty"), Vec::new()));
     }
 
     let mut text = String::new();
@@ -248,10 +351,10 @@ fn command_needs_message_and_buttons(
             (i.request_chat_id, i.request_message_id)
         })
     {
-        let is_public = item.request_chat_id
-            == env.config.telegram.chats.needs.chat.into()
+        let is_public = (env.config().telegram.chats.needs)
+            .is_some_and(|needs| item.request_chat_id == needs.chat.into())
             || env
-                .config
+                .config()
                 .telegram
                 .chats
                 .resident_owned
@@ -277,8 +380,9 @@ fn command_needs_message_and_buttons(
                 item.pinned_message_id
             },
         );
-        write!(text, "by ").unwrap();
-        format_user(&mut text, item.request_user_id, &user, false);
+        let mut name = String::new();
+        format_user(&mut name, item.request_user_id, &user, false);
+        text.push_str(&tr!(lang, "needs-item-by", user = name));
         text.push_str("</a>)\n");
 
         write!(button_text, ". {}", item.item).unwrap();
@@ -297,7 +401,8 @@ fn command_needs_message_and_buttons(
         ));
     }
 
-    text.push_str("\nPress a button to mark an item as bought.");
+    text.push('\n');
+    text.push_str(&tr!(lang, "needs-press-button"));
 
     Ok((text, buttons))
 }
@@ -339,9 +444,80 @@ async fn handle_callback_bought(
     bot: Bot,
     env: Arc<BotEnv>,
     callback: CallbackQuery,
-    rowid_: i32,
+    rowid: i32,
 ) -> Result<()> {
-    let result = env.transaction(|conn| {
+    let lang = callback_lang(&env, &callback).await;
+    let (item, has_more) = match set_bought(&env, rowid, callback.from.id)? {
+        Ok(result) => result,
+        Err(error) => {
+            let text = match error {
+                BuyError::NotFound => tr!(lang, "needs-not-found"),
+                BuyError::AlreadyBought => tr!(lang, "needs-already-bought"),
+            };
+            bot.answer_callback_query(&callback.id).text(text).await?;
+            return Ok(());
+        }
+    };
+
+    bot.answer_callback_query(&callback.id)
+        .text(tr!(lang, "needs-done"))
+        .await?;
+    notify_bought(
+        &bot,
+        &env,
+        &item,
+        has_more,
+        &callback.from.first_name,
+        callback.message.as_ref(),
+    )
+    .await
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum BuyError {
+    NotFound,
+    AlreadyBought,
+}
+
+impl BuyError {
+    /// Error message for the HTTP API.
+    pub const fn message(self) -> &'static str {
+        match self {
+            Self::NotFound => "Could not find item.",
+            Self::AlreadyBought => "Item already bought",
+        }
+    }
+}
+
+/// Mark an item as bought by a user, e.g. via the HTTP API.
+pub async fn mark_bought(
+    bot: &Bot,
+    env: &BotEnv,
+    rowid: i32,
+    buyer: UserId,
+) -> Result<Result<models::NeededItem, BuyError>> {
+    let (item, has_more) = match set_bought(env, rowid, buyer)? {
+        Ok(result) => result,
+        Err(error) => return Ok(Err(error)),
+    };
+    let buyer_user: Option<models::TgUser> = schema::tg_users::table
+        .filter(schema::tg_users::id.eq(DbUserId::from(buyer)))
+        .first(&mut *env.conn())
+        .optional()?;
+    let buyer_name =
+        buyer_user.map_or_else(|| buyer.to_string(), |u| u.first_name);
+    notify_bought(bot, env, &item, has_more, &buyer_name, None).await?;
+    Ok(Ok(item))
+}
+
+/// Set the buyer of an item. Returns the item and whether its request has
+/// more items to buy.
+fn set_bought(
+    env: &BotEnv,
+    rowid_: i32,
+    buyer: UserId,
+) -> Result<Result<(models::NeededItem, bool), BuyError>> {
+    Ok(env.transaction(|conn| {
         #[allow(clippy::wildcard_imports)]
         use schema::needed_items::dsl::*;
 
@@ -349,18 +525,19 @@ async fn handle_callback_bought(
             .filter(rowid.eq(rowid_))
             .get_result(conn)
             .optional()?;
-        let item_ = match item_ {
-            None => return Ok(Err("Could not find item.")),
+        let mut item_ = match item_ {
+            None => return Ok(Err(BuyError::NotFound)),
             Some(item_) if item_.buyer_user_id.is_some() => {
-                return Ok(Err("Item already bought"))
+                return Ok(Err(BuyError::AlreadyBought))
             }
             Some(item_) => item_,
         };
 
         diesel::update(schema::needed_items::table)
             .filter(rowid.eq(rowid_))
-            .set(buyer_user_id.eq(DbUserId::from(callback.from.id)))
+            .set(buyer_user_id.eq(DbUserId::from(buyer)))
             .execute(conn)?;
+        item_.buyer_user_id = Some(buyer.into());
 
         let remaining: i64 = schema::needed_items::table
             .filter(request_chat_id.eq(item_.request_chat_id))
@@ -370,43 +547,53 @@ async fn handle_callback_bought(
             .get_result(conn)?;
 
         Ok(Ok((item_, remaining > 0)))
-    })?;
-
-    let (item, has_more) = match result {
-        Ok((item, has_more)) => (item, has_more),
-        Err(error) => {
-            bot.answer_callback_query(&callback.id).text(error).await?;
-            return Ok(());
-        }
-    };
+    })?)
+}
 
-    bot.answer_callback_query(&callback.id).text("Done!").await?;
+/// Unpin the request if everything is bought, notify the needs thread, and
+/// update list messages.
+async fn notify_bought(
+    bot: &Bot,
+    env: &BotEnv,
+    item: &models::NeededItem,
+    has_more: bool,
+    buyer_name: &str,
+    list_message: Option<&Message>,
+) -> Result<()> {
     if !has_more {
         bot.unpin_chat_message(item.pinned_chat_id)
             .message_id(item.pinned_message_id.into())
             .await?;
     }
 
-    bot.send_message(
-        env.config.telegram.chats.needs.chat,
-        format!(
-            "{} marked an item {:?} as bought.",
-            callback.from.first_name, item.item
-        ),
-    )
-    .message_thread_id(env.config.telegram.chats.needs.thread)
-    .reply_markup(InlineKeyboardMarkup::new(vec![vec![
-        InlineKeyboardButton::callback("Undo", format!("n:undo:{rowid_}")),
-    ]]))
-    .await
-    .log_error(module_path!(), "Cannot send message to needs thread");
+    if let Some(needs) = env.config().telegram.chats.needs {
+        let lang = chat_lang(env, needs.chat).await;
+        bot.send_message(
+            needs.chat,
+            tr!(
+                lang,
+                "needs-marked-bought",
+                buyer = buyer_name,
+                item = item.item.as_str(),
+            ),
+        )
+        .message_thread_id(needs.thread)
+        .reply_markup(InlineKeyboardMarkup::new(vec![vec![
+            InlineKeyboardButton::callback(
+                tr!(lang, "needs-undo"),
+                format!("n:undo:{}", item.rowid),
+            ),
+        ]]))
+        .await
+        .log_error(module_path!(), "Cannot send message to needs thread");
+    }
 
-    if let Some(ref message) = callback.message {
-        edit_list_message(&bot, &env, message.chat.id, message.id)
+    if let Some(message) = list_message {
+        edit_list_message(bot, env, message.chat.id, message.id)
             .await
             .log_error(module_path!(), "Cannot edit callback message");
     }
-    update_pinned_needs_message(&bot, &env, callback.message.as_ref()).await?;
+    update_pinned_needs_message(bot, env, list_message).await?;
 
     Ok(())
 }
@@ -417,6 +604,7 @@ async fn handle_callback_undo(
     callback: CallbackQuery,
     rowid_: i32,
 ) -> Result<()> {
+    let lang = callback_lang(&env, &callback).await;
     let result = env.transaction(|conn| {
         #[allow(clippy::wildcard_imports)]
         use schema::needed_items::dsl::*;
@@ -426,14 +614,14 @@ async fn handle_callback_undo(
             .get_result(conn)
             .optional()?;
         let item_ = match item_ {
-            None => return Ok(Err("Could not find item.")),
+            None => return Ok(Err(tr!(lang, "needs-not-found"))),
             Some(models::NeededItem { buyer_user_id: None, .. }) => {
-                return Ok(Err("Item already undone."))
+                return Ok(Err(tr!(lang, "needs-already-undone")))
             }
             Some(models::NeededItem { buyer_user_id: Some(id), .. })
                 if UserId::from(id) != callback.from.id =>
             {
-                return Ok(Err("You did not buy this item."))
+                return Ok(Err(tr!(lang, "needs-not-buyer")))
             }
             Some(item_) => item_,
         };
@@ -532,6 +720,119 @@ fn letter_index(out: &mut String, index: usize) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testing::{
+        callback_query_update, message, message_update, TestBot, BOT_USER_ID,
+        CHAT, NEEDS_THREAD,
+    };
+
+    #[tokio::test]
+    async fn test_need_bought() {
+        let t = TestBot::new().await;
+        t.add_resident(100);
+
+        // Request from a private chat is forwarded to the needs thread
+        t.dispatch(message_update(message(100, None, 100, "/need milk")))
+            .await
+            .unwrap();
+        assert_eq!(t.api.take_methods(), ["forwardMessage", "pinChatMessage"]);
+
+        // The list is pinned in the needs thread
+        t.dispatch(message_update(message(
+            CHAT,
+            Some(NEEDS_THREAD),
+            100,
+            "/needs",
+        )))
+        .await
+        .unwrap();
+        let calls = t.api.take_calls();
+        assert_eq!(calls[0].method, "sendMessage");
+        assert!(calls[0].body["text"].as_str().unwrap().contains("milk"));
+        assert_eq!(calls[1].method, "pinChatMessage");
+        assert_eq!(calls.len(), 2);
+
+        let mut list_message =
+            message(CHAT, Some(NEEDS_THREAD), BOT_USER_ID, "");
+        list_message["message_id"] = calls[1].body["message_id"].clone();
+        t.dispatch(callback_query_update(100, list_message, "n:bought:1"))
+            .await
+            .unwrap();
+        let calls = t.api.take_calls();
+        assert_eq!(
+            calls.iter().map(|c| c.method.as_str()).collect_vec(),
+            [
+                "answerCallbackQuery",
+                "unpinChatMessage",
+                "sendMessage",
+                "editMessageText",
+            ]
+        );
+        assert_eq!(calls[3].body["text"], "No items needed.");
+
+        t.stop().await;
+    }
+
+    #[tokio::test]
+    async fn test_add_items_pin_failed() {
+        let t = TestBot::new().await;
+        t.api.add_response(
+            "pinChatMessage",
+            serde_json::json!({
+                "ok": false,
+                "error_code": 400,
+                "description": "Bad Request: not enough rights",
+            }),
+        );
+
+        let msg: Message = serde_json::from_value(message(
+            CHAT,
+            Some(NEEDS_THREAD),
+            100,
+            "- bread\n- butter",
+        ))
+        .unwrap();
+        let result =
+            add_items(&t.bot, &t.env, &["bread", "butter"], &msg).await;
+        assert!(result.is_err());
+        assert_eq!(t.api.take_methods(), ["pinChatMessage"]);
+
+        // Items are added nevertheless
+        let count: i64 = schema::needed_items::table
+            .count()
+            .get_result(&mut *t.env.conn())
+            .unwrap();
+        assert_eq!(count, 2);
+
+        t.stop().await;
+    }
+
+    #[tokio::test]
+    async fn test_add_and_buy_by_user() {
+        let t = TestBot::new().await;
+        t.add_resident(100);
+
+        let items = add_items_by_user(&t.bot, &t.env, UserId(100), &["tea"])
+            .await
+            .unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(t.api.take_methods(), ["sendMessage", "pinChatMessage"]);
+
+        let bought = mark_bought(&t.bot, &t.env, items[0].rowid, UserId(100))
+            .await
+            .unwrap();
+        assert!(bought.is_ok());
+        t.api.take_calls();
+
+        let again = mark_bought(&t.bot, &t.env, items[0].rowid, UserId(100))
+            .await
+            .unwrap();
+        assert!(matches!(again, Err(BuyError::AlreadyBought)));
+        let missing =
+            mark_bought(&t.bot, &t.env, 1000, UserId(100)).await.unwrap();
+        assert!(matches!(missing, Err(BuyError::NotFound)));
+
+        t.stop().await;
+    }
 
     #[test]
     fn test_subnumerate() {
@@ -15,22 +15,44 @@ use teloxide::types::{
     MessageId, PollType, ReplyMarkup, User,
 };
 
+use super::BotModule;
 use crate::common::{
     format_user, format_users, is_resident, BotEnv, UpdateHandler,
 };
-use crate::db::DbUserId;
+use crate::db::{DbConnection, DbUserId};
+use crate::i18n::{callback_lang, chat_lang, msg_lang, tr, Lang};
 use crate::utils::{format_to, BotExt, ResultExt, Sqlizer};
 use crate::{models, schema};
 
-pub fn message_handler() -> UpdateHandler {
-    dptree::filter_map(filter_polls).endpoint(handle_message)
+pub struct Module;
+
+impl BotModule for Module {
+    fn name(&self) -> &'static str {
+        "polls"
+    }
+
+    fn message_handler(&self) -> Option<UpdateHandler> {
+        Some(message_handler())
+    }
+
+    fn callback_handler(&self) -> Option<UpdateHandler> {
+        Some(callback_handler())
+    }
+
+    fn update_handler(&self) -> Option<UpdateHandler> {
+        Some(poll_answer_handler())
+    }
 }
 
-pub fn poll_answer_handler() -> UpdateHandler {
+fn message_handler() -> UpdateHandler {
+    dptree::filter_map_async(filter_polls).endpoint(handle_message)
+}
+
+fn poll_answer_handler() -> UpdateHandler {
     Update::filter_poll_answer().endpoint(handle_poll_answer)
 }
 
-pub fn callback_handler() -> UpdateHandler {
+fn callback_handler() -> UpdateHandler {
     dptree::filter_map(filter_callbacks).endpoint(handle_callback)
 }
 
@@ -41,13 +63,20 @@ enum PollKind {
     Forward(String),
 }
 
-fn filter_polls(me: Me, env: Arc<BotEnv>, msg: Message) -> Option<PollKind> {
+async fn filter_polls(
+    me: Me,
+    env: Arc<BotEnv>,
+    msg: Message,
+) -> Option<PollKind> {
     let poll = msg.poll()?;
     let from = msg.from.as_ref()?;
+    let from_id = from.id;
+    let resident = env.db.run(move |conn| is_resident(conn, from_id)).await;
     match msg.forward() {
         #[allow(clippy::nonminimal_bool)]
         None if poll.question.starts_with('!') => {
-            Some(match check_new_poll_requirements(&env, from, poll) {
+            let lang = msg_lang(&env, &msg).await;
+            Some(match check_new_poll_requirements(resident, poll, lang) {
                 Ok(()) => PollKind::New {
                     poll: Box::new(poll.clone()),
                     creator: from.clone(),
@@ -57,10 +86,7 @@ fn filter_polls(me: Me, env: Arc<BotEnv>, msg: Message) -> Option<PollKind> {
         }
         Some(Forward {
             from: ForwardedFrom::User(User { id, .. }), ..
-        }) if id == &me.user.id
-            && msg.chat.is_private()
-            && is_resident(&mut env.conn(), from) =>
-        {
+        }) if id == &me.user.id && msg.chat.is_private() && resident => {
             Some(PollKind::Forward(poll.id.clone()))
         }
         _ => None,
@@ -68,24 +94,27 @@ fn filter_polls(me: Me, env: Arc<BotEnv>, msg: Message) -> Option<PollKind> {
 }
 
 fn check_new_poll_requirements(
-    env: &BotEnv,
-    from: &User,
+    resident: bool,
     poll: &Poll,
+    lang: Lang,
 ) -> Result<(), String> {
     let mut diag_ok = true;
     let mut diag_text = String::new();
-    let mut check = |ok, text| {
+    let mut check = |ok, text: String| {
         diag_ok &= ok;
         format_to!(diag_text, "{} {}\n", if ok { "✅" } else { "❌" }, text);
     };
-    check(poll.total_voter_count == 0, "no votes (aka new poll)");
-    check(!poll.is_closed, "not closed already");
-    check(!poll.is_anonymous, "not anonymous");
+    check(poll.total_voter_count == 0, tr!(lang, "poll-req-new"));
+    check(!poll.is_closed, tr!(lang, "poll-req-not-closed"));
+    check(!poll.is_anonymous, tr!(lang, "poll-req-not-anonymous"));
     // Bots can't obtain information from quiz polls, so we can't track them
     // properly.
-    check(poll.poll_type == PollType::Regular, "regular poll, not quiz");
-    check(is_resident(&mut env.conn(), from), "created by resident");
-    diag_ok.then_some(()).ok_or(diag_text)
+    check(poll.poll_type == PollType::Regular, tr!(lang, "poll-req-regular"));
+    check(resident, tr!(lang, "poll-req-resident"));
+    if diag_ok {
+        return Ok(());
+    }
+    Err(tr!(lang, "poll-requirements-failed", diag = diag_text))
 }
 
 async fn handle_message(
@@ -98,16 +127,8 @@ async fn handle_message(
         PollKind::New { poll, creator } => {
             intercept_new_poll(bot, msg, &poll, creator, env).await
         }
-        PollKind::FailedDiag(chat_id, msg_id, diag_text) => {
-            bot.send_message(
-                chat_id,
-                format!(
-                    "It seems you tried to create a bot-tracked poll, \
-                    but it doesn't meet all of the requirements:\n{diag_text}",
-                ),
-            )
-            .reply_to_message_id(msg_id)
-            .await?;
+        PollKind::FailedDiag(chat_id, msg_id, text) => {
+            bot.send_message(chat_id, text).reply_to_message_id(msg_id).await?;
             Ok(())
         }
         PollKind::Forward(poll_id) => {
@@ -151,6 +172,7 @@ async fn intercept_new_poll(
         anyhow::bail!("Failed to delete poll message: {e}");
     }
 
+    let lang = chat_lang(&env, msg.chat.id).await;
     let non_voters = db_find_non_voters(&mut env.conn(), &[]);
 
     let creator_info = models::TgUser {
@@ -163,11 +185,18 @@ async fn intercept_new_poll(
     let poll_info = bot
         .reply_message(
             &msg,
-            poll_text((creator.id.into(), Some(creator_info)), &non_voters?, 0),
+            poll_text(
+                (creator.id.into(), Some(creator_info)),
+                &non_voters?,
+                0,
+                lang,
+            ),
         )
         .reply_to_message_id(new_poll.id)
         .parse_mode(teloxide::types::ParseMode::Html)
-        .reply_markup(ReplyMarkup::InlineKeyboard(make_keyboard(&poll.id)))
+        .reply_markup(ReplyMarkup::InlineKeyboard(make_keyboard(
+            &poll.id, lang,
+        )))
         .disable_web_page_preview(true)
         .await?;
 
@@ -198,11 +227,12 @@ async fn hande_poll_forward(
         Ok(Some(non_voters))
     })?;
 
+    let lang = msg_lang(&env, &msg).await;
     let mut text = String::new();
 
     if let Some(non_voters) = poll_results {
         if non_voters.is_empty() {
-            write!(text, "Everyone voted!").unwrap();
+            text.push_str(&tr!(lang, "poll-everyone-voted"));
         } else {
             non_voters
                 .iter()
@@ -213,7 +243,7 @@ async fn hande_poll_forward(
                 });
         }
     } else {
-        write!(text, "Unknown poll").unwrap();
+        text.push_str(&tr!(lang, "poll-unknown"));
     }
 
     bot.reply_message(&msg, text).disable_web_page_preview(true).await?;
@@ -272,13 +302,14 @@ async fn handle_poll_answer(
         return Ok(());
     };
 
+    let lang = chat_lang(&env, info_chat_id.into()).await;
     bot.edit_message_text(
         info_chat_id,
         info_message_id.into(),
-        poll_text(creator, &non_voters, total_voters),
+        poll_text(creator, &non_voters, total_voters, lang),
     )
     .parse_mode(teloxide::types::ParseMode::Html)
-    .reply_markup(make_keyboard(&poll_answer.poll_id))
+    .reply_markup(make_keyboard(&poll_answer.poll_id, lang))
     .disable_web_page_preview(true)
     .await?;
 
@@ -316,15 +347,18 @@ async fn handle_callback(
     stop: StopPollQuery,
     callback: CallbackQuery,
 ) -> Result<()> {
+    let lang = callback_lang(&env, &callback).await;
     let db_poll = db_find_poll(&mut env.conn(), &stop.poll_id)?;
     let Some((db_poll, _)) = db_poll else {
-        bot.answer_callback_query(&callback.id).text("Poll not found.").await?;
+        bot.answer_callback_query(&callback.id)
+            .text(tr!(lang, "poll-not-found"))
+            .await?;
         return Ok(());
     };
 
     if callback.from.id != UserId::from(db_poll.creator_id) {
         bot.answer_callback_query(&callback.id)
-            .text("You are not the creator of this poll.")
+            .text(tr!(lang, "poll-not-creator"))
             .await?;
         return Ok(());
     }
@@ -334,23 +368,19 @@ async fn handle_callback(
         callback.message.as_ref().and_then(|m| m.reply_to_message());
     let Some(poll_message) = poll_message else {
         bot.answer_callback_query(&callback.id)
-            .text("Poll message not found.")
+            .text(tr!(lang, "poll-message-not-found"))
             .await?;
         return Ok(());
     };
 
+    let keyboard_lang = chat_lang(&env, db_poll.info_chat_id.into()).await;
     let reply_markup = match stop.action {
         Action::Stop => {
             bot.answer_callback_query(&callback.id)
-                .text(
-                    // Based on the original Telegram client message
-                    "If you stop this poll now, \
-                    nobody will be able to vote in anymore. \
-                    This action cannot be undone.",
-                )
+                .text(tr!(lang, "poll-stop-warning"))
                 .show_alert(true)
                 .await?;
-            Some(make_keyboard_confirmation(&stop.poll_id))
+            Some(make_keyboard_confirmation(&stop.poll_id, keyboard_lang))
         }
         Action::Confirm => {
             bot.answer_callback_query(&callback.id).await?;
@@ -359,7 +389,7 @@ async fn handle_callback(
         }
         Action::Cancel => {
             bot.answer_callback_query(&callback.id).await?;
-            Some(make_keyboard(&stop.poll_id))
+            Some(make_keyboard(&stop.poll_id, keyboard_lang))
         }
     };
 
@@ -377,54 +407,56 @@ fn poll_text(
     creator: (DbUserId, Option<models::TgUser>),
     non_voters: &[(DbUserId, Option<models::TgUser>)],
     total_voters: usize,
+    lang: Lang,
 ) -> String {
-    let mut text = String::new();
-
-    text.push_str("Poll by ");
-    format_user(&mut text, creator.0, &creator.1, true);
-    text.push_str(". ");
+    let mut name = String::new();
+    format_user(&mut name, creator.0, &creator.1, true);
+    let mut text = tr!(lang, "poll-by", creator = name);
+    text.push(' ');
 
     if non_voters.is_empty() {
-        text.push_str("Everyone voted!");
+        text.push_str(&tr!(lang, "poll-everyone-voted"));
     } else {
-        write!(
-            text,
-            "Voted {} user{}, pending vote {} user{}: ",
-            total_voters,
-            if total_voters == 1 { "" } else { "s" },
-            non_voters.len(),
-            if non_voters.len() == 1 { "" } else { "s" },
-        )
-        .unwrap();
-        format_users(&mut text, non_voters.iter().map(|(id, u)| (*id, u)));
-        text.push_str(".\n");
+        let mut users = String::new();
+        format_users(&mut users, non_voters.iter().map(|(id, u)| (*id, u)));
+        text.push_str(&tr!(
+            lang,
+            "poll-progress",
+            voted = total_voters,
+            pending = non_voters.len(),
+            users = users,
+        ));
+        text.push('\n');
     }
 
     text
 }
 
-fn make_keyboard(poll_id: &str) -> InlineKeyboardMarkup {
+fn make_keyboard(poll_id: &str, lang: Lang) -> InlineKeyboardMarkup {
     InlineKeyboardMarkup::new(vec![vec![InlineKeyboardButton::callback(
-        "Stop poll",
+        tr!(lang, "poll-stop"),
         format!("p:stop:{poll_id}"),
     )]])
 }
 
-fn make_keyboard_confirmation(poll_id: &str) -> InlineKeyboardMarkup {
+fn make_keyboard_confirmation(
+    poll_id: &str,
+    lang: Lang,
+) -> InlineKeyboardMarkup {
     InlineKeyboardMarkup::new(vec![vec![
         InlineKeyboardButton::callback(
-            "Cancel (do not stop)",
+            tr!(lang, "poll-stop-cancel"),
             format!("p:cancel:{poll_id}"),
         ),
         InlineKeyboardButton::callback(
-            "Confirm (stop poll)",
+            tr!(lang, "poll-stop-confirm"),
             format!("p:confirm:{poll_id}"),
         ),
     ]])
 }
 
 fn db_find_poll(
-    conn: &mut SqliteConnection,
+    conn: &mut DbConnection,
     poll_id: &str,
 ) -> Result<
     Option<(models::TrackedPoll, Option<models::TgUser>)>,
@@ -441,7 +473,7 @@ fn db_find_poll(
 }
 
 fn db_find_non_voters(
-    conn: &mut SqliteConnection,
+    conn: &mut DbConnection,
     voted_users: &[DbUserId],
 ) -> Result<Vec<(DbUserId, Option<models::TgUser>)>, diesel::result::Error> {
     // TODO: filter only residents at the moment of poll creation
@@ -458,3 +490,53 @@ fn db_find_non_voters(
         ))
         .load(conn)
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::{
+        message_update, poll_answer_update, poll_message, TestBot, CHAT,
+    };
+
+    #[tokio::test]
+    async fn test_tracked_poll() {
+        let t = TestBot::new().await;
+        t.add_resident(100);
+        t.add_resident(200);
+
+        t.dispatch(message_update(poll_message(
+            CHAT,
+            100,
+            "!Pizza?",
+            &["Yes", "No"],
+        )))
+        .await
+        .unwrap();
+        let calls = t.api.take_calls();
+        assert_eq!(calls[0].method, "sendPoll");
+        assert_eq!(calls[0].body["question"], "!Pizza?");
+        assert_eq!(calls[1].method, "deleteMessage");
+        assert_eq!(calls[2].method, "sendMessage");
+        assert_eq!(calls.len(), 3);
+
+        // The fake API numbers polls sent by the bot starting from 1
+        t.dispatch(poll_answer_update("1", 200, &[0])).await.unwrap();
+        let calls = t.api.take_calls();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].method, "editMessageText");
+        let text = calls[0].body["text"].as_str().unwrap();
+        assert!(text.contains("Voted 1 user, pending vote 1 user"), "{text}");
+
+        t.dispatch(poll_answer_update("1", 100, &[1])).await.unwrap();
+        let calls = t.api.take_calls();
+        assert!(calls[0].body["text"]
+            .as_str()
+            .unwrap()
+            .ends_with("Everyone voted!"));
+
+        // Answers to untracked polls are ignored
+        t.dispatch(poll_answer_update("2", 100, &[0])).await.unwrap();
+        assert!(t.api.take_calls().is_empty());
+
+        t.stop().await;
+    }
+}
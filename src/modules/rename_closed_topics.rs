@@ -12,15 +12,29 @@ use regex::Regex;
 use teloxide::prelude::*;
 use teloxide::types::MessageKind;
 
-use crate::common::BotEnv;
+use super::BotModule;
+use crate::common::{BotEnv, UpdateHandler};
 use crate::db::{DbChatId, DbThreadId};
+use crate::utils::inspect_err;
 
 lazy_static::lazy_static! {
     static ref CLOSED_TOPIC_REGEX: Regex =
         Regex::new(r"^\[[×xXхХ]\] *").unwrap();
 }
 
-pub async fn inspect_message<'a>(
+pub struct Module;
+
+impl BotModule for Module {
+    fn name(&self) -> &'static str {
+        "rename_closed_topics"
+    }
+
+    fn inspect_message(&self) -> Option<UpdateHandler> {
+        Some(inspect_err(inspect_message))
+    }
+}
+
+async fn inspect_message<'a>(
     bot: Bot,
     env: Arc<BotEnv>,
     msg: Message,
@@ -1,5 +1,6 @@
 //! Add or remove users from residency when they join or leave residential
-//! chats.
+//! chats. Command lists of the users are refreshed accordingly, see
+//! [`super::command_menu`].
 //!
 //! **Scope**: chats listed in [`telegram.chats.residential`] config option.
 //!
@@ -13,35 +14,61 @@ use diesel::prelude::*;
 use teloxide::prelude::*;
 use teloxide::types::{Chat, ChatKind, ChatPublic, UpdateKind, User};
 
-use crate::common::BotEnv;
-use crate::db::{DbChatId, DbUserId};
+use super::{command_menu, BotModule, Registry};
+use crate::common::{BotEnv, UpdateHandler};
+use crate::db::{DbChatId, DbConnection, DbUserId};
 use crate::schema;
-use crate::utils::ResultExt;
+use crate::utils::{inspect_err, ResultExt};
 
 struct Filtered<'a> {
     cm: &'a ChatMemberUpdated,
     is_joined: bool,
 }
 
+pub struct Module;
+
+impl BotModule for Module {
+    fn name(&self) -> &'static str {
+        "resident_tracker"
+    }
+
+    fn inspect_update(&self) -> Option<UpdateHandler> {
+        Some(inspect_err(inspect_update))
+    }
+}
+
 /// Wrapper around [scrape].
-pub fn inspect_update(env: Arc<BotEnv>, upd: Update) {
-    let residential_chats = env.config.telegram.chats.residential.as_slice();
-    let Some(filtered) = filter(&upd, residential_chats) else { return };
-    env.transaction(|conn| {
-        handle_update_transaction(conn, residential_chats, filtered)
-    })
-    .log_error(module_path!(), "resident_tracker::handle_update");
+async fn inspect_update(
+    bot: Bot,
+    env: Arc<BotEnv>,
+    registry: Arc<Registry>,
+    upd: Update,
+) -> anyhow::Result<()> {
+    let config = env.config();
+    let residential_chats = config.telegram.chats.residential.as_slice();
+    let Some(filtered) = filter(&upd, residential_chats) else { return Ok(()) };
+    let user_id = filtered.cm.new_chat_member.user.id;
+    let changed = env
+        .transaction(|conn| {
+            handle_update_transaction(conn, residential_chats, filtered)
+        })
+        .log_ok(module_path!(), "resident_tracker::handle_update");
+    if changed == Some(true) && !config.telegram.passive_mode {
+        command_menu::refresh_user(&bot, &env, &registry, user_id).await;
+    }
+    Ok(())
 }
 
 /// Scrape an update for residential chat joins/leaves and update the
 /// `residents` table accordingly.
 pub fn scrape(
-    conn: &mut SqliteConnection,
+    conn: &mut DbConnection,
     upd: &Update,
     residential_chats: &[ChatId],
 ) -> Result<(), diesel::result::Error> {
     let Some(filtered) = filter(upd, residential_chats) else { return Ok(()) };
-    handle_update_transaction(conn, residential_chats, filtered)
+    handle_update_transaction(conn, residential_chats, filtered)?;
+    Ok(())
 }
 
 fn filter<'a>(
@@ -72,11 +99,12 @@ fn filter<'a>(
     Some(Filtered { cm, is_joined })
 }
 
+/// Returns whether the residency of the user has changed.
 fn handle_update_transaction(
-    conn: &mut SqliteConnection,
+    conn: &mut DbConnection,
     residential_chats: &[ChatId],
     f: Filtered<'_>,
-) -> Result<(), diesel::result::Error> {
+) -> Result<bool, diesel::result::Error> {
     let user_id = DbUserId::from(f.cm.new_chat_member.user.id);
 
     let residential_chats =
@@ -116,6 +144,7 @@ fn handle_update_transaction(
                 .filter(r::end_date.is_null())
                 .set(r::end_date.eq(diesel::dsl::now))
                 .execute(conn)?;
+            Ok(true)
         }
         (false, true, true) => {
             // Add to residency
@@ -131,14 +160,13 @@ fn handle_update_transaction(
                     r::begin_date.eq(diesel::dsl::now),
                 ))
                 .execute(conn)?;
+            Ok(true)
         }
         // Do not make any unintuitive changes. E.g. if a non-resident left
         // a residential chat, do not add them to residency, even if they
         // are still seen in other residential chats.
-        _ => (),
+        _ => Ok(false),
     }
-
-    Ok(())
 }
 
 fn user_text(user: &User) -> String {
@@ -0,0 +1,234 @@
+//! Commands to grant and revoke named roles, see [`has_role`].
+//!
+//! [`has_role`]: crate::common::has_role
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use diesel::prelude::*;
+use macro_rules_attribute::derive;
+use teloxide::macros::BotCommands;
+use teloxide::prelude::*;
+use teloxide::types::ParseMode;
+use teloxide::utils::html::escape;
+
+use super::{command_menu, BotModule, ModuleCommands, Registry};
+use crate::common::{
+    filter_command, format_user, BotCommandsExt, BotEnv, UpdateHandler,
+};
+use crate::db::DbUserId;
+use crate::utils::{format_to, BotExt};
+use crate::{models, schema};
+
+const USAGE: &str = "Usage: <code>/grant_role ROLE USER</code> or \
+    <code>/revoke_role ROLE USER</code>, where USER is @username or a numeric \
+    id. Alternatively, reply to a message of the user with \
+    <code>/grant_role ROLE</code>.";
+
+#[derive(Clone, BotCommands, BotCommandsExt!)]
+#[command(rename_rule = "snake_case")]
+pub enum Commands {
+    #[command(description = "list users with roles.")]
+    #[custom(admin = true)]
+    Roles,
+
+    #[command(description = "grant a role to a user.")]
+    #[custom(admin = true)]
+    GrantRole(String),
+
+    #[command(description = "revoke a role from a user.")]
+    #[custom(admin = true)]
+    RevokeRole(String),
+}
+
+pub struct Module;
+
+impl BotModule for Module {
+    fn name(&self) -> &'static str {
+        "roles"
+    }
+
+    fn message_handler(&self) -> Option<UpdateHandler> {
+        Some(command_handler())
+    }
+
+    fn commands(&self) -> Option<ModuleCommands> {
+        Some(ModuleCommands::of::<Commands>())
+    }
+}
+
+fn command_handler() -> UpdateHandler {
+    filter_command::<Commands>().endpoint(cmd_roles)
+}
+
+async fn cmd_roles(
+    bot: Bot,
+    env: Arc<BotEnv>,
+    registry: Arc<Registry>,
+    msg: Message,
+    command: Commands,
+) -> Result<()> {
+    let (args, grant) = match command {
+        Commands::Roles => {
+            let text = list_roles(&env)?;
+            bot.reply_message(&msg, text).parse_mode(ParseMode::Html).await?;
+            return Ok(());
+        }
+        Commands::GrantRole(args) => (args, true),
+        Commands::RevokeRole(args) => (args, false),
+    };
+    let Some(from) = &msg.from else { return Ok(()) };
+
+    let mut args = args.split_whitespace();
+    let target = match (args.next(), args.next(), args.next()) {
+        (Some(role), None, None) => msg
+            .reply_to_message()
+            .and_then(|m| m.from.as_ref())
+            .map(|u| Ok((role, Some(DbUserId::from(u.id))))),
+        (Some(role), Some(user), None) => {
+            Some(find_user(&env, user).map(|id| (role, id)))
+        }
+        _ => None,
+    };
+    let (role, user_id) = match target.transpose()? {
+        Some((role, Some(user_id))) => (role, user_id),
+        Some((_, None)) => {
+            bot.reply_message(&msg, "Unknown user.").await?;
+            return Ok(());
+        }
+        None => {
+            bot.reply_message(&msg, USAGE).parse_mode(ParseMode::Html).await?;
+            return Ok(());
+        }
+    };
+
+    let changed = if grant {
+        diesel::insert_into(schema::user_roles::table)
+            .values(models::UserRole {
+                user_id,
+                role: role.to_string(),
+                granted_by: from.id.into(),
+                granted_at: chrono::Utc::now().naive_utc(),
+            })
+            .on_conflict_do_nothing()
+            .execute(&mut *env.conn())?
+    } else {
+        diesel::delete(schema::user_roles::table)
+            .filter(schema::user_roles::user_id.eq(user_id))
+            .filter(schema::user_roles::role.eq(role))
+            .execute(&mut *env.conn())?
+    };
+
+    let text = match (changed > 0, grant) {
+        (true, true) => "Role granted.",
+        (true, false) => "Role revoked.",
+        (false, true) => "The user already has this role.",
+        (false, false) => "The user doesn't have this role.",
+    };
+    bot.reply_message(&msg, text).await?;
+
+    if changed > 0 {
+        command_menu::refresh_user(&bot, &env, &registry, user_id.into()).await;
+    }
+    Ok(())
+}
+
+/// Find a user by @username or numeric id.
+fn find_user(env: &BotEnv, user: &str) -> Result<Option<DbUserId>> {
+    if let Some(username) = user.strip_prefix('@') {
+        Ok(schema::tg_users::table
+            .filter(schema::tg_users::username.eq(username))
+            .select(schema::tg_users::id)
+            .first(&mut *env.conn())
+            .optional()?)
+    } else {
+        Ok(user
+            .parse::<u64>()
+            .ok()
+            .filter(|&id| i64::try_from(id).is_ok())
+            .map(|id| UserId(id).into()))
+    }
+}
+
+fn list_roles(env: &BotEnv) -> Result<String> {
+    let roles: Vec<(models::UserRole, Option<models::TgUser>)> =
+        schema::user_roles::table
+            .left_join(
+                schema::tg_users::table
+                    .on(schema::tg_users::id.eq(schema::user_roles::user_id)),
+            )
+            .order((schema::user_roles::role, schema::user_roles::user_id))
+            .select((
+                models::UserRole::as_select(),
+                Option::<models::TgUser>::as_select(),
+            ))
+            .load(&mut *env.conn())?;
+    if roles.is_empty() {
+        return Ok(format!("No roles are granted.\n\n{USAGE}"));
+    }
+    let mut text = String::new();
+    let mut last_role = None;
+    for (role, user) in &roles {
+        if last_role != Some(&role.role) {
+            format_to!(text, "\n<b>{}</b>:\n", escape(&role.role));
+            last_role = Some(&role.role);
+        }
+        text.push_str("• ");
+        format_user(&mut text, role.user_id, user, false);
+        format_to!(text, ", since {}\n", role.granted_at.format("%Y-%m-%d"));
+    }
+    let mut text = text.trim_start().to_string();
+    text.push_str("\nRoles from LDAP groups are not listed.");
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::has_role;
+    use crate::testing::{message, message_update, TestBot};
+
+    #[tokio::test]
+    async fn test_roles() {
+        let t = TestBot::new().await;
+        let admin = crate::testing::test_config().telegram.admins[0];
+        let has = || has_role(&t.env, UserId(100), "plumber");
+
+        t.dispatch(message_update(message(
+            admin.0.try_into().unwrap(),
+            None,
+            admin.0,
+            "/grant_role plumber 100",
+        )))
+        .await
+        .unwrap();
+        let calls = t.api.take_calls();
+        assert_eq!(calls[0].body["text"], "Role granted.");
+        assert!(has().await.unwrap());
+
+        // Only admins can grant roles
+        t.dispatch(message_update(message(
+            200,
+            None,
+            200,
+            "/grant_role plumber 200",
+        )))
+        .await
+        .unwrap();
+        t.api.take_calls();
+        assert!(!has_role(&t.env, UserId(200), "plumber").await.unwrap());
+
+        t.dispatch(message_update(message(
+            admin.0.try_into().unwrap(),
+            None,
+            admin.0,
+            "/revoke_role plumber 100",
+        )))
+        .await
+        .unwrap();
+        assert_eq!(t.api.take_calls()[0].body["text"], "Role revoked.");
+        assert!(!has().await.unwrap());
+
+        t.stop().await;
+    }
+}
@@ -0,0 +1,314 @@
+//! `/search` in the message archive, see `telegram.chats.archive`, and
+//! `/forget` to remove a message from it.
+//!
+//! Telegram doesn't notify bots about deleted messages, so `/forget` is the
+//! only way to remove a message, except for editing it.
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Text};
+use itertools::Itertools as _;
+use macro_rules_attribute::derive;
+use teloxide::macros::BotCommands;
+use teloxide::prelude::*;
+use teloxide::types::ParseMode;
+use teloxide::utils::html::escape;
+
+use super::{BotModule, ModuleCommands};
+use crate::common::{filter_command, BotCommandsExt, BotEnv, UpdateHandler};
+use crate::db::{DbChatId, DbConnection, DbMessageId, DbUserId};
+use crate::i18n::{msg_lang, tr};
+use crate::schema;
+use crate::utils::{write_message_link, BotExt};
+
+/// Maximum number of hits in a reply.
+const MAX_HITS: i64 = 10;
+
+/// Hits are shown as the beginning of the message text, up to this many
+/// characters.
+const SNIPPET_LENGTH: usize = 80;
+
+#[cfg(not(feature = "postgres"))]
+const SEARCH_TG_MESSAGES: &str = include_str!("../sql/search_tg_messages.sql");
+#[cfg(feature = "postgres")]
+const SEARCH_TG_MESSAGES: &str =
+    include_str!("../sql/search_tg_messages_pg.sql");
+
+#[derive(Clone, BotCommands, BotCommandsExt!)]
+#[command(rename_rule = "snake_case")]
+pub enum Commands {
+    #[command(description = "search messages in chats you are in.")]
+    #[custom(in_group = false, resident = true)]
+    Search(String),
+
+    #[command(description = "remove the replied message from the archive.")]
+    #[custom(in_private = false)]
+    Forget,
+}
+
+pub struct Module;
+
+impl BotModule for Module {
+    fn name(&self) -> &'static str {
+        "search"
+    }
+
+    fn message_handler(&self) -> Option<UpdateHandler> {
+        Some(command_handler())
+    }
+
+    fn commands(&self) -> Option<ModuleCommands> {
+        Some(ModuleCommands::of::<Commands>())
+    }
+}
+
+fn command_handler() -> UpdateHandler {
+    filter_command::<Commands>().endpoint(cmd_search)
+}
+
+async fn cmd_search(
+    bot: Bot,
+    env: Arc<BotEnv>,
+    msg: Message,
+    command: Commands,
+) -> Result<()> {
+    let Some(from) = &msg.from else { return Ok(()) };
+    let lang = msg_lang(&env, &msg).await;
+    let query = match command {
+        Commands::Search(query) => query,
+        Commands::Forget => return cmd_forget(bot, env, msg).await,
+    };
+    let Some(query) = match_query(&query) else {
+        bot.reply_message(&msg, tr!(lang, "search-usage"))
+            .parse_mode(ParseMode::Html)
+            .await?;
+        return Ok(());
+    };
+
+    let user_id = DbUserId::from(from.id);
+    let hits =
+        env.db.run(move |conn| search(conn, user_id, &query, MAX_HITS)).await?;
+    if hits.is_empty() {
+        bot.reply_message(&msg, tr!(lang, "search-no-hits")).await?;
+        return Ok(());
+    }
+
+    let mut text = tr!(lang, "search-hits");
+    text.push('\n');
+    for hit in &hits {
+        text.push_str("• ");
+        write_message_link(&mut text, hit.chat_id, hit.message_id);
+        text.push_str(&escape(&snippet(&hit.text)));
+        text.push_str("</a>\n");
+    }
+    bot.reply_message(&msg, text)
+        .parse_mode(ParseMode::Html)
+        .disable_web_page_preview(true)
+        .await?;
+    Ok(())
+}
+
+async fn cmd_forget(bot: Bot, env: Arc<BotEnv>, msg: Message) -> Result<()> {
+    use schema::tg_messages as m;
+
+    let Some(from) = &msg.from else { return Ok(()) };
+    let lang = msg_lang(&env, &msg).await;
+    let Some(target) = msg.reply_to_message() else {
+        bot.reply_message(&msg, tr!(lang, "forget-usage")).await?;
+        return Ok(());
+    };
+    let is_author = target.from.as_ref().is_some_and(|u| u.id == from.id);
+    if !is_author && !env.config().telegram.admins.contains(&from.id) {
+        bot.reply_message(&msg, tr!(lang, "forget-not-author")).await?;
+        return Ok(());
+    }
+
+    let (chat_id, message_id) = (target.chat.id, target.id);
+    let deleted = env
+        .db
+        .run(move |conn| {
+            diesel::delete(m::table)
+                .filter(m::chat_id.eq(DbChatId::from(chat_id)))
+                .filter(m::message_id.eq(DbMessageId::from(message_id)))
+                .execute(conn)
+        })
+        .await?;
+    let text = if deleted == 0 {
+        tr!(lang, "forget-not-archived")
+    } else {
+        tr!(lang, "forget-done")
+    };
+    bot.reply_message(&msg, text).await?;
+    Ok(())
+}
+
+#[derive(QueryableByName)]
+struct Hit {
+    #[diesel(sql_type = BigInt)]
+    chat_id: DbChatId,
+    #[diesel(sql_type = diesel::sql_types::Integer)]
+    message_id: DbMessageId,
+    #[diesel(sql_type = Text)]
+    text: String,
+}
+
+/// Best matches for a query built by [`match_query`], only from chats where
+/// the user is a member.
+fn search(
+    conn: &mut DbConnection,
+    user_id: DbUserId,
+    query: &str,
+    limit: i64,
+) -> QueryResult<Vec<Hit>> {
+    diesel::sql_query(SEARCH_TG_MESSAGES)
+        .bind::<BigInt, _>(user_id)
+        .bind::<Text, _>(query)
+        .bind::<BigInt, _>(limit)
+        .load(conn)
+}
+
+/// Build an FTS5 query matching messages that contain words starting with
+/// each of the words in the user input. FTS5 syntax in the input is not
+/// interpreted.
+#[cfg(not(feature = "postgres"))]
+fn match_query(input: &str) -> Option<String> {
+    let words = input.split_whitespace().collect_vec();
+    if words.is_empty() {
+        return None;
+    }
+    Some(
+        words
+            .iter()
+            .map(|w| format!("\"{}\"*", w.replace('"', "\"\"")))
+            .join(" "),
+    )
+}
+
+/// Build a tsquery matching messages that contain words starting with each of
+/// the words in the user input. Tsquery syntax in the input is not
+/// interpreted.
+#[cfg(feature = "postgres")]
+fn match_query(input: &str) -> Option<String> {
+    let words = input.split_whitespace().collect_vec();
+    if words.is_empty() {
+        return None;
+    }
+    Some(
+        words
+            .iter()
+            .map(|w| {
+                format!("'{}':*", w.replace('\\', "\\\\").replace('\'', "''"))
+            })
+            .join(" & "),
+    )
+}
+
+/// The beginning of the text on a single line.
+fn snippet(text: &str) -> String {
+    let text = text.split_whitespace().join(" ");
+    match text.char_indices().nth(SNIPPET_LENGTH) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use teloxide::types::ChatId;
+
+    use crate::testing::{
+        edited_message_update, message, message_update, TestBot, CHAT,
+    };
+
+    /// A chat that is not in `telegram.chats.archive`.
+    const OTHER_CHAT: i64 = -1_001_234_567_891;
+
+    #[tokio::test]
+    async fn test_search() {
+        let t = TestBot::new().await;
+        t.add_resident(100);
+        assert_eq!(t.env.config().telegram.chats.archive, [ChatId(CHAT)]);
+
+        let hello = message(CHAT, None, 100, "Hello, world!");
+        t.dispatch(message_update(hello.clone())).await.unwrap();
+        for (chat, user, text) in [
+            (CHAT, 101, "The world is not enough"),
+            (CHAT, 101, "/search world"),
+            (OTHER_CHAT, 100, "Another world"),
+        ] {
+            t.dispatch(message_update(message(chat, None, user, text)))
+                .await
+                .unwrap();
+        }
+        t.api.take_calls();
+
+        let search = |text: &str| message(100, None, 100, text);
+        t.dispatch(message_update(search("/search wor"))).await.unwrap();
+        let calls = t.api.take_calls();
+        let reply = calls[0].body["text"].as_str().unwrap();
+        assert!(reply.contains("Hello, world!"), "{reply}");
+        assert!(reply.contains("The world is not enough"), "{reply}");
+        assert!(!reply.contains("Another"), "{reply}");
+        assert!(!reply.contains("/search"), "{reply}");
+
+        // Residents who are not members of the chat see nothing.
+        t.add_resident(102);
+        t.dispatch(message_update(message(102, None, 102, "/search world")))
+            .await
+            .unwrap();
+        let calls = t.api.take_calls();
+        assert_eq!(calls[0].body["text"], "Nothing found.");
+
+        // Edits replace the text.
+        let mut edited = hello.clone();
+        edited["text"] = "Goodbye".into();
+        t.dispatch(edited_message_update(edited)).await.unwrap();
+        t.dispatch(message_update(search("/search hello"))).await.unwrap();
+        let calls = t.api.take_calls();
+        assert_eq!(calls[0].body["text"], "Nothing found.");
+
+        // Only the author can forget a message.
+        let mut forget = message(CHAT, None, 101, "/forget");
+        forget["reply_to_message"] = hello.clone();
+        t.dispatch(message_update(forget)).await.unwrap();
+        let mut forget = message(CHAT, None, 100, "/forget");
+        forget["reply_to_message"] = hello;
+        t.dispatch(message_update(forget)).await.unwrap();
+        t.dispatch(message_update(search("/search goodbye"))).await.unwrap();
+        let replies = t
+            .api
+            .take_calls()
+            .iter()
+            .map(|c| c.body["text"].as_str().unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            replies,
+            [
+                "Only the author of the message or a bot admin can do this.",
+                "The message is removed from the archive.",
+                "Nothing found.",
+            ]
+        );
+
+        t.stop().await;
+    }
+
+    #[test]
+    fn test_match_query() {
+        assert_eq!(super::match_query("  "), None);
+        #[cfg(not(feature = "postgres"))]
+        assert_eq!(
+            super::match_query("a \"b\" NEAR"),
+            Some("\"a\"* \"\"\"b\"\"\"* \"NEAR\"*".to_string()),
+        );
+    }
+
+    #[test]
+    fn test_snippet() {
+        assert_eq!(super::snippet("a\n b"), "a b");
+        let long = "x".repeat(100);
+        assert_eq!(super::snippet(&long), format!("{}…", "x".repeat(80)));
+    }
+}
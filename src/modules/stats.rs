@@ -0,0 +1,473 @@
+//! `/stats` command showing chat activity: messages per chat and topic, top
+//! posters, active hours and new topics. Messages are counted by
+//! [`super::tg_scraper`]; the same stats are served by the HTTP API.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use anyhow::Result;
+use chrono::{Datelike as _, NaiveDateTime, Timelike as _, Utc};
+use diesel::dsl;
+use diesel::prelude::*;
+use itertools::Itertools as _;
+use macro_rules_attribute::derive;
+use teloxide::macros::BotCommands;
+use teloxide::prelude::*;
+use teloxide::types::{ParseMode, ThreadId};
+use teloxide::utils::html::escape;
+
+use super::{BotModule, ModuleCommands};
+use crate::common::{filter_command, BotCommandsExt, BotEnv, UpdateHandler};
+use crate::db::{DbChatId, DbConnection, DbThreadId};
+use crate::i18n::{msg_lang, tr, Lang};
+use crate::utils::{format_to, parse_duration, BotExt, GENERAL_THREAD_ID};
+use crate::{models, schema};
+
+/// Period of `/stats` without an argument.
+const DEFAULT_PERIOD: chrono::Duration = chrono::Duration::days(30);
+
+/// Longest period of stats, to keep the queries and the reply reasonable.
+const MAX_PERIOD: chrono::Duration = chrono::Duration::days(366);
+
+/// Number of users shown in top posters.
+pub const TOP_POSTERS: i64 = 10;
+
+/// Number of topics shown per chat in `/stats`.
+const MAX_TOPICS: usize = 5;
+
+/// Levels of the active hours heatmap, from none to the busiest hour.
+const HEATMAP_LEVELS: [char; 9] = [' ', '▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+#[derive(Clone, BotCommands, BotCommandsExt!)]
+#[command(rename_rule = "snake_case")]
+pub enum Commands {
+    #[command(description = "show chat activity, e.g. /stats 7d.")]
+    #[custom(resident = true)]
+    Stats(String),
+}
+
+pub struct Module;
+
+impl BotModule for Module {
+    fn name(&self) -> &'static str {
+        "stats"
+    }
+
+    fn message_handler(&self) -> Option<UpdateHandler> {
+        Some(command_handler())
+    }
+
+    fn commands(&self) -> Option<ModuleCommands> {
+        Some(ModuleCommands::of::<Commands>())
+    }
+}
+
+fn command_handler() -> UpdateHandler {
+    filter_command::<Commands>().endpoint(cmd_stats)
+}
+
+/// All stats shown by `/stats`.
+struct Stats {
+    chats: Vec<models::DataChatStats>,
+    top_posters: Vec<models::DataPosterStats>,
+    active_hours: Vec<Vec<i64>>,
+    topic_growth: Vec<models::DataTopicGrowth>,
+}
+
+async fn cmd_stats(
+    bot: Bot,
+    env: Arc<BotEnv>,
+    msg: Message,
+    Commands::Stats(arg): Commands,
+) -> Result<()> {
+    let lang = msg_lang(&env, &msg).await;
+    let Some(since) = parse_since(arg.trim()) else {
+        bot.reply_message(&msg, tr!(lang, "stats-usage"))
+            .parse_mode(ParseMode::Html)
+            .await?;
+        return Ok(());
+    };
+
+    // In a group, only the stats of this group. In private, of all
+    // residential chats.
+    let chats = if msg.chat.is_private() {
+        residential_chats(&env)
+    } else {
+        vec![msg.chat.id.into()]
+    };
+    let stats = env
+        .db
+        .run(move |conn| -> QueryResult<_> {
+            Ok(Stats {
+                chats: chat_stats(conn, &chats, since)?,
+                top_posters: top_posters(conn, &chats, since, TOP_POSTERS)?,
+                active_hours: active_hours(conn, &chats, since)?,
+                topic_growth: topic_growth(conn, &chats, since)?,
+            })
+        })
+        .await?;
+
+    bot.reply_message(&msg, render(&stats, since, lang))
+        .parse_mode(ParseMode::Html)
+        .await?;
+    Ok(())
+}
+
+/// Parse a period like `7d` or `2w` into its start time. An empty string
+/// means [`DEFAULT_PERIOD`], periods longer than [`MAX_PERIOD`] are rejected.
+pub fn parse_since(input: &str) -> Option<NaiveDateTime> {
+    let period = parse_period(input)?;
+    Utc::now().naive_utc().checked_sub_signed(period)
+}
+
+fn parse_period(input: &str) -> Option<chrono::Duration> {
+    if input.is_empty() {
+        return Some(DEFAULT_PERIOD);
+    }
+    chrono::Duration::from_std(parse_duration(input)?)
+        .ok()
+        .filter(|period| *period <= MAX_PERIOD)
+}
+
+/// Chats shown in the stats when not asked from a group.
+pub fn residential_chats(env: &BotEnv) -> Vec<DbChatId> {
+    let chats = &env.config().telegram.chats.residential;
+    chats.iter().map(|&c| c.into()).collect()
+}
+
+/// Messages per chat and topic since `since`, the most active first.
+pub fn chat_stats(
+    conn: &mut DbConnection,
+    chats: &[DbChatId],
+    since: NaiveDateTime,
+) -> QueryResult<Vec<models::DataChatStats>> {
+    use schema::{tg_chat_topics as t, tg_chats as c, tg_message_stats as s};
+
+    let counts: Vec<(DbChatId, DbThreadId, Option<i64>)> = s::table
+        .filter(s::chat_id.eq_any(chats))
+        .filter(s::hour.ge(since))
+        .group_by((s::chat_id, s::thread_id))
+        .select((s::chat_id, s::thread_id, dsl::sum(s::messages)))
+        .load(conn)?;
+    let titles: HashMap<DbChatId, Option<String>> = c::table
+        .filter(c::id.eq_any(chats))
+        .select((c::id, c::title))
+        .load(conn)?
+        .into_iter()
+        .collect();
+    let mut topic_names: HashMap<(DbChatId, DbThreadId), Option<String>> =
+        t::table
+            .filter(t::chat_id.eq_any(chats))
+            .select((t::chat_id, t::topic_id, t::name))
+            .load::<(DbChatId, DbThreadId, Option<String>)>(conn)?
+            .into_iter()
+            .map(|(chat_id, topic_id, name)| ((chat_id, topic_id), name))
+            .collect();
+
+    let mut result = counts
+        .into_iter()
+        .map(|(chat_id, thread_id, messages)| {
+            (chat_id, (thread_id, messages.unwrap_or(0)))
+        })
+        .into_group_map()
+        .into_iter()
+        .map(|(chat_id, topics)| {
+            let mut topics = topics
+                .into_iter()
+                .map(|(thread_id, messages)| models::DataTopicStats {
+                    thread_id,
+                    name: topic_names.remove(&(chat_id, thread_id)).flatten(),
+                    messages,
+                })
+                .collect_vec();
+            topics.sort_by_key(|t| (-t.messages, t.thread_id));
+            models::DataChatStats {
+                chat_id,
+                title: titles.get(&chat_id).cloned().flatten(),
+                messages: topics.iter().map(|t| t.messages).sum(),
+                topics,
+            }
+        })
+        .collect_vec();
+    result.sort_by_key(|c| (-c.messages, c.chat_id));
+    Ok(result)
+}
+
+/// Users who sent the most messages since `since`.
+pub fn top_posters(
+    conn: &mut DbConnection,
+    chats: &[DbChatId],
+    since: NaiveDateTime,
+    limit: i64,
+) -> QueryResult<Vec<models::DataPosterStats>> {
+    use schema::{tg_message_stats as s, tg_users as u};
+
+    let counts: Vec<(crate::db::DbUserId, Option<i64>)> = s::table
+        .filter(s::chat_id.eq_any(chats))
+        .filter(s::hour.ge(since))
+        .group_by(s::user_id)
+        .select((s::user_id, dsl::sum(s::messages)))
+        .order((dsl::sum(s::messages).desc(), s::user_id))
+        .limit(limit)
+        .load(conn)?;
+    let mut users: HashMap<_, models::TgUser> = u::table
+        .filter(u::id.eq_any(counts.iter().map(|(id, _)| *id)))
+        .load::<models::TgUser>(conn)?
+        .into_iter()
+        .map(|user| (user.id, user))
+        .collect();
+
+    Ok(counts
+        .into_iter()
+        .filter_map(|(id, messages)| {
+            let user = users.remove(&id)?;
+            Some(models::DataPosterStats {
+                id: id.into(),
+                username: user.username,
+                first_name: user.first_name,
+                last_name: user.last_name,
+                messages: messages.unwrap_or(0),
+            })
+        })
+        .collect())
+}
+
+/// Messages since `since` by weekday (Monday first) and hour, in UTC.
+pub fn active_hours(
+    conn: &mut DbConnection,
+    chats: &[DbChatId],
+    since: NaiveDateTime,
+) -> QueryResult<Vec<Vec<i64>>> {
+    use schema::tg_message_stats as s;
+
+    let counts: Vec<(NaiveDateTime, Option<i64>)> = s::table
+        .filter(s::chat_id.eq_any(chats))
+        .filter(s::hour.ge(since))
+        .group_by(s::hour)
+        .select((s::hour, dsl::sum(s::messages)))
+        .load(conn)?;
+    let mut hours = vec![vec![0; 24]; 7];
+    for (hour, messages) in counts {
+        let weekday = hour.weekday().num_days_from_monday() as usize;
+        hours[weekday][hour.hour() as usize] += messages.unwrap_or(0);
+    }
+    Ok(hours)
+}
+
+/// Number of forum topics by day, from `since` until today. A topic is
+/// counted from the hour of its first counted message.
+pub fn topic_growth(
+    conn: &mut DbConnection,
+    chats: &[DbChatId],
+    since: NaiveDateTime,
+) -> QueryResult<Vec<models::DataTopicGrowth>> {
+    use schema::tg_message_stats as s;
+
+    let first_messages: Vec<Option<NaiveDateTime>> = s::table
+        .filter(s::chat_id.eq_any(chats))
+        .filter(s::thread_id.ne(DbThreadId::from(GENERAL_THREAD_ID)))
+        .group_by((s::chat_id, s::thread_id))
+        .select(dsl::min(s::hour))
+        .load(conn)?;
+    let mut total_topics = 0;
+    let mut new_topics = BTreeMap::new();
+    for first in first_messages.into_iter().flatten() {
+        if first < since {
+            total_topics += 1;
+        } else {
+            *new_topics.entry(first.date()).or_insert(0) += 1;
+        }
+    }
+
+    let today = Utc::now().date_naive();
+    Ok(since
+        .date()
+        .iter_days()
+        .take_while(|date| *date <= today)
+        .map(|date| {
+            let new_topics = new_topics.get(&date).copied().unwrap_or(0);
+            total_topics += new_topics;
+            models::DataTopicGrowth { date, new_topics, total_topics }
+        })
+        .collect())
+}
+
+fn render(stats: &Stats, since: NaiveDateTime, lang: Lang) -> String {
+    let since = since.format("%Y-%m-%d %H:%M").to_string();
+    let mut text = tr!(lang, "stats-header", since = since);
+    if stats.chats.is_empty() {
+        format_to!(text, "\n{}", tr!(lang, "stats-no-data"));
+        return text;
+    }
+
+    for chat in &stats.chats {
+        let title = chat
+            .title
+            .clone()
+            .unwrap_or_else(|| ChatId::from(chat.chat_id).to_string());
+        format_to!(
+            text,
+            "\n\n<b>{}</b>: {}",
+            escape(&title),
+            tr!(lang, "stats-messages", count = chat.messages),
+        );
+        if chat.topics.len() < 2 {
+            continue;
+        }
+        for topic in chat.topics.iter().take(MAX_TOPICS) {
+            let id = topic.thread_id;
+            let name = match &topic.name {
+                Some(name) => escape(name),
+                None if id == DbThreadId::from(GENERAL_THREAD_ID) => {
+                    tr!(lang, "stats-general-topic")
+                }
+                None => {
+                    tr!(lang, "topics-unnamed", id = ThreadId::from(id).0 .0)
+                }
+            };
+            format_to!(text, "\n  {name}: {}", topic.messages);
+        }
+    }
+
+    format_to!(text, "\n\n<b>{}</b>", tr!(lang, "stats-top-posters"));
+    for (i, poster) in stats.top_posters.iter().enumerate() {
+        format_to!(text, "\n{}. {}", i + 1, escape(&poster.first_name));
+        if let Some(last_name) = &poster.last_name {
+            format_to!(text, " {}", escape(last_name));
+        }
+        format_to!(text, ": {}", poster.messages);
+    }
+
+    format_to!(text, "\n\n<b>{}</b>\n<pre>", tr!(lang, "stats-active-hours"));
+    render_heatmap(
+        &mut text,
+        &stats.active_hours,
+        &tr!(lang, "stats-weekdays"),
+    );
+    text.push_str("</pre>");
+
+    if let Some(last) = stats.topic_growth.last() {
+        let new_topics: i64 =
+            stats.topic_growth.iter().map(|g| g.new_topics).sum();
+        format_to!(
+            text,
+            "\n\n{}",
+            tr!(
+                lang,
+                "stats-topics",
+                total = last.total_topics,
+                new = new_topics
+            ),
+        );
+    }
+    text
+}
+
+/// Render weekday × hour counts as rows of block characters, relative to the
+/// busiest hour. `weekdays` are space-separated weekday names.
+fn render_heatmap(out: &mut String, hours: &[Vec<i64>], weekdays: &str) {
+    let max = hours.iter().flatten().copied().max().unwrap_or(0).max(1);
+    let steps = i64::try_from(HEATMAP_LEVELS.len() - 1).unwrap();
+    format_to!(out, "   {:<6}{:<6}{:<6}{:<6}", 0, 6, 12, 18);
+    for (day, counts) in weekdays.split_whitespace().zip(hours) {
+        format_to!(out, "\n{day:<3}");
+        for &count in counts {
+            // Round up, so that any activity is visible.
+            let level = usize::try_from((count * steps + max - 1) / max);
+            out.push(HEATMAP_LEVELS[level.unwrap_or(0)]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{message, message_update, TestBot, CHAT};
+
+    #[tokio::test]
+    async fn test_stats() {
+        let t = TestBot::new().await;
+        t.add_resident(100);
+
+        let hello = message(CHAT, Some(2), 100, "Hello");
+        for msg in [
+            hello.clone(),
+            message(CHAT, Some(2), 101, "Hi"),
+            message(CHAT, None, 101, "Hi again"),
+        ] {
+            t.dispatch(message_update(msg)).await.unwrap();
+        }
+        // Scraping the same message again doesn't count it twice.
+        t.dispatch(message_update(hello)).await.unwrap();
+        t.api.take_calls();
+
+        let since = Utc::now().naive_utc() - DEFAULT_PERIOD;
+        let chats = [DbChatId::from(ChatId(CHAT))];
+        let mut conn = t.env.conn();
+        let stats = chat_stats(&mut conn, &chats, since).unwrap();
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].messages, 3);
+        let topics = stats[0]
+            .topics
+            .iter()
+            .map(|t| (ThreadId::from(t.thread_id).0 .0, t.messages));
+        assert_eq!(topics.collect_vec(), [(2, 2), (1, 1)]);
+        let posters = top_posters(&mut conn, &chats, since, 10).unwrap();
+        let posters = posters.iter().map(|p| (p.id.0, p.messages));
+        assert_eq!(posters.collect_vec(), [(101, 2), (100, 1)]);
+        let hours = active_hours(&mut conn, &chats, since).unwrap();
+        assert_eq!(hours.iter().flatten().sum::<i64>(), 3);
+        let growth = topic_growth(&mut conn, &chats, since).unwrap();
+        let last = growth.last().unwrap();
+        assert_eq!((last.new_topics, last.total_topics), (1, 1));
+        drop(conn);
+
+        t.dispatch(message_update(message(100, None, 100, "/stats 1w")))
+            .await
+            .unwrap();
+        let calls = t.api.take_calls();
+        let reply = calls[0].body["text"].as_str().unwrap();
+        assert!(reply.contains(": 3 messages"), "{reply}");
+        assert!(reply.contains("1. User101: 2"), "{reply}");
+        assert!(reply.contains("Topics: 1, 1 new."), "{reply}");
+
+        t.dispatch(message_update(message(100, None, 100, "/stats 1y")))
+            .await
+            .unwrap();
+        let calls = t.api.take_calls();
+        assert!(calls[0].body["text"].as_str().unwrap().starts_with("Usage"));
+
+        t.dispatch(message_update(message(100, None, 100, "/stats 20000000w")))
+            .await
+            .unwrap();
+        let calls = t.api.take_calls();
+        assert!(calls[0].body["text"].as_str().unwrap().starts_with("Usage"));
+
+        t.stop().await;
+    }
+
+    #[test]
+    fn test_parse_period() {
+        assert_eq!(parse_period(""), Some(DEFAULT_PERIOD));
+        assert_eq!(parse_period("2w"), Some(chrono::Duration::weeks(2)));
+        assert_eq!(parse_period("366d"), Some(MAX_PERIOD));
+        assert_eq!(parse_period("367d"), None);
+        assert_eq!(parse_period("20000000w"), None);
+        assert!(parse_since("52w").is_some());
+        assert!(parse_since("20000000w").is_none());
+    }
+
+    #[test]
+    fn test_render_heatmap() {
+        let mut hours = vec![vec![0; 24]; 7];
+        hours[0][0] = 8;
+        hours[0][1] = 1;
+        hours[6][23] = 4;
+        let mut out = String::new();
+        render_heatmap(&mut out, &hours, "Mo Tu We Th Fr Sa Su");
+        let lines = out.lines().collect_vec();
+        assert_eq!(lines[0].trim_end(), "   0     6     12    18");
+        assert_eq!(lines[1].trim_end(), "Mo █▁");
+        assert_eq!(lines[7], format!("Su {}▄", " ".repeat(23)));
+    }
+}
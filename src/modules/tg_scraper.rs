@@ -1,16 +1,28 @@
 //! Passively scrape Telegram updates and store various info in the database.
+//!
+//! Text of messages in chats listed in `telegram.chats.archive` is stored as
+//! well, for `/search`, and messages in all groups are counted for `/stats`.
 
 use std::sync::Arc;
 
-use diesel::{ExpressionMethods, RunQueryDsl, SqliteConnection};
+use chrono::{DateTime, DurationRound as _, NaiveDateTime, Utc};
+use diesel::upsert::excluded;
+use diesel::{
+    ExpressionMethods, OptionalExtension as _, QueryDsl as _, QueryResult,
+    RunQueryDsl,
+};
 use teloxide::types::{
-    Chat, ChatKind, ChatMemberUpdated, Message, MessageKind, PublicChatKind,
-    Update, UpdateKind, User,
+    Chat, ChatId, ChatKind, ChatMemberUpdated, Message, MessageKind,
+    PublicChatKind, Update, UpdateKind, User,
 };
 
-use crate::common::BotEnv;
-use crate::db::{DbChatId, DbMessageId, DbThreadId};
-use crate::utils::Sqlizer;
+use super::BotModule;
+use crate::common::{BotEnv, UpdateHandler};
+use crate::db::{
+    write_transaction, DbChatId, DbConnection, DbMessageId, DbThreadId,
+    DbUserId,
+};
+use crate::utils::{ResultExt as _, Sqlizer};
 use crate::{models, schema};
 
 /// Scrapped info from an update.
@@ -32,45 +44,98 @@ struct ChatTopicUpdate<'a> {
     icon_emoji: Option<&'a str>,
 }
 
-/// Wrapper around [scrape].
-pub fn inspect_update(env: Arc<BotEnv>, upd: Update) {
-    env.transaction(|conn| scrape(conn, &upd)).unwrap();
+pub struct Module;
+
+impl BotModule for Module {
+    fn name(&self) -> &'static str {
+        "tg_scraper"
+    }
+
+    fn inspect_update(&self) -> Option<UpdateHandler> {
+        Some(dptree::inspect_async(inspect_update))
+    }
+}
+
+/// Wrapper around [scrape]. Failures are logged, so that the update is still
+/// handled.
+async fn inspect_update(env: Arc<BotEnv>, upd: Update) {
+    let archive = env.config().telegram.chats.archive.clone();
+    env.db
+        .run(move |conn| {
+            write_transaction(conn, |conn| scrape(conn, &upd, &archive))
+        })
+        .await
+        .log_error(module_path!(), "Failed to scrape update");
 }
 
-/// Scrape an update and store various info in the database.
+#[cfg(not(feature = "postgres"))]
+const UPSERT_TG_CHAT_TOPIC: &str =
+    include_str!("../sql/upsert_tg_chat_topic.sql");
+#[cfg(feature = "postgres")]
+const UPSERT_TG_CHAT_TOPIC: &str =
+    include_str!("../sql/upsert_tg_chat_topic_pg.sql");
+
+/// Scrape an update and store various info in the database, archiving
+/// messages in the `archive` chats.
 pub fn scrape(
-    conn: &mut SqliteConnection,
+    conn: &mut DbConnection,
     upd: &Update,
+    archive: &[ChatId],
 ) -> Result<(), diesel::result::Error> {
+    use schema::{
+        tg_chats as c, tg_messages as m, tg_users as u, tg_users_in_chats as uc,
+    };
+
     let scrape = ScrapedInfo::scrape(upd);
-    diesel::replace_into(schema::tg_users::table)
-        .values(scrape.users)
-        .execute(conn)?;
-    diesel::replace_into(schema::tg_chats::table)
-        .values(scrape.chats)
-        .execute(conn)?;
+    for user in &scrape.users {
+        diesel::insert_into(u::table)
+            .values(user)
+            .on_conflict(u::id)
+            .do_update()
+            .set((
+                u::username.eq(excluded(u::username)),
+                u::first_name.eq(excluded(u::first_name)),
+                u::last_name.eq(excluded(u::last_name)),
+            ))
+            .execute(conn)?;
+    }
+    for chat in &scrape.chats {
+        diesel::insert_into(c::table)
+            .values(chat)
+            .on_conflict(c::id)
+            .do_update()
+            .set((
+                c::kind.eq(excluded(c::kind)),
+                c::username.eq(excluded(c::username)),
+                c::title.eq(excluded(c::title)),
+            ))
+            .execute(conn)?;
+    }
     if let Some(user_in_chat) = scrape.user_in_chat {
         if user_in_chat.chat_member.is_some() {
             // Update from ChatMemberUpdated
-            diesel::replace_into(schema::tg_users_in_chats::table)
+            diesel::insert_into(uc::table)
                 .values(&user_in_chat)
+                .on_conflict((uc::chat_id, uc::user_id))
+                .do_update()
+                .set((
+                    uc::chat_member.eq(excluded(uc::chat_member)),
+                    uc::seen.eq(excluded(uc::seen)),
+                ))
                 .execute(conn)?;
         } else {
             // Update from Message seen
-            diesel::insert_into(schema::tg_users_in_chats::table)
+            diesel::insert_into(uc::table)
                 .values(&user_in_chat)
-                .on_conflict((
-                    schema::tg_users_in_chats::chat_id,
-                    schema::tg_users_in_chats::user_id,
-                ))
+                .on_conflict((uc::chat_id, uc::user_id))
                 .do_update()
-                .set(schema::tg_users_in_chats::seen.eq(true))
+                .set(uc::seen.eq(true))
                 .execute(conn)?;
         }
     }
     for topic in scrape.topics {
         use diesel::sql_types::{BigInt, Bool, Integer, Nullable, Text};
-        diesel::sql_query(include_str!("../sql/upsert_tg_chat_topic.sql"))
+        diesel::sql_query(UPSERT_TG_CHAT_TOPIC)
             .bind::<BigInt, _>(topic.chat_id)
             .bind::<Integer, _>(topic.topic_id)
             .bind::<Nullable<Bool>, _>(topic.closed)
@@ -80,9 +145,104 @@ pub fn scrape(
             .bind::<Integer, _>(topic.message_id)
             .execute(conn)?;
     }
+    if let Some(message) = archived_message(upd, archive) {
+        diesel::insert_into(m::table)
+            .values(&message)
+            .on_conflict((m::chat_id, m::message_id))
+            .do_update()
+            .set(m::text.eq(excluded(m::text)))
+            .execute(conn)?;
+    }
+    if let Some(msg) = counted_message(upd) {
+        count_message(conn, msg)?;
+    }
     Ok(())
 }
 
+/// A new message from a user in a group, to be counted in the stats.
+fn counted_message(upd: &Update) -> Option<&Message> {
+    let UpdateKind::Message(msg) = &upd.kind else { return None };
+    let is_group = msg.chat.is_group() || msg.chat.is_supergroup();
+    (is_group
+        && msg.from.is_some()
+        && matches!(msg.kind, MessageKind::Common(_)))
+    .then_some(msg)
+}
+
+/// Add a message to `tg_message_stats`, unless a message with the same or
+/// greater id in this chat has been counted already.
+fn count_message(conn: &mut DbConnection, msg: &Message) -> QueryResult<()> {
+    use schema::{tg_message_stats as s, tg_message_stats_cursors as sc};
+
+    let Some(from) = &msg.from else { return Ok(()) };
+    let chat_id = DbChatId::from(msg.chat.id);
+    let message_id = DbMessageId::from(msg.id);
+    let last_counted: Option<DbMessageId> = sc::table
+        .filter(sc::chat_id.eq(chat_id))
+        .select(sc::message_id)
+        .first(conn)
+        .optional()?;
+    if last_counted.is_some_and(|id| id >= message_id) {
+        return Ok(());
+    }
+    diesel::insert_into(sc::table)
+        .values((sc::chat_id.eq(chat_id), sc::message_id.eq(message_id)))
+        .on_conflict(sc::chat_id)
+        .do_update()
+        .set(sc::message_id.eq(message_id))
+        .execute(conn)?;
+
+    // Replies in non-forum chats have a thread id too, count them as General.
+    let is_topic_message =
+        matches!(&msg.kind, MessageKind::Common(k) if k.is_topic_message);
+    let thread_id = msg.thread_id.filter(|_| is_topic_message);
+    diesel::insert_into(s::table)
+        .values((
+            s::chat_id.eq(chat_id),
+            s::thread_id.eq(DbThreadId::from(thread_id)),
+            s::user_id.eq(DbUserId::from(from.id)),
+            s::hour.eq(hour_start(msg.date)),
+            s::messages.eq(1),
+        ))
+        .on_conflict((s::chat_id, s::thread_id, s::user_id, s::hour))
+        .do_update()
+        .set(s::messages.eq(s::messages + 1))
+        .execute(conn)?;
+    Ok(())
+}
+
+fn hour_start(date: DateTime<Utc>) -> NaiveDateTime {
+    date.duration_trunc(chrono::Duration::hours(1))
+        .map_or_else(|_| date.naive_utc(), |d| d.naive_utc())
+}
+
+/// A new or edited message to store in the archive. Commands are not
+/// archived.
+fn archived_message<'a>(
+    upd: &'a Update,
+    archive: &[ChatId],
+) -> Option<models::NewTgMessage<'a>> {
+    let (UpdateKind::Message(msg) | UpdateKind::EditedMessage(msg)) = &upd.kind
+    else {
+        return None;
+    };
+    if !archive.contains(&msg.chat.id) {
+        return None;
+    }
+    let text = msg.text().or_else(|| msg.caption())?;
+    if text.starts_with('/') {
+        return None;
+    }
+    Some(models::NewTgMessage {
+        chat_id: msg.chat.id.into(),
+        message_id: msg.id.into(),
+        thread_id: msg.thread_id.into(),
+        user_id: msg.from.as_ref().map(|u| u.id.into()),
+        date: msg.date.naive_utc(),
+        text,
+    })
+}
+
 #[allow(clippy::option_map_unit_fn)] // allow for brevity
 impl<'a> ScrapedInfo<'a> {
     pub fn scrape(update: &'a Update) -> Self {
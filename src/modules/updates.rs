@@ -5,17 +5,39 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use anyhow::Result;
+use futures::future::BoxFuture;
 use teloxide::prelude::*;
 use teloxide::types::ParseMode;
 use tokio::select;
 use tokio::time::sleep;
 use tokio_util::sync::CancellationToken;
 
+use super::BotModule;
 use crate::common::BotEnv;
 use crate::models;
 use crate::utils::{get_wikijs_updates, ResultExt as _, StatusChangeDetector};
 
-pub async fn task(env: Arc<BotEnv>, bot: Bot, shutdown: CancellationToken) {
+pub struct Module;
+
+impl BotModule for Module {
+    fn name(&self) -> &'static str {
+        "updates"
+    }
+
+    fn task(
+        &self,
+        bot: Bot,
+        env: Arc<BotEnv>,
+        cancel: CancellationToken,
+    ) -> Option<BoxFuture<'static, ()>> {
+        if env.config().telegram.passive_mode {
+            return None;
+        }
+        Some(Box::pin(task(env, bot, cancel)))
+    }
+}
+
+async fn task(env: Arc<BotEnv>, bot: Bot, shutdown: CancellationToken) {
     let mut initial = true;
     let mut ed = StatusChangeDetector::new();
     loop {
@@ -25,6 +47,9 @@ pub async fn task(env: Arc<BotEnv>, bot: Bot, shutdown: CancellationToken) {
             }
             () = sleep(Duration::from_secs(60)) => {}
         }
+        if env.config().services.wikijs.is_none() {
+            continue;
+        }
 
         let res = check_wikijs_updates(&env, &bot, initial).await;
         crate::metrics::update_service("wikijs", res.is_ok());
@@ -39,10 +64,12 @@ async fn check_wikijs_updates(
     bot: &Bot,
     initial: bool,
 ) -> Result<()> {
+    let config = env.config();
+    let Some(wikijs) = &config.services.wikijs else { return Ok(()) };
     let old_update_state = models::wikijs_update_state.get(&mut env.conn())?;
     let (updates, new_update_state) = get_wikijs_updates(
-        &env.config.services.wikijs.url,
-        &env.config.services.wikijs.token,
+        &wikijs.url,
+        &wikijs.token,
         old_update_state.clone(),
     )
     .await?;
@@ -51,22 +78,21 @@ async fn check_wikijs_updates(
         || updates
             .iter()
             .flat_map(|x| x.paths())
-            .any(|p| p == env.config.services.wikijs.dashboard_page)
+            .any(|p| p == wikijs.dashboard_page)
     {
         crate::modules::dashboard::update(bot, env)
             .await
             .log_error(module_path!(), "Failed to update dashboard");
     }
 
-    if let Some(updates) = updates {
-        bot.send_message(
-            env.config.telegram.chats.wikijs_updates.chat,
-            updates.to_html(),
-        )
-        .message_thread_id(env.config.telegram.chats.wikijs_updates.thread)
-        .parse_mode(ParseMode::Html)
-        .disable_web_page_preview(true)
-        .await?;
+    if let (Some(updates), Some(thread)) =
+        (updates, config.telegram.chats.wikijs_updates)
+    {
+        bot.send_message(thread.chat, updates.to_html())
+            .message_thread_id(thread.thread)
+            .parse_mode(ParseMode::Html)
+            .disable_web_page_preview(true)
+            .await?;
     }
 
     // XXX: Not sure if this check makes sense.  I want to avoid spurious
@@ -9,6 +9,7 @@ use macro_rules_attribute::derive;
 use teloxide::macros::BotCommands;
 use teloxide::prelude::*;
 
+use super::{BotModule, ModuleCommands};
 use crate::common::{filter_command, BotCommandsExt, BotEnv, UpdateHandler};
 use crate::db::DbUserId;
 use crate::utils::BotExt;
@@ -34,7 +35,23 @@ struct UserctlArgs {
     remove_mac: Vec<macaddr::MacAddr6>,
 }
 
-pub fn command_handler() -> UpdateHandler {
+pub struct Module;
+
+impl BotModule for Module {
+    fn name(&self) -> &'static str {
+        "userctl"
+    }
+
+    fn message_handler(&self) -> Option<UpdateHandler> {
+        Some(command_handler())
+    }
+
+    fn commands(&self) -> Option<ModuleCommands> {
+        Some(ModuleCommands::of::<Commands>())
+    }
+}
+
+fn command_handler() -> UpdateHandler {
     filter_command::<Commands>().endpoint(cmd_userctl)
 }
 
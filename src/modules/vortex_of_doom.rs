@@ -4,25 +4,57 @@ use std::sync::Arc;
 use anyhow::Context;
 use chrono::Utc;
 use cron::Schedule;
+use futures::future::BoxFuture;
 use log::debug;
 use teloxide::payloads::{SendMessageSetters, SendPhotoSetters};
 use teloxide::requests::Requester;
 use teloxide::Bot;
 use tokio::time::sleep;
+use tokio_util::sync::CancellationToken;
 
-use crate::config::{Config, EspCam, VortexOfDoom};
+use super::BotModule;
+use crate::common::BotEnv;
+use crate::i18n::{chat_lang, tr};
 use crate::utils::{read_camera_image, ResultExt};
 
+pub struct Module;
+
+impl BotModule for Module {
+    fn name(&self) -> &'static str {
+        "vortex_of_doom"
+    }
+
+    fn task(
+        &self,
+        bot: Bot,
+        env: Arc<BotEnv>,
+        cancel: CancellationToken,
+    ) -> Option<BoxFuture<'static, ()>> {
+        Some(Box::pin(vortex_of_doom(bot, env, cancel)))
+    }
+}
+
 async fn vortex_of_doom_internal(
     bot: Bot,
-    client: reqwest::Client,
-    chat_config: &VortexOfDoom,
-    camera_config: &EspCam,
+    env: Arc<BotEnv>,
+    cancel: CancellationToken,
 ) -> anyhow::Result<()> {
-    let schedule = Schedule::from_str(&chat_config.schedule)
-        .context("failed to parse schedule")?;
-
     loop {
+        let reloaded = env.config_reloaded.notified();
+        tokio::pin!(reloaded);
+        reloaded.as_mut().enable();
+
+        let config = env.config();
+        let Some(chat_config) = &config.telegram.chats.vortex_of_doom else {
+            tokio::select! {
+                () = cancel.cancelled() => return Ok(()),
+                () = reloaded => continue,
+            }
+        };
+        let camera_config = &config.services.vortex_of_doom_cam;
+        let schedule = Schedule::from_str(&chat_config.schedule)
+            .context("failed to parse schedule")?;
+
         let next_run = schedule
             .upcoming(Utc)
             .next()
@@ -33,13 +65,26 @@ async fn vortex_of_doom_internal(
         let diff = next_run - now;
         debug!("Waiting for next schedule {}", diff);
 
-        sleep(diff.to_std()?).await;
+        tokio::select! {
+            () = cancel.cancelled() => return Ok(()),
+            () = sleep(diff.to_std()?) => (),
+            () = reloaded => {
+                debug!("Config reloaded, rescheduling");
+                continue;
+            }
+        }
 
-        let image = read_camera_image(client.clone(), camera_config)
-            .await
-            .log_ok(module_path!(), "failed to fetch espcam image");
+        let image = match camera_config {
+            Some(camera_config) => {
+                read_camera_image(env.reqwest_client.clone(), camera_config)
+                    .await
+                    .log_ok(module_path!(), "failed to fetch espcam image")
+            }
+            None => None,
+        };
 
-        let mut text = "It's vortex of doom time! Please move the boxes, and throw away the last one and send a picture.".to_string();
+        let lang = chat_lang(&env, chat_config.chat.chat).await;
+        let mut text = tr!(lang, "vortex-of-doom");
         if let Some(additional_text) = &chat_config.additional_text {
             text.push_str("\n\n");
             text.push_str(additional_text);
@@ -51,9 +96,10 @@ async fn vortex_of_doom_internal(
                 .parse_mode(teloxide::types::ParseMode::Html)
                 .await?;
         } else {
-            text.push_str(
-                "\n\nFailed to fetch camera image, please check the logs.",
-            );
+            if camera_config.is_some() {
+                text.push_str("\n\n");
+                text.push_str(&tr!(lang, "vortex-of-doom-no-camera"));
+            }
             bot.send_message(chat_config.chat.chat, &text)
                 .message_thread_id(chat_config.chat.thread)
                 .parse_mode(teloxide::types::ParseMode::Html)
@@ -62,17 +108,8 @@ async fn vortex_of_doom_internal(
     }
 }
 
-pub async fn vortex_of_doom(
-    bot: Bot,
-    client: reqwest::Client,
-    config: Arc<Config>,
-) {
-    vortex_of_doom_internal(
-        bot,
-        client,
-        &config.telegram.chats.vortex_of_doom,
-        &config.services.vortex_of_doom_cam,
-    )
-    .await
-    .log_error(module_path!(), "Vortex of doom error");
+async fn vortex_of_doom(bot: Bot, env: Arc<BotEnv>, cancel: CancellationToken) {
+    vortex_of_doom_internal(bot, env, cancel)
+        .await
+        .log_error(module_path!(), "Vortex of doom error");
 }
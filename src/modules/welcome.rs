@@ -12,24 +12,59 @@ use std::sync::{Arc, Mutex};
 use anyhow::{Context as _, Result};
 use chrono::{Duration, Utc};
 use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use dptree::di::DependencyMap;
 use itertools::Itertools as _;
 use reqwest::Url;
 use teloxide::prelude::*;
 use teloxide::types::{InlineKeyboardButton, ParseMode, ReplyMarkup, User};
 
+use super::BotModule;
 use crate::common::{BotEnv, UpdateHandler};
 use crate::db::DbUserId;
-use crate::utils::UserExt as _;
+use crate::models;
+use crate::utils::{ResultExt as _, UserExt as _};
 
 /// State contains the set of users who have already been welcomed.
 #[derive(Clone, Debug, Default)]
 pub struct State(HashSet<UserId>);
 
-pub fn state() -> Arc<Mutex<State>> {
-    Arc::new(Mutex::new(State::default()))
+#[derive(Default)]
+pub struct Module {
+    state: Arc<Mutex<State>>,
+}
+
+impl BotModule for Module {
+    fn name(&self) -> &'static str {
+        "welcome"
+    }
+
+    fn message_handler(&self) -> Option<UpdateHandler> {
+        Some(message_handler())
+    }
+
+    fn dependencies(&self, deps: &mut DependencyMap) {
+        deps.insert(Arc::clone(&self.state));
+    }
+
+    fn load_state(&self, env: &BotEnv) {
+        let users = models::welcomed_users
+            .get(&mut env.conn())
+            .log_ok(module_path!(), "Failed to load welcomed users")
+            .flatten();
+        if let Some(users) = users {
+            self.state.lock().unwrap().0 = users;
+        }
+    }
+
+    fn save_state(&self, env: &BotEnv) {
+        let state = self.state.lock().unwrap().clone();
+        models::welcomed_users
+            .set(&mut env.conn(), &state.0)
+            .log_error(module_path!(), "Failed to save welcomed users");
+    }
 }
 
-pub fn message_handler() -> UpdateHandler {
+fn message_handler() -> UpdateHandler {
     Update::filter_message().filter_map(filter_joins).endpoint(handle_join)
 }
 
@@ -41,7 +76,7 @@ fn filter_joins(
     state: Arc<Mutex<State>>,
     msg: Message,
 ) -> Option<Newcomers> {
-    if *env.config.telegram.chats.residential.first()? != msg.chat.id {
+    if *env.config().telegram.chats.residential.first()? != msg.chat.id {
         return None;
     }
     let new_members = msg.new_chat_members()?;
@@ -83,10 +118,12 @@ async fn handle_join(
     msg: Message,
     newcomers: Newcomers,
 ) -> Result<()> {
+    let config = env.config();
+    let Some(wikijs) = &config.services.wikijs else { return Ok(()) };
     let page = crate::utils::get_wikijs_page(
-        &env.config.services.wikijs.url,
-        &env.config.services.wikijs.token,
-        &env.config.services.wikijs.welcome_message_page,
+        &wikijs.url,
+        &wikijs.token,
+        &wikijs.welcome_message_page,
     )
     .await?;
 
@@ -102,12 +139,8 @@ async fn handle_join(
         "✏️ Edit this message",
         Url::parse(&format!(
             "{}/{}",
-            env.config.services.wikijs.url,
-            env.config
-                .services
-                .wikijs
-                .welcome_message_page
-                .trim_start_matches('/'),
+            wikijs.url,
+            wikijs.welcome_message_page.trim_start_matches('/'),
         ))?,
     );
 
@@ -141,6 +174,21 @@ pub fn extract_message(text: &str) -> Option<&str> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testing::TestBot;
+
+    #[tokio::test]
+    async fn test_state() {
+        let t = TestBot::new().await;
+        let module = Module::default();
+        module.state.lock().unwrap().0.insert(UserId(100));
+        module.save_state(&t.env);
+
+        let restored = Module::default();
+        restored.load_state(&t.env);
+        assert!(restored.state.lock().unwrap().0.contains(&UserId(100)));
+
+        t.stop().await;
+    }
 
     #[test]
     fn test_extract_message() {
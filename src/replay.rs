@@ -0,0 +1,220 @@
+//! Replay a log written by [`crate::tracing_proxy`] through the bot handlers.
+//!
+//! Updates from the log are dispatched one by one, in order, through the same
+//! handler tree as used by the running bot. Requests made by the handlers are
+//! sent to a local fake Telegram Bot API server which answers them with the
+//! responses recorded in the same log, so nothing is sent to Telegram.
+//!
+//! Recorded responses are matched to requests by method name, in the order
+//! they were logged. Responses logged before the update being replayed can't
+//! belong to its handlers (e.g. they were made by background tasks), so they
+//! are skipped. Requests without a matching response are answered with an
+//! error.
+//!
+//! Only Telegram is faked. LDAP is not connected, and other services are
+//! accessed as configured.
+
+use std::collections::{HashMap, VecDeque};
+use std::convert::Infallible;
+use std::ffi::OsStr;
+use std::net::SocketAddr;
+use std::ops::ControlFlow;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Context as _, Result};
+use arc_swap::ArcSwap;
+use hyper::server::conn::AddrIncoming;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server};
+use teloxide::requests::Requester;
+use teloxide::types::Update;
+use teloxide::Bot;
+use tokio::net::TcpListener;
+
+use crate::common::BotEnv;
+use crate::db::Pool;
+use crate::modules::Registry;
+use crate::trace_log;
+use crate::utils::{parse_tgapi_method, RateLimiter};
+
+/// A request/response pair logged by [`crate::tracing_proxy`].
+#[derive(serde::Deserialize, Debug)]
+struct LoggedCall {
+    method: Option<String>,
+    status: u16,
+    request: Option<serde_json::Value>,
+    response: Option<serde_json::Value>,
+}
+
+/// State of the fake Telegram Bot API server.
+#[derive(Default)]
+struct FakeApi {
+    /// Line number of the update being replayed.
+    line: usize,
+    /// Recorded calls grouped by method, along with their line numbers.
+    calls: HashMap<String, VecDeque<(usize, LoggedCall)>>,
+}
+
+impl FakeApi {
+    /// Take the next recorded call of `method` logged after the current line.
+    fn take(&mut self, method: &str) -> Option<LoggedCall> {
+        let queue = self.calls.get_mut(method)?;
+        while let Some((line, call)) = queue.pop_front() {
+            if line >= self.line {
+                return Some(call);
+            }
+        }
+        None
+    }
+}
+
+/// Replay the log. The database is modified by the handlers, so it's better
+/// to use a copy.
+pub async fn run(
+    config_fpath: &OsStr,
+    db_fpath: &str,
+    trace_fpath: &OsStr,
+) -> Result<()> {
+    let config = Arc::new(crate::load_config(config_fpath)?);
+    let (updates, api) = read_log(trace_fpath)?;
+    let api = Arc::new(Mutex::new(api));
+    let api_url = start_fake_api(Arc::clone(&api)).await?;
+
+    let db = Pool::open(db_fpath, 1)?;
+    crate::apply_migrations(&mut db.get())?;
+
+    let bot_env = Arc::new(BotEnv {
+        db,
+        reqwest_client: reqwest::Client::new(),
+        openai_client: async_openai::Client::with_config(
+            async_openai::config::OpenAIConfig::new()
+                .with_api_key(config.services.openai_api_key()),
+        ),
+        config: ArcSwap::new(Arc::clone(&config)),
+        config_path: config_fpath.into(),
+        config_reloaded: tokio::sync::Notify::new(),
+        ldap_client: tokio::sync::Mutex::new(None),
+        rate_limiter: RateLimiter::new(),
+    });
+
+    let bot = Bot::new(&config.telegram.token).set_api_url(api_url);
+    let me = bot.get_me().await.context("No getMe response in the log")?;
+
+    let registry = Arc::new(Registry::default());
+    let handler = registry.handler_tree();
+    let mut deps = registry.dependencies(bot_env);
+    deps.insert(me);
+    deps.insert(bot);
+
+    let total = updates.len();
+    for (line, update) in updates {
+        api.lock().unwrap().line = line;
+        let mut deps = deps.clone();
+        deps.insert(update);
+        match handler.dispatch(deps).await {
+            ControlFlow::Break(Ok(())) => (),
+            ControlFlow::Break(Err(e)) => {
+                log::error!("Error handling update at line {line}: {e:?}");
+            }
+            ControlFlow::Continue(_) => {
+                log::warn!("Unhandled update at line {line}");
+            }
+        }
+    }
+    log::info!("Replayed {total} updates");
+
+    Ok(())
+}
+
+/// Read the log, returning updates and recorded calls along with their line
+/// numbers. Line numbers are counted across all rotated segments.
+fn read_log(trace_fpath: &OsStr) -> Result<(Vec<(usize, Update)>, FakeApi)> {
+    let lines = trace_log::read_lines(Path::new(trace_fpath))
+        .context("Failed to open trace file")?;
+    let mut updates = Vec::new();
+    let mut api = FakeApi::default();
+    for (idx, line) in lines.enumerate() {
+        let line_no = idx + 1;
+        let line = line?;
+        if line.starts_with(r#"{"__f0bot":""#) {
+            let call: LoggedCall = serde_json::from_str(&line)
+                .with_context(|| format!("Failed to parse line {line_no}"))?;
+            if let Some(method) = call.method.clone() {
+                api.calls.entry(method).or_default().push_back((line_no, call));
+            }
+        } else {
+            let update: Update = serde_json::from_str(&line)
+                .with_context(|| format!("Failed to parse line {line_no}"))?;
+            updates.push((line_no, update));
+        }
+    }
+    Ok((updates, api))
+}
+
+/// Start a fake Telegram Bot API server. Returns the URL of the server.
+async fn start_fake_api(api: Arc<Mutex<FakeApi>>) -> Result<reqwest::Url> {
+    let listener =
+        TcpListener::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).await?;
+    let local_addr = listener.local_addr()?;
+    let server = Server::builder(AddrIncoming::from_listener(listener)?).serve(
+        make_service_fn(move |_conn| {
+            let api = Arc::clone(&api);
+            async {
+                Ok::<_, Infallible>(service_fn(move |req| {
+                    handle_request(req, Arc::clone(&api))
+                }))
+            }
+        }),
+    );
+    tokio::spawn(async move {
+        if let Err(e) = server.await {
+            log::error!("Fake API server error: {e}");
+        }
+    });
+
+    Ok(reqwest::Url::parse(&format!("http://{local_addr}"))?)
+}
+
+async fn handle_request(
+    request: Request<Body>,
+    api: Arc<Mutex<FakeApi>>,
+) -> Result<Response<Body>> {
+    let method = parse_tgapi_method(request.uri().path()).map(str::to_owned);
+    let body = hyper::body::to_bytes(request.into_body()).await?;
+    let body = serde_json::from_slice::<serde_json::Value>(&body).ok();
+
+    let Some(method) = method else {
+        return Ok(Response::builder().status(404).body(Body::empty())?);
+    };
+
+    let Some(call) = api.lock().unwrap().take(&method) else {
+        log::warn!("{method}: no recorded response, request: {body:?}");
+        let response = serde_json::json!({
+            "ok": false,
+            "error_code": 400,
+            "description": "Bad Request: no recorded response",
+        });
+        return Ok(Response::builder()
+            .status(400)
+            .header("content-type", "application/json")
+            .body(Body::from(response.to_string()))?);
+    };
+
+    if call.request == body {
+        log::info!("{method}: {body:?}");
+    } else {
+        log::warn!(
+            "{method}: request differs from the recorded one, \
+             recorded: {:?}, actual: {body:?}",
+            call.request,
+        );
+    }
+
+    Ok(Response::builder()
+        .status(call.status)
+        .header("content-type", "application/json")
+        .body(Body::from(
+            call.response.map_or_else(String::new, |r| r.to_string()),
+        ))?)
+}
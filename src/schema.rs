@@ -1,5 +1,16 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    api_tokens (rowid) {
+        rowid -> Integer,
+        user_id -> BigInt,
+        name -> Text,
+        token_hash -> Text,
+        created_at -> Timestamp,
+        last_used_at -> Nullable<Timestamp>,
+    }
+}
+
 diesel::table! {
     borrowed_items (chat_id, user_message_id) {
         chat_id -> BigInt,
@@ -11,6 +22,13 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    chat_languages (chat_id) {
+        chat_id -> BigInt,
+        lang -> Text,
+    }
+}
+
 diesel::table! {
     dashboard_messages (chat_id, thread_id, message_id) {
         chat_id -> BigInt,
@@ -72,6 +90,35 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    tg_message_stats (chat_id, thread_id, user_id, hour) {
+        chat_id -> BigInt,
+        thread_id -> Integer,
+        user_id -> BigInt,
+        hour -> Timestamp,
+        messages -> Integer,
+    }
+}
+
+diesel::table! {
+    tg_message_stats_cursors (chat_id) {
+        chat_id -> BigInt,
+        message_id -> Integer,
+    }
+}
+
+diesel::table! {
+    tg_messages (rowid) {
+        rowid -> Integer,
+        chat_id -> BigInt,
+        message_id -> Integer,
+        thread_id -> Integer,
+        user_id -> Nullable<BigInt>,
+        date -> Timestamp,
+        text -> Text,
+    }
+}
+
 diesel::table! {
     tg_users (id) {
         id -> BigInt,
@@ -107,16 +154,31 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    user_roles (user_id, role) {
+        user_id -> BigInt,
+        role -> Text,
+        granted_by -> BigInt,
+        granted_at -> Timestamp,
+    }
+}
+
 diesel::allow_tables_to_appear_in_same_query!(
+    api_tokens,
     borrowed_items,
+    chat_languages,
     dashboard_messages,
     needed_items,
     options,
     residents,
     tg_chat_topics,
     tg_chats,
+    tg_message_stats,
+    tg_message_stats_cursors,
+    tg_messages,
     tg_users,
     tg_users_in_chats,
     tracked_polls,
     user_macs,
+    user_roles,
 );
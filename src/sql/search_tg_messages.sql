@@ -0,0 +1,10 @@
+-- Archived messages matching an FTS5 query, in chats where the user is a
+-- member. Parameters: user id, query, limit.
+SELECT m.chat_id, m.message_id, m.text
+FROM tg_messages_fts
+JOIN tg_messages m ON m.rowid = tg_messages_fts.rowid
+JOIN tg_users_in_chats uc
+  ON uc.chat_id = m.chat_id AND uc.user_id = ? AND uc.seen
+WHERE tg_messages_fts MATCH ?
+ORDER BY tg_messages_fts.rank, m.date DESC
+LIMIT ?
@@ -0,0 +1,11 @@
+-- Archived messages matching a tsquery, in chats where the user is a member.
+-- Parameters: user id, query, limit.
+SELECT m.chat_id, m.message_id, m.text
+FROM tg_messages m
+JOIN tg_users_in_chats uc
+  ON uc.chat_id = m.chat_id AND uc.user_id = $1 AND uc.seen
+WHERE to_tsvector('simple', m.text) @@ to_tsquery('simple', $2)
+ORDER BY
+  ts_rank(to_tsvector('simple', m.text), to_tsquery('simple', $2)) DESC,
+  m.date DESC
+LIMIT $3
@@ -0,0 +1,35 @@
+-- PostgreSQL version of upsert_tg_chat_topic.sql.
+INSERT INTO tg_chat_topics AS t (
+    chat_id,
+    topic_id,
+    closed,
+    name,
+    icon_color,
+    icon_emoji,
+    id_closed,
+    id_name,
+    id_icon_emoji
+)
+VALUES (
+    $1,
+    $2,
+    $3,
+    $4,
+    $5,
+    $6,
+    CASE WHEN $3 IS NULL THEN 0 ELSE $7 END,
+    CASE WHEN $4 IS NULL THEN 0 ELSE $7 END,
+    CASE WHEN $6 IS NULL THEN 0 ELSE $7 END
+)
+ON CONFLICT (chat_id, topic_id)
+DO UPDATE SET
+    closed        = CASE WHEN t.id_closed     > $7 OR $3 IS NULL THEN t.closed        ELSE $3 END,
+    id_closed     = CASE WHEN t.id_closed     > $7 OR $3 IS NULL THEN t.id_closed     ELSE $7 END,
+
+    name          = CASE WHEN t.id_name       > $7 OR $4 IS NULL THEN t.name          ELSE $4 END,
+    id_name       = CASE WHEN t.id_name       > $7 OR $4 IS NULL THEN t.id_name       ELSE $7 END,
+
+    icon_color    = COALESCE($5, t.icon_color),
+
+    icon_emoji    = CASE WHEN t.id_icon_emoji > $7 OR $6 IS NULL THEN t.icon_emoji    ELSE $6 END,
+    id_icon_emoji = CASE WHEN t.id_icon_emoji > $7 OR $6 IS NULL THEN t.id_icon_emoji ELSE $7 END
@@ -0,0 +1,507 @@
+//! Helpers for handler tests: a fake Telegram Bot API server and a bot
+//! instance wired to it.
+//!
+//! Handlers are driven through the same handler tree as the running bot by
+//! [`TestBot::dispatch`]. All requests made by the handlers are recorded by
+//! [`FakeTelegram`] and answered with plausible responses (e.g. `sendMessage`
+//! returns a new message with the requested text), unless overridden with
+//! [`FakeTelegram::add_response`].
+
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::ops::ControlFlow;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use arc_swap::ArcSwap;
+use diesel::{ExpressionMethods as _, RunQueryDsl as _};
+use dptree::di::DependencyMap;
+use hyper::{Body, Response, Server};
+use teloxide::requests::Requester as _;
+use teloxide::types::{Update, UserId};
+use teloxide::Bot;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+use crate::common::{BotEnv, UpdateHandler};
+use crate::config::Config;
+use crate::db::{DbUserId, Pool};
+use crate::modules::Registry;
+use crate::utils::{parse_tgapi_method, RateLimiter};
+
+/// Chat of all threads in `config.example.yaml`.
+pub const CHAT: i64 = -1_001_234_567_890;
+
+/// Thread for the `needs` module, as in `config.example.yaml`.
+pub const NEEDS_THREAD: i32 = 123;
+
+/// Thread for the `borrowed_items` module, as in `config.example.yaml`.
+pub const BORROWED_ITEMS_THREAD: i32 = 124;
+
+/// User ID of the bot, as returned by `getMe`.
+pub const BOT_USER_ID: u64 = 1;
+
+/// Used for update IDs and message IDs of synthetic updates.
+static NEXT_ID: AtomicI32 = AtomicI32::new(1);
+
+/// A request made by the bot.
+#[derive(Debug, Clone)]
+pub struct Call {
+    /// Method name, as in the Bot API documentation, e.g. `sendMessage`.
+    pub method: String,
+    /// Request body, or `null` if it is not JSON (e.g. multipart).
+    pub body: serde_json::Value,
+}
+
+#[derive(Default)]
+struct State {
+    calls: Vec<Call>,
+    responses: HashMap<String, VecDeque<serde_json::Value>>,
+    next_message_id: i32,
+    next_poll_id: i32,
+}
+
+/// A local stand-in for `api.telegram.org`.
+pub struct FakeTelegram {
+    stop: oneshot::Sender<()>,
+    addr: SocketAddr,
+    server: JoinHandle<()>,
+    state: Arc<Mutex<State>>,
+}
+
+impl FakeTelegram {
+    pub fn start() -> Self {
+        let state = Arc::new(Mutex::new(State {
+            next_message_id: 10_000,
+            ..State::default()
+        }));
+
+        let state_clone = Arc::clone(&state);
+        let make_svc = hyper::service::make_service_fn(move |_| {
+            let state = Arc::clone(&state_clone);
+            async move {
+                Ok::<_, hyper::Error>(hyper::service::service_fn(move |req| {
+                    let state = Arc::clone(&state);
+                    async move {
+                        let method = parse_tgapi_method(req.uri().path())
+                            .map(api_method_name)
+                            .expect("Invalid API path");
+                        let body = hyper::body::to_bytes(req.into_body())
+                            .await
+                            .expect("Failed to read body");
+                        let body = serde_json::from_slice(&body)
+                            .unwrap_or(serde_json::Value::Null);
+                        let response =
+                            state.lock().unwrap().handle(method, body);
+                        Ok::<_, hyper::Error>(
+                            Response::builder()
+                                .header("content-type", "application/json")
+                                .body(Body::from(response.to_string()))
+                                .unwrap(),
+                        )
+                    }
+                }))
+            }
+        });
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_svc);
+        let addr = server.local_addr();
+        let (tx, rx) = oneshot::channel::<()>();
+        let graceful = server.with_graceful_shutdown(async {
+            rx.await.ok();
+        });
+        Self {
+            stop: tx,
+            addr,
+            server: tokio::spawn(async move {
+                graceful.await.unwrap();
+            }),
+            state,
+        }
+    }
+
+    pub fn url(&self) -> reqwest::Url {
+        reqwest::Url::parse(&format!("http://{}", self.addr)).unwrap()
+    }
+
+    /// Override the response to the next call of `method`. The response is
+    /// the whole body, e.g. `{"ok": false, "error_code": 400, ...}`.
+    pub fn add_response(&self, method: &str, response: serde_json::Value) {
+        self.state
+            .lock()
+            .unwrap()
+            .responses
+            .entry(method.to_string())
+            .or_default()
+            .push_back(response);
+    }
+
+    /// Return calls made since the last invocation.
+    pub fn take_calls(&self) -> Vec<Call> {
+        std::mem::take(&mut self.state.lock().unwrap().calls)
+    }
+
+    /// Return names of methods called since the last invocation.
+    pub fn take_methods(&self) -> Vec<String> {
+        self.take_calls().into_iter().map(|c| c.method).collect()
+    }
+
+    pub async fn stop(self) {
+        self.stop.send(()).unwrap();
+        self.server.await.unwrap();
+    }
+}
+
+impl State {
+    fn handle(
+        &mut self,
+        method: String,
+        body: serde_json::Value,
+    ) -> serde_json::Value {
+        self.calls.push(Call { method: method.clone(), body: body.clone() });
+        if let Some(response) =
+            self.responses.get_mut(&method).and_then(VecDeque::pop_front)
+        {
+            return response;
+        }
+        let result = match method.as_str() {
+            "getMe" => serde_json::json!({
+                "id": BOT_USER_ID,
+                "is_bot": true,
+                "first_name": "Test bot",
+                "username": "test_bot",
+                "can_join_groups": true,
+                "can_read_all_group_messages": true,
+                "supports_inline_queries": false,
+            }),
+            "sendMessage" | "forwardMessage" | "sendPhoto" | "sendDocument" => {
+                self.next_message_id += 1;
+                fake_message(self.next_message_id, &body)
+            }
+            "editMessageText" | "editMessageReplyMarkup" => {
+                fake_message(body["message_id"].as_i64().unwrap_or(0), &body)
+            }
+            "sendPoll" => {
+                self.next_message_id += 1;
+                self.next_poll_id += 1;
+                let mut message = fake_message(self.next_message_id, &body);
+                message.as_object_mut().unwrap().remove("text");
+                message["poll"] = fake_poll(self.next_poll_id, &body);
+                message
+            }
+            _ => serde_json::Value::Bool(true),
+        };
+        serde_json::json!({ "ok": true, "result": result })
+    }
+}
+
+/// Convert a method name from the URL (e.g. `SendMessage`) into the form
+/// used by the Bot API documentation (e.g. `sendMessage`).
+fn api_method_name(method: &str) -> String {
+    let mut chars = method.chars();
+    chars.next().map_or_else(String::new, |c| {
+        c.to_ascii_lowercase().to_string() + chars.as_str()
+    })
+}
+
+fn fake_message(
+    message_id: impl Into<i64>,
+    request: &serde_json::Value,
+) -> serde_json::Value {
+    let chat_id = request["chat_id"].as_i64().unwrap_or(0);
+    let mut message = serde_json::json!({
+        "message_id": message_id.into(),
+        "date": 0,
+        "chat": chat(chat_id),
+        "from": user(BOT_USER_ID),
+        "text": request["text"].as_str().unwrap_or_default(),
+    });
+    if let Some(thread) = request["message_thread_id"].as_i64() {
+        message["message_thread_id"] = thread.into();
+        message["is_topic_message"] = true.into();
+    }
+    message
+}
+
+fn fake_poll(poll_id: i32, request: &serde_json::Value) -> serde_json::Value {
+    let options = request["options"]
+        .as_array()
+        .map(|options| {
+            options
+                .iter()
+                .map(|o| serde_json::json!({"text": o, "voter_count": 0}))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    serde_json::json!({
+        "id": poll_id.to_string(),
+        "question": request["question"],
+        "options": options,
+        "total_voter_count": 0,
+        "is_closed": false,
+        "is_anonymous": request["is_anonymous"].as_bool().unwrap_or(true),
+        "type": "regular",
+        "allows_multiple_answers":
+            request["allows_multiple_answers"].as_bool().unwrap_or(false),
+    })
+}
+
+/// URL of a new empty database: an in-memory one for SQLite.
+#[cfg(not(feature = "postgres"))]
+pub fn test_database_url() -> String {
+    ":memory:".to_string()
+}
+
+/// URL of a new empty database, created on the PostgreSQL server given by the
+/// `BOTKA_TEST_POSTGRES` environment variable, see `just test-postgres`.
+#[cfg(feature = "postgres")]
+pub fn test_database_url() -> String {
+    use diesel::Connection as _;
+
+    let server = std::env::var("BOTKA_TEST_POSTGRES")
+        .expect("BOTKA_TEST_POSTGRES is not set, see `just test-postgres`");
+    let name = format!("botka_test_{}_{}", std::process::id(), next_id());
+    let mut conn = diesel::PgConnection::establish(&server).unwrap();
+    diesel::sql_query(format!("CREATE DATABASE {name}"))
+        .execute(&mut conn)
+        .unwrap();
+    let mut url = reqwest::Url::parse(&server).unwrap();
+    url.set_path(&name);
+    url.to_string()
+}
+
+/// A bot connected to a [`FakeTelegram`] and an empty database.
+pub struct TestBot {
+    pub api: FakeTelegram,
+    pub bot: Bot,
+    pub env: Arc<BotEnv>,
+    handler: UpdateHandler,
+    deps: DependencyMap,
+}
+
+impl TestBot {
+    /// Start a bot with [`test_config`].
+    pub async fn new() -> Self {
+        Self::with_config(test_config()).await
+    }
+
+    pub async fn with_config(config: Config) -> Self {
+        let config = Arc::new(config);
+        // Each in-memory connection is a separate database, so use only one.
+        let db = Pool::open(&test_database_url(), 1).unwrap();
+        crate::db::migrate(&mut db.get(), false).unwrap();
+
+        let env = Arc::new(BotEnv {
+            db,
+            reqwest_client: reqwest::Client::new(),
+            openai_client: async_openai::Client::with_config(
+                async_openai::config::OpenAIConfig::new()
+                    .with_api_key(config.services.openai_api_key()),
+            ),
+            config: ArcSwap::new(Arc::clone(&config)),
+            config_path: "config.example.yaml".into(),
+            config_reloaded: tokio::sync::Notify::new(),
+            ldap_client: tokio::sync::Mutex::new(None),
+            rate_limiter: RateLimiter::new(),
+        });
+
+        let api = FakeTelegram::start();
+        let bot = Bot::new(&config.telegram.token).set_api_url(api.url());
+        let me = bot.get_me().await.unwrap();
+        api.take_calls();
+
+        let registry = Arc::new(Registry::default());
+        let mut deps = registry.dependencies(Arc::clone(&env));
+        deps.insert(me);
+        deps.insert(bot.clone());
+
+        Self { api, bot, env, handler: registry.handler_tree(), deps }
+    }
+
+    /// Pass an update through the handler tree.
+    pub async fn dispatch(&self, update: Update) -> Result<()> {
+        self.dispatch_to(&self.handler, update).await
+    }
+
+    /// Pass an update through another handler with the same dependencies,
+    /// e.g. one built from commands defined in a test.
+    pub async fn dispatch_to(
+        &self,
+        handler: &UpdateHandler,
+        update: Update,
+    ) -> Result<()> {
+        let mut deps = self.deps.clone();
+        deps.insert(update);
+        match handler.dispatch(deps).await {
+            ControlFlow::Break(result) => result,
+            ControlFlow::Continue(_) => anyhow::bail!("Unhandled update"),
+        }
+    }
+
+    /// Mark a user as a current resident.
+    pub fn add_resident(&self, user_id: u64) {
+        diesel::insert_into(crate::schema::residents::table)
+            .values((
+                crate::schema::residents::tg_id
+                    .eq(DbUserId::from(UserId(user_id))),
+                crate::schema::residents::begin_date
+                    .eq(chrono::Utc::now().naive_utc()),
+            ))
+            .execute(&mut *self.env.conn())
+            .unwrap();
+    }
+
+    pub async fn stop(self) {
+        self.api.stop().await;
+    }
+}
+
+/// `config.example.yaml` with external services disabled.
+pub fn test_config() -> Config {
+    let mut config: Config =
+        serde_yaml::from_str(include_str!("../config.example.yaml")).unwrap();
+    config.services.openai = None;
+    #[cfg(feature = "postgres")]
+    {
+        // Not connected to, see `test_database_url`.
+        config.postgres = Some(crate::config::Postgres {
+            url: "postgres://localhost/botka".to_string(),
+            url_file: None,
+        });
+        config.backup = None;
+    }
+    config
+}
+
+fn next_id() -> i32 {
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+pub fn user(id: u64) -> serde_json::Value {
+    serde_json::json!({
+        "id": id,
+        "is_bot": id == BOT_USER_ID,
+        "first_name": format!("User{id}"),
+        "username": format!("user{id}"),
+    })
+}
+
+pub fn chat(id: i64) -> serde_json::Value {
+    if id < 0 {
+        serde_json::json!({
+            "id": id,
+            "type": "supergroup",
+            "title": "Test chat",
+            "is_forum": true,
+        })
+    } else {
+        serde_json::json!({
+            "id": id,
+            "type": "private",
+            "first_name": format!("User{id}"),
+        })
+    }
+}
+
+/// A text message sent by `from`. Use `thread` for forum topics.
+pub fn message(
+    chat_id: i64,
+    thread: Option<i32>,
+    from: u64,
+    text: &str,
+) -> serde_json::Value {
+    let mut message = serde_json::json!({
+        "message_id": next_id(),
+        "date": chrono::Utc::now().timestamp(),
+        "chat": chat(chat_id),
+        "from": user(from),
+        "text": text,
+    });
+    if let Some(thread) = thread {
+        message["message_thread_id"] = thread.into();
+        message["is_topic_message"] = true.into();
+    }
+    if text.starts_with('/') {
+        let len = text.find(' ').unwrap_or(text.len());
+        message["entities"] = serde_json::json!([
+            { "type": "bot_command", "offset": 0, "length": len },
+        ]);
+    }
+    message
+}
+
+/// A non-anonymous regular poll sent by `from`.
+pub fn poll_message(
+    chat_id: i64,
+    from: u64,
+    question: &str,
+    options: &[&str],
+) -> serde_json::Value {
+    serde_json::json!({
+        "message_id": next_id(),
+        "date": chrono::Utc::now().timestamp(),
+        "chat": chat(chat_id),
+        "from": user(from),
+        "poll": {
+            "id": format!("user-poll-{}", next_id()),
+            "question": question,
+            "options": options
+                .iter()
+                .map(|o| serde_json::json!({"text": o, "voter_count": 0}))
+                .collect::<Vec<_>>(),
+            "total_voter_count": 0,
+            "is_closed": false,
+            "is_anonymous": false,
+            "type": "regular",
+            "allows_multiple_answers": false,
+        },
+    })
+}
+
+pub fn message_update(message: serde_json::Value) -> Update {
+    update("message", message)
+}
+
+pub fn edited_message_update(message: serde_json::Value) -> Update {
+    update("edited_message", message)
+}
+
+/// A button press by `from` on the `message` sent by the bot.
+pub fn callback_query_update(
+    from: u64,
+    message: serde_json::Value,
+    data: &str,
+) -> Update {
+    update(
+        "callback_query",
+        serde_json::json!({
+            "id": next_id().to_string(),
+            "from": user(from),
+            "message": message,
+            "chat_instance": "0",
+            "data": data,
+        }),
+    )
+}
+
+pub fn poll_answer_update(
+    poll_id: &str,
+    from: u64,
+    option_ids: &[u8],
+) -> Update {
+    update(
+        "poll_answer",
+        serde_json::json!({
+            "poll_id": poll_id,
+            "user": user(from),
+            "option_ids": option_ids,
+        }),
+    )
+}
+
+fn update(kind: &str, value: serde_json::Value) -> Update {
+    let update = serde_json::json!({ "update_id": next_id(), kind: value });
+    // Deserializing `Update` from `serde_json::Value` results in
+    // `UpdateKind::Error`, so go through a string.
+    serde_json::from_str(&update.to_string()).unwrap()
+}
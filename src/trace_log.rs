@@ -0,0 +1,300 @@
+//! Trace file written by [`crate::tracing_proxy`], with rotation, compression
+//! and retention of closed segments.
+//!
+//! The active segment is always written to the configured trace path, e.g.
+//! `trace.jsonl`. When it grows too large or too old, it's renamed to
+//! `trace.<timestamp>.jsonl`, and a new one is started. Closed segments are
+//! then compressed in background (`trace.<timestamp>.jsonl.gz` or `.zst`),
+//! and the oldest ones are deleted.
+//!
+//! [`read_lines`] reads all segments in order, decompressing them as needed.
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context as _, Result};
+
+use crate::config::{TraceCompression, TraceRotation};
+
+pub struct TraceWriter {
+    path: PathBuf,
+    rotation: TraceRotation,
+    file: File,
+    size: u64,
+    opened_at: SystemTime,
+    /// The last write failed, so the file may end with a partial line.
+    broken_line: bool,
+}
+
+impl TraceWriter {
+    pub fn open(path: &Path, rotation: TraceRotation) -> Result<Self> {
+        let file = open_append(path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        let metadata = file.metadata()?;
+        Ok(Self {
+            path: path.to_owned(),
+            rotation,
+            size: metadata.len(),
+            opened_at: metadata.created().unwrap_or_else(|_| SystemTime::now()),
+            file,
+            broken_line: false,
+        })
+    }
+
+    /// Append values to the trace, one JSON per line. Rotates the file
+    /// beforehand if needed.
+    pub fn append(
+        &mut self,
+        values: impl Iterator<Item = impl serde::Serialize>,
+    ) -> Result<()> {
+        if self.should_rotate() {
+            let segment = self.rotate()?;
+            let rotation = self.rotation.clone();
+            let path = self.path.clone();
+            tokio::task::spawn_blocking(move || {
+                if let Err(e) = finish_segment(&path, &segment, &rotation) {
+                    log::error!("Failed to finish trace segment: {e:?}");
+                }
+            });
+        }
+
+        let mut buf = Vec::new();
+        if self.broken_line {
+            buf.push(b'\n');
+        }
+        for value in values {
+            serde_json::to_writer(&mut buf, &value)?;
+            buf.push(b'\n');
+        }
+        let result = self.file.write_all(&buf).and_then(|()| self.file.flush());
+        self.broken_line = result.is_err();
+        self.size += buf.len() as u64;
+        Ok(result?)
+    }
+
+    fn should_rotate(&self) -> bool {
+        if self.size == 0 {
+            return false;
+        }
+        let too_large = self.rotation.max_size.is_some_and(|s| self.size >= s);
+        let too_old = self.rotation.max_age_hours.is_some_and(|h| {
+            self.opened_at.elapsed().unwrap_or_default()
+                >= Duration::from_secs(h * 60 * 60)
+        });
+        too_large || too_old
+    }
+
+    /// Close the active segment and start a new one. Returns the path of the
+    /// closed segment.
+    fn rotate(&mut self) -> Result<PathBuf> {
+        let segment = self.path.with_file_name(format!(
+            "{}.{}.{}",
+            file_stem(&self.path),
+            chrono::Utc::now().format("%Y%m%dT%H%M%S%3f"),
+            file_extension(&self.path),
+        ));
+        std::fs::rename(&self.path, &segment).with_context(|| {
+            format!("Failed to rename trace file to {}", segment.display())
+        })?;
+        self.file = open_append(&self.path)?;
+        self.size = 0;
+        self.opened_at = SystemTime::now();
+        self.broken_line = false;
+        Ok(segment)
+    }
+}
+
+/// Compress a closed segment and delete old segments, according to the
+/// rotation settings.
+fn finish_segment(
+    path: &Path,
+    segment: &Path,
+    rotation: &TraceRotation,
+) -> Result<()> {
+    compress(segment, rotation.compression)?;
+    if let Some(keep) = rotation.keep {
+        let segments = rotated_segments(path)?;
+        for old in &segments[..segments.len().saturating_sub(keep)] {
+            log::info!("Removing old trace segment {}", old.display());
+            std::fs::remove_file(old)?;
+        }
+    }
+    Ok(())
+}
+
+fn compress(segment: &Path, compression: TraceCompression) -> Result<()> {
+    let suffix = match compression {
+        TraceCompression::None => return Ok(()),
+        TraceCompression::Gzip => "gz",
+        TraceCompression::Zstd => "zst",
+    };
+    let mut target = segment.as_os_str().to_owned();
+    target.push(".");
+    target.push(suffix);
+    let target = PathBuf::from(target);
+
+    let mut input = File::open(segment)?;
+    let output = File::create(&target)?;
+    let result = match compression {
+        TraceCompression::None => unreachable!(),
+        TraceCompression::Gzip => {
+            let mut encoder = flate2::write::GzEncoder::new(
+                output,
+                flate2::Compression::default(),
+            );
+            std::io::copy(&mut input, &mut encoder)
+                .and_then(|_| encoder.finish()?.sync_all())
+        }
+        TraceCompression::Zstd => zstd::stream::Encoder::new(output, 0)
+            .and_then(|mut encoder| {
+                std::io::copy(&mut input, &mut encoder)?;
+                encoder.finish()?.sync_all()
+            }),
+    };
+    if let Err(e) = result {
+        // Keep the uncompressed segment, it's still readable.
+        std::fs::remove_file(&target).ok();
+        return Err(e).with_context(|| {
+            format!("Failed to compress {}", segment.display())
+        });
+    }
+    std::fs::remove_file(segment)?;
+    Ok(())
+}
+
+/// Closed segments of the trace at `path`, oldest first.
+fn rotated_segments(path: &Path) -> Result<Vec<PathBuf>> {
+    let dir = match path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    };
+    let prefix = format!("{}.", file_stem(path));
+    let suffix = format!(".{}", file_extension(path));
+
+    let mut segments = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let Some(name) = name.to_str() else { continue };
+        let uncompressed = name
+            .strip_suffix(".gz")
+            .or_else(|| name.strip_suffix(".zst"))
+            .unwrap_or(name);
+        let is_segment = uncompressed
+            .strip_prefix(&prefix)
+            .and_then(|s| s.strip_suffix(&suffix))
+            .is_some_and(|ts| {
+                !ts.is_empty() && ts.chars().all(|c| c.is_ascii_alphanumeric())
+            });
+        if is_segment {
+            segments.push(entry.path());
+        }
+    }
+    // Timestamps are fixed-width, so lexicographic order is chronological.
+    segments.sort();
+    Ok(segments)
+}
+
+/// Segments to read for the given trace path. A compressed file is read on
+/// its own, otherwise all closed segments are read before the active one.
+fn segments(path: &Path) -> Result<Vec<PathBuf>> {
+    if is_compressed(path) {
+        return Ok(vec![path.to_owned()]);
+    }
+    let mut segments = rotated_segments(path)?;
+    if path.exists() || segments.is_empty() {
+        segments.push(path.to_owned());
+    }
+    Ok(segments)
+}
+
+fn open_segment(path: &Path) -> Result<Box<dyn BufRead>> {
+    let file = File::open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    Ok(match path.extension().and_then(|e| e.to_str()) {
+        Some("gz") => {
+            Box::new(BufReader::new(flate2::read::MultiGzDecoder::new(file)))
+        }
+        Some("zst") => {
+            Box::new(BufReader::new(zstd::stream::Decoder::new(file)?))
+        }
+        _ => Box::new(BufReader::new(file)),
+    })
+}
+
+/// Read lines of the trace at `path`, including rotated and compressed
+/// segments.
+pub fn read_lines(
+    path: &Path,
+) -> Result<impl Iterator<Item = std::io::Result<String>>> {
+    let readers = segments(path)?
+        .into_iter()
+        .map(|p| open_segment(&p))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(readers.into_iter().flat_map(BufRead::lines))
+}
+
+fn open_append(path: &Path) -> std::io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+fn is_compressed(path: &Path) -> bool {
+    matches!(path.extension().and_then(|e| e.to_str()), Some("gz" | "zst"))
+}
+
+fn file_stem(path: &Path) -> String {
+    path.file_stem().unwrap_or_default().to_string_lossy().into_owned()
+}
+
+fn file_extension(path: &Path) -> String {
+    path.extension().unwrap_or_default().to_string_lossy().into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(path: &Path) -> Vec<String> {
+        read_lines(path).unwrap().map(Result::unwrap).collect()
+    }
+
+    #[test]
+    fn test_rotation() {
+        let dir = std::env::temp_dir()
+            .join(format!("f0bot-test-trace-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("trace.jsonl");
+
+        let rotation = TraceRotation {
+            max_size: Some(1),
+            max_age_hours: None,
+            compression: TraceCompression::None,
+            keep: Some(2),
+        };
+        let mut writer = TraceWriter::open(&path, rotation.clone()).unwrap();
+        writer.append([1].iter()).unwrap();
+        for (i, compression) in [
+            (2, TraceCompression::Gzip),
+            (3, TraceCompression::Zstd),
+            (4, TraceCompression::None),
+        ] {
+            let segment = writer.rotate().unwrap();
+            let rotation = TraceRotation { compression, ..rotation.clone() };
+            finish_segment(&path, &segment, &rotation).unwrap();
+            writer.append([i].iter()).unwrap();
+            // Make sure segment names differ.
+            std::thread::sleep(Duration::from_millis(2));
+        }
+
+        let segments = rotated_segments(&path).unwrap();
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[0].extension().unwrap(), "zst");
+        assert_eq!(segments[1].extension().unwrap(), "jsonl");
+        assert_eq!(lines(&path), ["2", "3", "4"]);
+        assert_eq!(lines(&segments[0]), ["2"]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
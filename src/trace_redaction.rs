@@ -0,0 +1,403 @@
+//! Redaction of values written to the trace by [`crate::tracing_proxy`], so
+//! the trace can be shared with other maintainers without leaking credentials
+//! or private conversations.
+//!
+//! Following values are redacted:
+//! - Secrets from the current config (bot token, webhook secret, service
+//!   passwords and tokens), and secrets registered with [`hide_secret`],
+//!   wherever they occur.
+//! - Everything in private chats (if `trace_redaction.private_chats` is set)
+//!   and in chats listed in `trace_redaction.chats`.
+//! - Values matched by `trace_redaction.rules`.
+//!
+//! Only string values are replaced, strings under [`KEPT_KEYS`] are left as
+//! is, and URLs are replaced with [`REDACTED_URL`], so redacted updates and
+//! responses still can be deserialized, e.g. by [`crate::replay`].
+
+use std::sync::{Arc, Mutex};
+
+use arc_swap::ArcSwap;
+use serde_json::Value;
+use teloxide::types::ChatId;
+
+use crate::config::{Config, TraceRedaction, TraceRedactionRule};
+
+const REDACTED: &str = "<redacted>";
+
+/// Replacement for values under keys ending with `url`, since teloxide
+/// parses them as URLs.
+const REDACTED_URL: &str = "https://redacted.invalid/";
+
+/// Keys of enum-like and MIME type strings that are kept when redacting an
+/// object, since teloxide rejects other values of them.
+const KEPT_KEYS: &[&str] =
+    &["type", "status", "parse_mode", "emoji", "mime_type"];
+
+/// Secrets registered with [`hide_secret`].
+static HIDDEN_SECRETS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Redact `secret` from the trace until the returned guard is dropped. Used
+/// for secrets that are sent to Telegram, e.g. generated passwords.
+#[must_use]
+pub fn hide_secret(secret: &str) -> HiddenSecret {
+    HIDDEN_SECRETS.lock().unwrap().push(secret.to_owned());
+    HiddenSecret(secret.to_owned())
+}
+
+pub struct HiddenSecret(String);
+
+impl Drop for HiddenSecret {
+    fn drop(&mut self) {
+        let mut secrets = HIDDEN_SECRETS.lock().unwrap();
+        if let Some(idx) = secrets.iter().position(|s| *s == self.0) {
+            secrets.swap_remove(idx);
+        }
+    }
+}
+
+pub struct Redactor {
+    config: TraceRedaction,
+    /// Secrets from the config, see [`Redactor::update_secrets`].
+    secrets: ArcSwap<Vec<String>>,
+}
+
+impl Redactor {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            config: config.trace_redaction.clone(),
+            secrets: ArcSwap::from_pointee(config_secrets(config)),
+        }
+    }
+
+    /// Redact secrets of a reloaded config. The rest of the redaction
+    /// settings require a restart.
+    pub fn update_secrets(&self, config: &Config) {
+        self.secrets.store(Arc::new(config_secrets(config)));
+    }
+
+    /// Redact an update received with `getUpdates`.
+    pub fn redact_update(&self, update: &mut Value) {
+        let chat = update_chat(update);
+        if self.is_redacted_chat(chat) {
+            redact_all(update);
+        } else {
+            self.apply_rules("getUpdates", chat, update);
+        }
+        self.redact_secrets(update);
+    }
+
+    /// Redact a request/response record.
+    pub fn redact_call(&self, method: Option<&str>, record: &mut Value) {
+        let chat = record["request"]["chat_id"]
+            .as_i64()
+            .or_else(|| record["response"]["result"]["chat"]["id"].as_i64())
+            .map(ChatId);
+        if self.is_redacted_chat(chat) {
+            redact_all(&mut record["request"]);
+            redact_all(&mut record["response"]);
+        } else if let Some(method) = method {
+            self.apply_rules(method, chat, record);
+        }
+        self.redact_secrets(record);
+    }
+
+    fn is_redacted_chat(&self, chat: Option<ChatId>) -> bool {
+        chat.is_some_and(|chat| {
+            (self.config.private_chats && chat.is_user())
+                || self.config.chats.contains(&chat)
+        })
+    }
+
+    fn apply_rules(
+        &self,
+        method: &str,
+        chat: Option<ChatId>,
+        value: &mut Value,
+    ) {
+        for rule in &self.config.rules {
+            if rule_matches(rule, method, chat) {
+                for path in &rule.paths {
+                    redact_path(value, &path.split('.').collect::<Vec<_>>());
+                }
+            }
+        }
+    }
+
+    fn redact_secrets(&self, value: &mut Value) {
+        let hidden = HIDDEN_SECRETS.lock().unwrap().clone();
+        let config_secrets = self.secrets.load();
+        let secrets = config_secrets.iter().chain(hidden.iter());
+        visit_strings(value, &mut |s| {
+            for secret in secrets.clone() {
+                if s.contains(secret.as_str()) {
+                    *s = s.replace(secret.as_str(), REDACTED);
+                }
+            }
+        });
+    }
+}
+
+fn config_secrets(config: &Config) -> Vec<String> {
+    let services = &config.services;
+    [
+        Some(&config.telegram.token),
+        config.telegram.webhook.as_ref().map(|w| &w.secret_token),
+        services.mikrotik.as_ref().map(|s| &s.password),
+        services.home_assistant.as_ref().map(|s| &s.token),
+        services.wikijs.as_ref().map(|s| &s.token),
+        services.openai.as_ref().map(|s| &s.api_key),
+        services.ldap.as_ref().map(|s| &s.password),
+    ]
+    .into_iter()
+    .flatten()
+    .filter(|s| !s.is_empty())
+    .cloned()
+    .collect()
+}
+
+fn rule_matches(
+    rule: &TraceRedactionRule,
+    method: &str,
+    chat: Option<ChatId>,
+) -> bool {
+    rule.method.iter().all(|m| m.eq_ignore_ascii_case(method))
+        && rule.chat.iter().all(|&c| chat == Some(c))
+}
+
+/// Chat the update belongs to, if any.
+fn update_chat(update: &Value) -> Option<ChatId> {
+    [
+        "message",
+        "edited_message",
+        "channel_post",
+        "edited_channel_post",
+        "my_chat_member",
+        "chat_member",
+        "chat_join_request",
+    ]
+    .iter()
+    .find_map(|key| update.get(key))
+    .or_else(|| update.get("callback_query")?.get("message"))
+    .and_then(|v| v["chat"]["id"].as_i64())
+    .map(ChatId)
+}
+
+/// Redact values at the dot-separated path. `*` matches any object key or
+/// array element.
+fn redact_path(value: &mut Value, path: &[&str]) {
+    let Some((&head, tail)) = path.split_first() else {
+        redact_all(value);
+        return;
+    };
+    let children: Vec<&mut Value> = match (value, head) {
+        (Value::Object(map), "*") => map.values_mut().collect(),
+        (Value::Object(map), key) => map.get_mut(key).into_iter().collect(),
+        (Value::Array(items), "*") => items.iter_mut().collect(),
+        (Value::Array(items), idx) => idx
+            .parse::<usize>()
+            .ok()
+            .and_then(|idx| items.get_mut(idx))
+            .into_iter()
+            .collect(),
+        _ => Vec::new(),
+    };
+    for child in children {
+        redact_path(child, tail);
+    }
+}
+
+/// Replace all strings in the value, except ones under [`KEPT_KEYS`], and
+/// URLs with a valid one.
+fn redact_all(value: &mut Value) {
+    match value {
+        Value::String(s) => REDACTED.clone_into(s),
+        Value::Array(items) => items.iter_mut().for_each(redact_all),
+        Value::Object(map) => {
+            for (key, value) in map {
+                match value {
+                    _ if KEPT_KEYS.contains(&key.as_str()) => (),
+                    Value::String(s) if key.ends_with("url") => {
+                        REDACTED_URL.clone_into(s);
+                    }
+                    value => redact_all(value),
+                }
+            }
+        }
+        _ => (),
+    }
+}
+
+fn visit_strings(value: &mut Value, f: &mut impl FnMut(&mut String)) {
+    match value {
+        Value::String(s) => f(s),
+        Value::Array(items) => {
+            for item in items {
+                visit_strings(item, f);
+            }
+        }
+        Value::Object(map) => {
+            for value in map.values_mut() {
+                visit_strings(value, f);
+            }
+        }
+        _ => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_redact() {
+        let mut config = crate::testing::test_config();
+        config.trace_redaction = TraceRedaction {
+            private_chats: true,
+            chats: vec![ChatId(-1)],
+            rules: vec![TraceRedactionRule {
+                method: Some("sendPoll".to_string()),
+                chat: None,
+                paths: vec!["request.options.0".to_string()],
+            }],
+        };
+        let token = config.telegram.token.clone();
+        let redactor = Redactor::new(&config);
+
+        let mut update = json!({
+            "update_id": 1,
+            "message": {
+                "chat": { "id": 42, "type": "private" },
+                "text": "/ldap_reset_password",
+            },
+        });
+        redactor.redact_update(&mut update);
+        assert_eq!(
+            update,
+            json!({
+                "update_id": 1,
+                "message": {
+                    "chat": { "id": 42, "type": "private" },
+                    "text": REDACTED,
+                },
+            }),
+        );
+
+        let mut record = json!({
+            "method": "SendPoll",
+            "request": {
+                "chat_id": -2,
+                "question": format!("token {token}?"),
+                "options": ["yes", "no"],
+            },
+            "response": { "ok": true },
+        });
+        let guard = hide_secret("no");
+        redactor.redact_call(Some("SendPoll"), &mut record);
+        drop(guard);
+        assert_eq!(
+            record["request"],
+            json!({
+                "chat_id": -2,
+                "question": format!("token {REDACTED}?"),
+                "options": [REDACTED, REDACTED],
+            }),
+        );
+        assert!(HIDDEN_SECRETS.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_update_secrets() {
+        let mut config = crate::testing::test_config();
+        let redactor = Redactor::new(&config);
+        config.services.wikijs = Some(crate::config::WikiJs {
+            url: "https://wiki.example.com".to_string(),
+            token: "rotated-token".to_string(),
+            token_file: None,
+            welcome_message_page: "welcome".to_string(),
+            dashboard_page: "dashboard".to_string(),
+        });
+        redactor.update_secrets(&config);
+
+        let mut record = json!({
+            "method": "SendMessage",
+            "request": { "chat_id": -2, "text": "rotated-token" },
+        });
+        redactor.redact_call(Some("SendMessage"), &mut record);
+        assert_eq!(record["request"]["text"], REDACTED);
+    }
+
+    #[test]
+    fn test_redacted_updates_deserialize() {
+        let mut config = crate::testing::test_config();
+        config.trace_redaction.private_chats = true;
+        let redactor = Redactor::new(&config);
+        let message = |content: Value| {
+            let mut message = json!({
+                "message_id": 1,
+                "date": 1_700_000_000,
+                "chat": { "id": 42, "type": "private", "first_name": "A" },
+                "from": { "id": 42, "is_bot": false, "first_name": "A" },
+            });
+            message
+                .as_object_mut()
+                .unwrap()
+                .extend(content.as_object().unwrap().clone());
+            json!({ "update_id": 1, "message": message })
+        };
+
+        for mut update in [
+            message(json!({
+                "document": {
+                    "file_id": "file",
+                    "file_unique_id": "unique",
+                    "file_name": "passport.pdf",
+                    "mime_type": "application/pdf",
+                },
+                "caption": "My passport",
+                "caption_entities": [{
+                    "type": "text_link",
+                    "offset": 0,
+                    "length": 2,
+                    "url": "https://example.com/private",
+                }],
+            })),
+            message(json!({ "dice": { "emoji": "🎲", "value": 3 } })),
+        ] {
+            redactor.redact_update(&mut update);
+            assert!(!update.to_string().contains("passport"), "{update}");
+            assert!(!update.to_string().contains("example.com"), "{update}");
+            // As read by `replay` and `scrape-log`.
+            let update: teloxide::types::Update =
+                serde_json::from_str(&update.to_string()).unwrap();
+            assert!(matches!(
+                update.kind,
+                teloxide::types::UpdateKind::Message(_)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_redact_webhook_secret() {
+        let mut config = crate::testing::test_config();
+        config.telegram.webhook = Some(crate::config::Webhook {
+            url: "https://example.com/webhook".parse().unwrap(),
+            secret_token: "webhook-secret".to_string(),
+            secret_token_file: None,
+        });
+        let redactor = Redactor::new(&config);
+
+        let mut record = json!({
+            "method": "SetWebhook",
+            "request": {
+                "url": "https://example.com/webhook",
+                "secret_token": "webhook-secret",
+            },
+            "response": { "ok": true, "result": true },
+        });
+        redactor.redact_call(Some("SetWebhook"), &mut record);
+        assert!(!record.to_string().contains("webhook-secret"), "{record}");
+        assert_eq!(record["request"]["secret_token"], REDACTED);
+    }
+}
@@ -1,6 +1,4 @@
 use std::convert::Infallible;
-use std::fs::{File, OpenOptions};
-use std::io::Write;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Duration;
@@ -12,12 +10,24 @@ use hyper::{Body, Request, Response, Server, Uri};
 use reqwest::Client;
 use tokio::net::TcpListener;
 use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
 
+use crate::common::BotEnv;
+use crate::config::Config;
+use crate::trace_log::TraceWriter;
+use crate::trace_redaction::Redactor;
 use crate::utils::parse_tgapi_method;
 
 struct Proxy {
     client: Client,
-    log_file: Mutex<File>,
+    trace: Arc<Trace>,
+}
+
+/// The trace file, along with the redaction rules applied to everything
+/// written to it.
+pub struct Trace {
+    writer: Mutex<TraceWriter>,
+    redactor: Redactor,
 }
 
 #[derive(serde::Deserialize, Debug)]
@@ -27,10 +37,71 @@ struct GetUpdatesResponse {
     result: Vec<serde_json::Value>,
 }
 
+impl Trace {
+    pub fn open(config: &Config) -> Result<Self> {
+        Ok(Self {
+            writer: Mutex::new(TraceWriter::open(
+                &config.paths.trace_file(),
+                config.trace_rotation.clone(),
+            )?),
+            redactor: Redactor::new(config),
+        })
+    }
+
+    /// Log updates received via getUpdates or a webhook.
+    pub async fn log_updates(&self, mut updates: Vec<serde_json::Value>) {
+        for update in &mut updates {
+            self.redactor.redact_update(update);
+        }
+        self.append(updates.iter()).await;
+    }
+
+    async fn log_call(
+        &self,
+        method: Option<&str>,
+        mut record: serde_json::Value,
+    ) {
+        self.redactor.redact_call(method, &mut record);
+        self.append(std::iter::once(record)).await;
+    }
+
+    async fn append(
+        &self,
+        values: impl Iterator<Item = impl serde::Serialize> + Send,
+    ) {
+        let result = self.writer.lock().await.append(values);
+        if let Err(e) = result {
+            log::error!("Failed to write to the trace file: {e:?}");
+            metrics::increment_counter!("botka_trace_write_errors_total");
+        }
+    }
+}
+
+/// Redact secrets of the current config, updating them after the config is
+/// reloaded.
+pub async fn redaction_task(
+    trace: Arc<Trace>,
+    env: Arc<BotEnv>,
+    cancel: CancellationToken,
+) {
+    loop {
+        let reloaded = env.config_reloaded.notified();
+        tokio::pin!(reloaded);
+        reloaded.as_mut().enable();
+
+        trace.redactor.update_secrets(&env.config());
+
+        tokio::select! {
+            () = cancel.cancelled() => break,
+            () = reloaded => (),
+        }
+    }
+}
+
 /// Start a proxy server that forwards requests to the Telegram API and logs
-/// getUpdates responses, as well as all other requests and responses.
-/// Returns the URL of the proxy server.
-pub async fn start() -> Result<reqwest::Url> {
+/// getUpdates responses, as well as all other requests and responses, to the
+/// trace. Returns the URL of the proxy server.
+pub async fn start(trace: Arc<Trace>) -> Result<reqwest::Url> {
     // Make client from teloxide::net::default_reqwest_settings, plus 3 seconds.
     let client = reqwest::Client::builder()
         .connect_timeout(Duration::from_secs(5 + 3))
@@ -38,14 +109,7 @@ pub async fn start() -> Result<reqwest::Url> {
         .tcp_nodelay(true)
         .build()?;
 
-    let log_file = Mutex::new(
-        OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(crate::TRACE_FILENAME)?,
-    );
-
-    let proxy = Arc::new(Proxy { client, log_file });
+    let proxy = Arc::new(Proxy { client, trace });
 
     let listener =
         TcpListener::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).await?;
@@ -134,27 +198,23 @@ async fn handle_request(
             serde_json::from_slice::<GetUpdatesResponse>(&out_response_body)
         {
             crate::metrics::update_service("telegram", true);
-            append_values_to_log_file(&proxy, response_body.result.iter())
-                .await;
+            proxy.trace.log_updates(response_body.result).await;
         } else {
             crate::metrics::update_service("telegram", false);
         }
     } else {
         // Log request and response
-        append_values_to_log_file(
-            &proxy,
-            std::iter::once(serde_json::json!({
-                "__f0bot": "v0",
-                "date": now,
-                "method": method,
-                "status": out_response_status.as_u16(),
-                "request": in_request_body_json,
-                "response": serde_json::from_slice::<serde_json::Value>(
-                    out_response_body.as_ref(),
-                ).ok(),
-            })),
-        )
-        .await;
+        let record = serde_json::json!({
+            "__f0bot": "v0",
+            "date": now,
+            "method": method,
+            "status": out_response_status.as_u16(),
+            "request": in_request_body_json,
+            "response": serde_json::from_slice::<serde_json::Value>(
+                out_response_body.as_ref(),
+            ).ok(),
+        });
+        proxy.trace.log_call(method.as_deref(), record).await;
     }
 
     let mut in_response = Response::new(out_response_body.into());
@@ -164,15 +224,3 @@ async fn handle_request(
 
     Ok(in_response)
 }
-
-async fn append_values_to_log_file(
-    proxy: &Proxy,
-    values: impl Iterator<Item = impl serde::Serialize> + Send,
-) {
-    let mut log_file = proxy.log_file.lock().await;
-    for value in values {
-        serde_json::to_writer(&mut *log_file, &value).unwrap();
-        log_file.write_all(b"\n").unwrap();
-    }
-    log_file.flush().unwrap();
-}
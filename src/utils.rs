@@ -8,19 +8,22 @@ pub mod ldap;
 mod log_error;
 pub mod mikrotik;
 mod parsers;
+mod rate_limiter;
 mod replace_urls;
 mod status_change;
 mod teloxide;
 mod wikijs;
 
 pub use diesel_json::Sqlizer;
-pub use dptree_ext::HandlerExt;
+pub use dptree_ext::inspect_err;
 pub use espcam::read_camera_image;
 pub(crate) use format_to::format_to;
 pub use log_error::ResultExt;
 pub use parsers::{
-    deserealize_duration, parse_tg_thread_link, parse_tgapi_method,
+    deserealize_duration, parse_duration, parse_tg_thread_link,
+    parse_tgapi_method,
 };
+pub use rate_limiter::{RateLimited, RateLimiter};
 pub use replace_urls::replace_urls_with_titles;
 pub use status_change::StatusChangeDetector;
 pub use wikijs::{get_wikijs_page, get_wikijs_updates, WikiJsUpdateState};
@@ -4,43 +4,8 @@ use std::sync::Arc;
 use dptree::di::Injectable;
 use dptree::{from_fn_with_description, Handler, HandlerDescription};
 
-/// Extension trait for [`dptree::Handler`].
-pub trait HandlerExt<'a, Input, Output, Descr>: Sized + 'a
-where
-    Input: Send + 'a,
-    Output: 'a,
-    Descr: HandlerDescription,
-{
-    /// Similar to [`Handler::inspect_async`], but the handler function
-    /// can return an error. The error will be logged then discarded.
-    fn inspect_err<F, Error, Args>(
-        self,
-        f: F,
-    ) -> Handler<'a, Input, Output, Descr>
-    where
-        F: Injectable<Input, Result<(), Error>, Args> + Send + Sync + 'a,
-        Error: 'a + Debug;
-}
-
-impl<'a, Input, Output, Descr> HandlerExt<'a, Input, Output, Descr>
-    for Handler<'a, Input, Output, Descr>
-where
-    Input: Send + 'a,
-    Output: 'a,
-    Descr: HandlerDescription,
-{
-    fn inspect_err<F, Error, Args>(
-        self,
-        f: F,
-    ) -> Handler<'a, Input, Output, Descr>
-    where
-        F: Injectable<Input, Result<(), Error>, Args> + Send + Sync + 'a,
-        Error: 'a + Debug,
-    {
-        self.chain(inspect_err(f))
-    }
-}
-
+/// Similar to [`dptree::inspect_async`], but the handler function can return
+/// an error. The error will be logged then discarded.
 pub fn inspect_err<'a, Input, Output, Error, Descr, F, Args>(
     f: F,
 ) -> Handler<'a, Input, Output, Descr>
@@ -24,6 +24,17 @@ where
     Ok(duration)
 }
 
+/// Parse a non-zero duration entered by a user, e.g. `"1w"` or `"2d12h"`.
+pub fn parse_duration(input: &str) -> Option<Duration> {
+    if input == "never" {
+        return None;
+    }
+    match duration(input) {
+        Ok(("", duration)) if !duration.is_zero() => Some(duration),
+        _ => None,
+    }
+}
+
 fn duration(mut input: &str) -> IResult<&str, Duration> {
     if input == "never" {
         return Ok(("", Duration::new(u64::MAX, 0)));
@@ -32,29 +43,31 @@ fn duration(mut input: &str) -> IResult<&str, Duration> {
     while !input.is_empty() {
         let (i, segment) = duration_segment(input)?;
         input = i;
-        duration += segment;
+        duration = duration.saturating_add(segment);
     }
     Ok((input, duration))
 }
 
 fn duration_segment(input: &str) -> IResult<&str, Duration> {
     let (input, (value, unit)) = tuple((digit1, one_of("wdhms")))(input)?;
-    let Ok(value) = value.parse::<u64>() else {
+    let unit_secs = match unit {
+        'w' => 7 * 24 * 60 * 60,
+        'd' => 24 * 60 * 60,
+        'h' => 60 * 60,
+        'm' => 60,
+        's' => 1,
+        _ => unreachable!(),
+    };
+    let Some(secs) =
+        value.parse::<u64>().ok().and_then(|v| v.checked_mul(unit_secs))
+    else {
         // TODO: better error
         return Err(nom::Err::Error(ParseError::from_error_kind(
             input,
             nom::error::ErrorKind::Digit,
         )));
     };
-    let duration = match unit {
-        'w' => Duration::from_secs(value * 7 * 24 * 60 * 60),
-        'd' => Duration::from_secs(value * 24 * 60 * 60),
-        'h' => Duration::from_secs(value * 60 * 60),
-        'm' => Duration::from_secs(value * 60),
-        's' => Duration::from_secs(value),
-        _ => unreachable!(),
-    };
-    Ok((input, duration))
+    Ok((input, Duration::from_secs(secs)))
 }
 
 /// Parse a telegram bot api path, dropping the credentials. E.g., parsing
@@ -119,6 +132,16 @@ mod tests {
         assert_eq!(time_struct.0, DURATION);
     }
 
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("2d12h"), Some(DAY * 2 + HOUR * 12));
+        assert_eq!(parse_duration(""), None);
+        assert_eq!(parse_duration("0d"), None);
+        assert_eq!(parse_duration("never"), None);
+        assert_eq!(parse_duration("3x"), None);
+        assert_eq!(parse_duration("99999999999999999w"), None);
+    }
+
     #[test]
     fn test_parse_tgapi_method() {
         assert_eq!(
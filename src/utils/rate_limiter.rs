@@ -0,0 +1,144 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::config::RateLimit;
+
+/// Buckets are pruned once there are this many of them.
+const PRUNE_THRESHOLD: usize = 1024;
+
+/// Token bucket rate limiter, with a bucket per key.
+#[allow(clippy::module_name_repetitions)] // Re-exported in the super module.
+pub struct RateLimiter<K> {
+    buckets: Mutex<HashMap<K, Bucket>>,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    limit: RateLimit,
+    /// Whether the user was already told about the cooldown since the bucket
+    /// ran out of tokens.
+    notified: bool,
+}
+
+/// The outcome of [`RateLimiter::check`].
+#[derive(Debug, Eq, PartialEq)]
+pub enum RateLimited {
+    /// The invocation is allowed.
+    No,
+    /// The invocation is rejected, and the caller should tell the user to
+    /// wait this long. Only returned once per cooldown.
+    Notify(Duration),
+    /// The invocation is rejected silently.
+    Silently,
+}
+
+impl Bucket {
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated);
+        self.updated = now;
+        if self.limit.refill_secs == 0 {
+            self.tokens = f64::from(self.limit.burst);
+            return;
+        }
+        self.tokens = f64::from(self.limit.burst).min(
+            self.tokens
+                + elapsed.as_secs_f64() / f64::from(self.limit.refill_secs),
+        );
+    }
+
+    fn is_full(&self) -> bool {
+        self.tokens >= f64::from(self.limit.burst)
+    }
+}
+
+impl<K: Eq + Hash> RateLimiter<K> {
+    pub fn new() -> Self {
+        Self { buckets: Mutex::new(HashMap::new()) }
+    }
+
+    /// Take a token from the bucket of the key.
+    pub fn check(&self, key: K, limit: RateLimit) -> RateLimited {
+        self.check_at(key, limit, Instant::now())
+    }
+
+    fn check_at(&self, key: K, limit: RateLimit, now: Instant) -> RateLimited {
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= PRUNE_THRESHOLD {
+            buckets.retain(|_, b| {
+                b.refill(now);
+                !b.is_full()
+            });
+        }
+        let bucket = buckets.entry(key).or_insert_with(|| Bucket {
+            tokens: f64::from(limit.burst),
+            updated: now,
+            limit,
+            notified: false,
+        });
+        if bucket.limit != limit {
+            // The config was reloaded.
+            bucket.limit = limit;
+            bucket.tokens = bucket.tokens.min(f64::from(limit.burst));
+        }
+        bucket.refill(now);
+
+        let result = if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            bucket.notified = false;
+            RateLimited::No
+        } else if bucket.notified {
+            RateLimited::Silently
+        } else {
+            bucket.notified = true;
+            let wait = (1.0 - bucket.tokens) * f64::from(limit.refill_secs);
+            RateLimited::Notify(Duration::from_secs_f64(wait.ceil()))
+        };
+        drop(buckets);
+        result
+    }
+}
+
+impl<K: Eq + Hash> Default for RateLimiter<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limiter() {
+        let limiter = RateLimiter::new();
+        let limit = RateLimit { burst: 2, refill_secs: 10 };
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        assert_eq!(limiter.check_at(1, limit, at(0)), RateLimited::No);
+        assert_eq!(limiter.check_at(1, limit, at(0)), RateLimited::No);
+        assert_eq!(
+            limiter.check_at(1, limit, at(1)),
+            RateLimited::Notify(Duration::from_secs(9))
+        );
+        assert_eq!(limiter.check_at(1, limit, at(2)), RateLimited::Silently);
+        assert_eq!(limiter.check_at(2, limit, at(2)), RateLimited::No);
+
+        assert_eq!(limiter.check_at(1, limit, at(10)), RateLimited::No);
+        assert!(matches!(
+            limiter.check_at(1, limit, at(10)),
+            RateLimited::Notify(_)
+        ));
+
+        // Fully refilled, but not above the burst
+        assert_eq!(limiter.check_at(1, limit, at(100)), RateLimited::No);
+        assert_eq!(limiter.check_at(1, limit, at(100)), RateLimited::No);
+        assert!(matches!(
+            limiter.check_at(1, limit, at(100)),
+            RateLimited::Notify(_)
+        ));
+    }
+}
@@ -1,25 +1,62 @@
-use std::sync::{Arc, Mutex, OnceLock};
+use std::convert::Infallible;
+use std::future::Future as _;
+use std::pin::Pin;
+use std::sync::{Arc, OnceLock};
+use std::task::Poll;
 
 use diesel::prelude::*;
 use itertools::Itertools;
 use metrics_exporter_prometheus::PrometheusHandle;
 use salvo::conn::TcpListener;
+use salvo::http::{StatusCode, StatusError};
 use salvo::writing::{Json, Text};
-use salvo::{Listener, Router, Server};
+use salvo::{Listener, Request, Response, Router, Server};
+use salvo_oapi::security::{Http, HttpAuthScheme, SecurityScheme};
 use salvo_oapi::{endpoint, OpenApi};
 use tap::Pipe as _;
+use teloxide::stop::{mk_stop_token, StopFlag, StopToken};
+use teloxide::types::Update;
+use teloxide::update_listeners::{StatefulListener, UpdateListener};
+use teloxide::Bot;
+use tokio::sync::mpsc;
 use tokio_util::sync::CancellationToken;
 
-use crate::config::Config;
+use crate::common::BotEnv;
 use crate::db::DbUserId;
+use crate::tracing_proxy::Trace;
 use crate::{models, schema};
 
+mod admin;
+mod auth;
+mod needs;
+mod stats;
+
 struct AppState {
-    conn: Mutex<SqliteConnection>,
-    config: Arc<Config>,
+    env: Arc<BotEnv>,
+    bot: Bot,
     prometheus: PrometheusHandle,
+    bot_username: String,
+    webhook: Option<Webhook>,
+    /// Cancelled when the bot starts shutting down, see [`get_health`].
+    draining: CancellationToken,
+}
+
+/// Receiving side of the Telegram webhook, see [`webhook`].
+pub struct Webhook {
+    secret_token: String,
+    updates: mpsc::UnboundedSender<Update>,
+    trace: Arc<Trace>,
 }
 
+/// State of the update listener returned by [`webhook`].
+pub struct WebhookUpdates {
+    updates: mpsc::UnboundedReceiver<Update>,
+    stop_token: StopToken,
+    stop_flag: StopFlag,
+}
+
+const SECRET_TOKEN_HEADER: &str = "X-Telegram-Bot-Api-Secret-Token";
+
 static STATE: OnceLock<AppState> = OnceLock::new();
 
 fn state() -> &'static AppState {
@@ -27,27 +64,65 @@ fn state() -> &'static AppState {
 }
 
 fn server_addr() -> String {
-    format!("http://{}", state().config.server_addr)
+    format!("http://{}", state().env.config().server_addr)
+}
+
+/// Create a webhook to be passed to [`run`], and an update listener for the
+/// dispatcher that receives updates from it. Received updates are written to
+/// the trace, like the ones received via getUpdates.
+pub fn webhook(
+    secret_token: String,
+    trace: Arc<Trace>,
+) -> (Webhook, impl UpdateListener<Err = Infallible>) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let (stop_token, stop_flag) = mk_stop_token();
+    let listener = StatefulListener::new(
+        WebhookUpdates { updates: rx, stop_token, stop_flag },
+        webhook_updates_stream,
+        |state: &mut WebhookUpdates| state.stop_token.clone(),
+    );
+    (Webhook { secret_token, updates: tx, trace }, listener)
+}
+
+/// Stream of updates received by the webhook, until the listener is stopped.
+fn webhook_updates_stream(
+    state: &mut WebhookUpdates,
+) -> impl futures::Stream<Item = Result<Update, Infallible>> + '_ {
+    futures::stream::poll_fn(|cx| {
+        if Pin::new(&mut state.stop_flag).poll(cx).is_ready() {
+            return Poll::Ready(None);
+        }
+        state.updates.poll_recv(cx).map(|update| update.map(Ok))
+    })
 }
 
 pub async fn run(
-    conn: SqliteConnection,
-    config: Arc<Config>,
+    env: Arc<BotEnv>,
+    bot: Bot,
     prometheus: PrometheusHandle,
-    cancel: CancellationToken,
+    bot_username: String,
+    webhook: Option<Webhook>,
+    draining: CancellationToken,
+    stop: CancellationToken,
 ) {
     let app_state = AppState {
-        conn: Mutex::new(conn),
-        config: Arc::clone(&config),
+        env: Arc::clone(&env),
+        bot,
         prometheus,
+        bot_username,
+        webhook,
+        draining,
     };
     STATE.set(app_state).ok().expect("AppState already initialized");
 
     let router = Router::new()
         .get(get_index)
+        .push(Router::with_path("/health").get(get_health))
         .push(Router::with_path("/metrics").get(get_metrics))
         .push(Router::with_path("/residents/v0").get(get_residents_v0))
-        .push(Router::with_path("/all_residents/v0").get(get_all_residents_v0));
+        .push(Router::with_path("/all_residents/v0").get(get_all_residents_v0))
+        .push(needs::router())
+        .push(stats::router());
 
     let doc = OpenApi::with_info(
         salvo_oapi::Info::new("Botka HTTP API", "0.1").description(
@@ -58,6 +133,10 @@ pub async fn run(
         ),
     )
     .add_server(salvo_oapi::Server::new(server_addr()))
+    .add_security_scheme(
+        "bearer",
+        SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)),
+    )
     .add_path(
         "/openapi.json",
         salvo_oapi::PathItem::new(
@@ -67,13 +146,18 @@ pub async fn run(
     )
     .merge_router(&router);
 
-    let router = router.unshift(doc.into_router("/openapi.json"));
+    let mut router =
+        router.unshift(doc.into_router("/openapi.json")).push(admin::router());
+    if let Some(webhook) = &env.config().telegram.webhook {
+        router = router
+            .push(Router::with_path(webhook.url.path()).post(post_webhook));
+    }
 
-    let listener = TcpListener::new(config.server_addr).bind().await;
+    let listener = TcpListener::new(env.config().server_addr).bind().await;
     Server::new(listener)
         .serve_with_graceful_shutdown(
             router,
-            async move { cancel.cancelled().await },
+            async move { stop.cancelled().await },
             None,
         )
         .await;
@@ -102,26 +186,94 @@ async fn get_index() -> Text<String> {
 .pipe(Text::Html)
 }
 
+/// Telegram webhook endpoint. Not included in the API docs.
+#[salvo::prelude::handler]
+async fn post_webhook(req: &mut Request, res: &mut Response) {
+    let Some(webhook) = &state().webhook else {
+        res.render(StatusError::not_found());
+        return;
+    };
+    if state().draining.is_cancelled() {
+        // The dispatcher is stopping, so let Telegram retry the update after
+        // the restart.
+        res.render(StatusError::service_unavailable());
+        return;
+    }
+    let secret_token = req.header::<String>(SECRET_TOKEN_HEADER);
+    if !secret_token.is_some_and(|t| {
+        constant_time_eq(t.as_bytes(), webhook.secret_token.as_bytes())
+    }) {
+        res.render(StatusError::unauthorized());
+        return;
+    }
+
+    let Ok(body) = req.payload().await else {
+        res.render(StatusError::bad_request());
+        return;
+    };
+    if let Ok(value) = serde_json::from_slice(body) {
+        webhook.trace.log_updates(vec![value]).await;
+    }
+    match serde_json::from_slice::<Update>(body) {
+        Ok(update) => {
+            // Fails only if the dispatcher is stopped.
+            webhook.updates.send(update).ok();
+        }
+        Err(e) => {
+            log::error!("Failed to parse webhook update: {e}");
+            res.render(StatusError::bad_request());
+        }
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len()
+        && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Health check. Responds with `{"status": "ok"}`, or with
+/// `{"status": "draining"}` and status 503 while the bot is shutting down.
+#[endpoint()]
+async fn get_health(res: &mut Response) {
+    let status = if state().draining.is_cancelled() {
+        res.status_code(StatusCode::SERVICE_UNAVAILABLE);
+        "draining"
+    } else {
+        "ok"
+    };
+    res.render(Json(serde_json::json!({ "status": status })));
+}
+
 /// Prometheus metrics endpoint.
 #[endpoint()]
 async fn get_metrics() -> String {
     let state = state();
-    crate::metrics::refresh(&mut state.conn.lock().unwrap());
+    let db_path = state.env.config().paths.db_file();
+    state.env.db.run(move |conn| crate::metrics::refresh(conn, &db_path)).await;
     state.prometheus.render()
 }
 
 /// Get a list of current residents.
 #[endpoint()]
 async fn get_residents_v0() -> Json<Vec<models::DataResident>> {
-    let residents: Vec<(DbUserId, models::TgUser)> = schema::residents::table
-        .filter(schema::residents::end_date.is_null())
-        .inner_join(
-            schema::tg_users::table
-                .on(schema::residents::tg_id.eq(schema::tg_users::id)),
-        )
-        .order(schema::residents::begin_date.desc())
-        .select((schema::residents::tg_id, schema::tg_users::all_columns))
-        .load(&mut *state().conn.lock().unwrap())
+    let residents: Vec<(DbUserId, models::TgUser)> = state()
+        .env
+        .db
+        .run(|conn| {
+            schema::residents::table
+                .filter(schema::residents::end_date.is_null())
+                .inner_join(
+                    schema::tg_users::table
+                        .on(schema::residents::tg_id.eq(schema::tg_users::id)),
+                )
+                .order(schema::residents::begin_date.desc())
+                .select((
+                    schema::residents::tg_id,
+                    schema::tg_users::all_columns,
+                ))
+                .load(conn)
+        })
+        .await
         .unwrap();
 
     let residents = residents
@@ -141,9 +293,15 @@ async fn get_residents_v0() -> Json<Vec<models::DataResident>> {
 /// The same resident may appear multiple times if they have left and returned.
 #[endpoint()]
 async fn get_all_residents_v0() -> Json<Vec<models::Resident>> {
-    schema::residents::table
-        .order(schema::residents::begin_date.desc())
-        .load(&mut *state().conn.lock().unwrap())
+    state()
+        .env
+        .db
+        .run(|conn| {
+            schema::residents::table
+                .order(schema::residents::begin_date.desc())
+                .load(conn)
+        })
+        .await
         .map(Json)
         .unwrap()
 }
@@ -0,0 +1,379 @@
+//! Read-only admin area. Users log in with the Telegram Login Widget, and only
+//! users listed in `telegram.admins` are let in.
+//!
+//! For the widget to work, the domain of the admin area should be linked to
+//! the bot with the `/setdomain` command of `@BotFather`.
+
+use std::collections::HashMap;
+
+use diesel::prelude::*;
+use hmac::{Hmac, Mac};
+use salvo::http::header::{COOKIE, SET_COOKIE};
+use salvo::http::StatusError;
+use salvo::writing::{Redirect, Text};
+use salvo::{Request, Response, Router};
+use sha2::{Digest, Sha256};
+use teloxide::types::{ChatId, UserId};
+use teloxide::utils::html::escape;
+
+use super::state;
+use crate::db::{DbConnection, DbUserId};
+use crate::utils::format_to;
+use crate::{models, schema};
+
+const SESSION_COOKIE: &str = "botka_admin_session";
+const SESSION_LIFETIME_SECS: u64 = 7 * 24 * 60 * 60;
+/// Login data older than this is rejected, to limit replay of leaked links.
+const LOGIN_DATA_LIFETIME_SECS: u64 = 10 * 60;
+
+pub fn router() -> Router {
+    Router::with_path("/admin")
+        .get(get_index)
+        .push(Router::with_path("login").get(get_login))
+        .push(Router::with_path("logout").get(get_logout))
+}
+
+#[salvo::prelude::handler]
+async fn get_index(req: &mut Request, res: &mut Response) {
+    match session_user(req) {
+        Some(user_id) => {
+            let tables = state().env.db.run(load_tables).await;
+            res.render(Text::Html(admin_page(user_id, tables)));
+        }
+        None => res.render(Text::Html(login_page(&state().bot_username))),
+    }
+}
+
+/// Callback of the Telegram Login Widget.
+#[salvo::prelude::handler]
+async fn get_login(req: &mut Request, res: &mut Response) {
+    let token = &state().env.config().telegram.token;
+    let data: HashMap<String, String> =
+        req.queries().iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+    let Some(user_id) = verify_login(token, &data, now()) else {
+        res.render(StatusError::unauthorized().brief("Invalid login data."));
+        return;
+    };
+    if !state().env.config().telegram.admins.contains(&user_id) {
+        log::warn!("Non-admin user {user_id} tried to log in");
+        res.render(StatusError::forbidden().brief("You are not an admin."));
+        return;
+    }
+
+    let expires = now() + SESSION_LIFETIME_SECS;
+    let cookie = format!(
+        "{SESSION_COOKIE}={}; Path=/admin; Max-Age={SESSION_LIFETIME_SECS}; \
+         HttpOnly; SameSite=Lax",
+        session_value(token, user_id, expires),
+    );
+    res.add_header(SET_COOKIE, cookie, false).ok();
+    res.render(Redirect::found("/admin"));
+}
+
+#[salvo::prelude::handler]
+async fn get_logout(res: &mut Response) {
+    let cookie = format!("{SESSION_COOKIE}=; Path=/admin; Max-Age=0");
+    res.add_header(SET_COOKIE, cookie, false).ok();
+    res.render(Redirect::found("/admin"));
+}
+
+fn now() -> u64 {
+    std::time::UNIX_EPOCH.elapsed().unwrap_or_default().as_secs()
+}
+
+/// Verify data sent by the Telegram Login Widget, as described in
+/// <https://core.telegram.org/widgets/login#checking-authorization>.
+/// Returns the ID of the logged in user.
+fn verify_login(
+    token: &str,
+    data: &HashMap<String, String>,
+    now: u64,
+) -> Option<UserId> {
+    let hash = hex::decode(data.get("hash")?).ok()?;
+    let mut fields = data
+        .iter()
+        .filter(|(k, _)| *k != "hash")
+        .map(|(k, v)| format!("{k}={v}"))
+        .collect::<Vec<_>>();
+    fields.sort();
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(&Sha256::digest(token))
+        .expect("HMAC accepts keys of any size");
+    mac.update(fields.join("\n").as_bytes());
+    mac.verify_slice(&hash).ok()?;
+
+    let auth_date = data.get("auth_date")?.parse::<u64>().ok()?;
+    if now.saturating_sub(auth_date) > LOGIN_DATA_LIFETIME_SECS {
+        return None;
+    }
+    data.get("id")?.parse().ok().map(UserId)
+}
+
+fn session_mac(token: &str, payload: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(token.as_bytes())
+        .expect("HMAC accepts keys of any size");
+    mac.update(b"botka admin session\n");
+    mac.update(payload.as_bytes());
+    mac
+}
+
+/// Session cookie value: `<user id>.<expiration time>.<signature>`.
+fn session_value(token: &str, user_id: UserId, expires: u64) -> String {
+    let payload = format!("{user_id}.{expires}");
+    let signature = session_mac(token, &payload).finalize().into_bytes();
+    format!("{payload}.{}", hex::encode(signature))
+}
+
+fn parse_session(token: &str, value: &str, now: u64) -> Option<UserId> {
+    let (payload, signature) = value.rsplit_once('.')?;
+    session_mac(token, payload)
+        .verify_slice(&hex::decode(signature).ok()?)
+        .ok()?;
+    let (user_id, expires) = payload.split_once('.')?;
+    if expires.parse::<u64>().ok()? < now {
+        return None;
+    }
+    user_id.parse().ok().map(UserId)
+}
+
+/// The admin logged in with the session cookie, if any. Admins removed from
+/// the config are logged out.
+fn session_user(req: &Request) -> Option<UserId> {
+    let config = &state().env.config();
+    let user_id = req
+        .headers()
+        .get_all(COOKIE)
+        .iter()
+        .filter_map(|h| h.to_str().ok())
+        .flat_map(|h| h.split(';'))
+        .filter_map(|c| c.trim().split_once('='))
+        .filter(|(name, _)| *name == SESSION_COOKIE)
+        .find_map(|(_, value)| {
+            parse_session(&config.telegram.token, value, now())
+        })?;
+    config.telegram.admins.contains(&user_id).then_some(user_id)
+}
+
+const PAGE_HEAD: &str = r#"<!doctype html>
+<html>
+<head>
+    <meta charset="utf-8">
+    <title>Botka Admin</title>
+    <style>
+        body { font-family: sans-serif; margin: 2em; }
+        table { border-collapse: collapse; margin-bottom: 2em; }
+        th, td { border: 1px solid #888; padding: 0.2em 0.5em; }
+        th { background: #eee; }
+    </style>
+</head>
+<body>
+"#;
+
+fn login_page(bot_username: &str) -> String {
+    format!(
+        r#"{PAGE_HEAD}<h1>Botka Admin</h1>
+<script async src="https://telegram.org/js/telegram-widget.js?22"
+    data-telegram-login="{}"
+    data-size="large"
+    data-auth-url="/admin/login"></script>
+</body>
+</html>
+"#,
+        escape(bot_username),
+    )
+}
+
+fn admin_page(user_id: UserId, tables: diesel::QueryResult<Tables>) -> String {
+    let mut out = PAGE_HEAD.to_string();
+    format_to!(
+        out,
+        "<h1>Botka Admin</h1>\n<p>Logged in as {user_id}. \
+         <a href=\"/admin/logout\">Log out</a></p>\n",
+    );
+    match tables {
+        Ok(tables) => write_tables(&mut out, &tables),
+        Err(e) => {
+            log::error!("Failed to render the admin page: {e}");
+            format_to!(
+                out,
+                "<p>Database error: {}</p>\n",
+                escape(&e.to_string())
+            );
+        }
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+/// Everything shown on the admin page.
+struct Tables {
+    users: HashMap<DbUserId, models::TgUser>,
+    residents: Vec<models::Resident>,
+    macs: Vec<models::UserMac>,
+    borrowed: Vec<models::BorrowedItems>,
+    needs: Vec<models::NeededItem>,
+    polls: Vec<models::TrackedPoll>,
+}
+
+fn load_tables(conn: &mut DbConnection) -> diesel::QueryResult<Tables> {
+    Ok(Tables {
+        users: schema::tg_users::table
+            .load::<models::TgUser>(conn)?
+            .into_iter()
+            .map(|u| (u.id, u))
+            .collect(),
+        residents: schema::residents::table
+            .filter(schema::residents::end_date.is_null())
+            .order(schema::residents::begin_date.desc())
+            .load(conn)?,
+        macs: schema::user_macs::table
+            .order(schema::user_macs::tg_id)
+            .load(conn)?,
+        borrowed: schema::borrowed_items::table.load(conn)?,
+        needs: schema::needed_items::table
+            .filter(schema::needed_items::buyer_user_id.is_null())
+            .order(schema::needed_items::rowid)
+            .load(conn)?,
+        polls: schema::tracked_polls::table.load(conn)?,
+    })
+}
+
+fn write_tables(out: &mut String, tables: &Tables) {
+    let user = |id: DbUserId| {
+        tables.users.get(&id).map_or_else(
+            || UserId::from(id).to_string(),
+            |u| {
+                let mut name = u.first_name.clone();
+                if let Some(last_name) = &u.last_name {
+                    format_to!(name, " {last_name}");
+                }
+                if let Some(username) = &u.username {
+                    format_to!(name, " (@{username})");
+                }
+                name
+            },
+        )
+    };
+
+    table(
+        out,
+        "Residents",
+        &["ID", "Name", "Since"],
+        tables.residents.iter().map(|r| {
+            vec![
+                UserId::from(r.tg_id).to_string(),
+                user(r.tg_id),
+                r.begin_date.to_string(),
+            ]
+        }),
+    );
+    table(
+        out,
+        "MAC Addresses",
+        &["User", "MAC"],
+        tables.macs.iter().map(|m| vec![user(m.tg_id), m.mac.to_string()]),
+    );
+    table(
+        out,
+        "Borrowed Items",
+        &["User", "Item", "Returned", "Chat"],
+        tables.borrowed.iter().flat_map(|b| {
+            b.items.iter().map(|item| {
+                vec![
+                    user(b.user_id),
+                    item.name.clone(),
+                    item.returned.map_or_else(String::new, |r| r.to_string()),
+                    ChatId::from(b.chat_id).to_string(),
+                ]
+            })
+        }),
+    );
+    table(
+        out,
+        "Needs",
+        &["Item", "Requested by"],
+        tables
+            .needs
+            .iter()
+            .map(|n| vec![n.item.clone(), user(n.request_user_id)]),
+    );
+    table(
+        out,
+        "Tracked Polls",
+        &["Poll ID", "Creator", "Chat", "Voted"],
+        tables.polls.iter().map(|p| {
+            vec![
+                p.tg_poll_id.clone(),
+                user(p.creator_id),
+                ChatId::from(p.info_chat_id).to_string(),
+                p.voted_users.len().to_string(),
+            ]
+        }),
+    );
+}
+
+fn table(
+    out: &mut String,
+    title: &str,
+    headers: &[&str],
+    rows: impl Iterator<Item = Vec<String>>,
+) {
+    format_to!(out, "<h2>{}</h2>\n<table>\n<tr>", escape(title));
+    for header in headers {
+        format_to!(out, "<th>{}</th>", escape(header));
+    }
+    out.push_str("</tr>\n");
+    for row in rows {
+        out.push_str("<tr>");
+        for cell in row {
+            format_to!(out, "<td>{}</td>", escape(&cell));
+        }
+        out.push_str("</tr>\n");
+    }
+    out.push_str("</table>\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOKEN: &str = "123456:ABC-DEF1234ghIkl-zyx57W2v1u123ew11";
+
+    #[test]
+    fn test_verify_login() {
+        let mut data: HashMap<String, String> = [
+            ("id", "42"),
+            ("first_name", "John"),
+            ("username", "john"),
+            ("auth_date", "1700000000"),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&Sha256::digest(TOKEN)).unwrap();
+        mac.update(
+            b"auth_date=1700000000\nfirst_name=John\nid=42\nusername=john",
+        );
+        data.insert(
+            "hash".to_string(),
+            hex::encode(mac.finalize().into_bytes()),
+        );
+
+        assert_eq!(verify_login(TOKEN, &data, 1_700_000_060), Some(UserId(42)));
+        assert_eq!(verify_login(TOKEN, &data, 1_700_100_000), None);
+        assert_eq!(verify_login("1:other", &data, 1_700_000_060), None);
+        data.insert("id".to_string(), "43".to_string());
+        assert_eq!(verify_login(TOKEN, &data, 1_700_000_060), None);
+    }
+
+    #[test]
+    fn test_session() {
+        let value = session_value(TOKEN, UserId(42), 1000);
+        assert_eq!(parse_session(TOKEN, &value, 999), Some(UserId(42)));
+        assert_eq!(parse_session(TOKEN, &value, 1001), None);
+        assert_eq!(parse_session("1:other", &value, 999), None);
+        let forged = value.replacen("42", "43", 1);
+        assert_eq!(parse_session(TOKEN, &forged, 999), None);
+    }
+}
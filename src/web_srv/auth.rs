@@ -0,0 +1,75 @@
+//! Authentication of write and non-public endpoints of the HTTP API with
+//! tokens issued by the `/api_token` command, see [`crate::modules::api_tokens`].
+
+use diesel::QueryResult;
+use salvo::http::StatusError;
+use salvo::{Depot, FlowCtrl, Request, Response};
+use teloxide::types::UserId;
+
+use super::state;
+use crate::common::is_resident;
+use crate::modules::api_tokens;
+
+/// The user authenticated by [`api_auth`].
+#[derive(Clone, Copy, Debug)]
+pub struct ApiUser(pub UserId);
+
+/// Resolve the `Authorization: Bearer <token>` header to a user, and put
+/// [`ApiUser`] into the depot. Requests without a valid token, or with a
+/// token of a user who is no longer a resident, are rejected.
+#[salvo::prelude::handler]
+pub async fn api_auth(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+    ctrl: &mut FlowCtrl,
+) {
+    let header = req.header::<String>("Authorization");
+    let Some(token) = header.as_deref().and_then(|h| h.strip_prefix("Bearer "))
+    else {
+        res.render(StatusError::unauthorized().brief("Missing API token."));
+        ctrl.skip_rest();
+        return;
+    };
+
+    let token = token.to_string();
+    let resolved = state()
+        .env
+        .db
+        .run(move |conn| {
+            let user = api_tokens::resolve_token(conn, &token)?;
+            QueryResult::Ok(user.map(|user| (user, is_resident(conn, user))))
+        })
+        .await;
+    let (user, resident) = match resolved {
+        Ok(Some(user)) => user,
+        Ok(None) => {
+            res.render(StatusError::unauthorized().brief("Invalid API token."));
+            ctrl.skip_rest();
+            return;
+        }
+        Err(e) => {
+            log::error!("Failed to resolve API token: {e}");
+            res.render(StatusError::internal_server_error());
+            ctrl.skip_rest();
+            return;
+        }
+    };
+    if !resident {
+        res.render(
+            StatusError::forbidden().brief("Only residents can use the API."),
+        );
+        ctrl.skip_rest();
+        return;
+    }
+
+    depot.inject(ApiUser(user));
+}
+
+/// Get the user authenticated by [`api_auth`].
+pub fn api_user(depot: &Depot) -> Result<UserId, StatusError> {
+    depot
+        .obtain::<ApiUser>()
+        .map(|u| u.0)
+        .map_err(|_| StatusError::unauthorized())
+}
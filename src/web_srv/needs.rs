@@ -0,0 +1,130 @@
+//! HTTP API for the shopping list, see [`crate::modules::needs`].
+
+use diesel::prelude::*;
+use salvo::http::StatusError;
+use salvo::writing::Json;
+use salvo::{Depot, Router};
+use salvo_oapi::extract::{JsonBody, PathParam};
+use salvo_oapi::{endpoint, ToSchema};
+use serde::Deserialize;
+
+use super::auth::{api_auth, api_user};
+use super::state;
+use crate::models::{self, DataNeededItem};
+use crate::modules::needs;
+use crate::schema;
+
+pub fn router() -> Router {
+    Router::with_path("/needs/v0")
+        .hoop(api_auth)
+        .get(get_needs_v0)
+        .post(post_needs_v0)
+        .push(Router::with_path("<id>/bought").post(post_bought_v0))
+}
+
+#[derive(Deserialize, Debug, ToSchema)]
+struct NewNeeds {
+    /// Items to add.
+    items: Vec<String>,
+}
+
+/// Get a list of items to buy.
+#[endpoint(security(("bearer" = [])))]
+async fn get_needs_v0() -> Result<Json<Vec<DataNeededItem>>, StatusError> {
+    let items: Vec<models::NeededItem> = state()
+        .env
+        .db
+        .run(|conn| {
+            schema::needed_items::table
+                .filter(schema::needed_items::buyer_user_id.is_null())
+                .order(schema::needed_items::rowid)
+                .load(conn)
+        })
+        .await
+        .map_err(|e| {
+            log::error!("Failed to load needed items: {e}");
+            StatusError::internal_server_error()
+        })?;
+    Ok(Json(items.into_iter().map(Into::into).collect()))
+}
+
+/// Add items to the shopping list on behalf of the token owner. The items are
+/// posted to the needs thread.
+#[endpoint(security(("bearer" = [])))]
+async fn post_needs_v0(
+    depot: &mut Depot,
+    body: JsonBody<NewNeeds>,
+) -> Result<Json<Vec<DataNeededItem>>, StatusError> {
+    let user = api_user(depot)?;
+    let items = body.items.iter().map(|i| i.trim()).filter(|i| !i.is_empty());
+    let items = items.collect::<Vec<_>>();
+    if items.is_empty() {
+        return Err(StatusError::bad_request().brief("No items."));
+    }
+
+    let state = state();
+    if state.env.config().telegram.chats.needs.is_none() {
+        return Err(StatusError::service_unavailable()
+            .brief("The shopping list is not configured."));
+    }
+    let added = needs::add_items_by_user(&state.bot, &state.env, user, &items)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to add needed items: {e:?}");
+            StatusError::internal_server_error()
+        })?;
+    Ok(Json(added.into_iter().map(Into::into).collect()))
+}
+
+/// Mark an item as bought by the token owner.
+#[endpoint(security(("bearer" = [])))]
+async fn post_bought_v0(
+    depot: &mut Depot,
+    id: PathParam<i32>,
+) -> Result<Json<DataNeededItem>, StatusError> {
+    let user = api_user(depot)?;
+    let state = state();
+    match needs::mark_bought(&state.bot, &state.env, id.into_inner(), user)
+        .await
+    {
+        Ok(Ok(item)) => Ok(Json(item.into())),
+        Ok(Err(error @ needs::BuyError::NotFound)) => {
+            Err(StatusError::not_found().brief(error.message()))
+        }
+        Ok(Err(error @ needs::BuyError::AlreadyBought)) => {
+            Err(StatusError::conflict().brief(error.message()))
+        }
+        Err(e) => {
+            log::error!("Failed to mark an item as bought: {e:?}");
+            Err(StatusError::internal_server_error())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use salvo::http::{Method, StatusCode};
+    use salvo::{Request, Service};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_token_required() {
+        let service = Service::new(router());
+        for (method, path) in [
+            (Method::GET, "/needs/v0"),
+            (Method::POST, "/needs/v0"),
+            (Method::POST, "/needs/v0/1/bought"),
+        ] {
+            let mut req = Request::new();
+            *req.method_mut() = method;
+            *req.uri_mut() = format!("http://localhost{path}").parse().unwrap();
+            let res = service.handle(req).await;
+            assert_eq!(
+                res.status_code,
+                Some(StatusCode::UNAUTHORIZED),
+                "{path}"
+            );
+        }
+    }
+}
@@ -0,0 +1,88 @@
+//! HTTP API for chat activity of residential chats, see
+//! [`crate::modules::stats`].
+
+use chrono::NaiveDateTime;
+use diesel::QueryResult;
+use salvo::http::StatusError;
+use salvo::writing::Json;
+use salvo::Router;
+use salvo_oapi::endpoint;
+use salvo_oapi::extract::QueryParam;
+use serde::Serialize;
+
+use super::auth::api_auth;
+use super::state;
+use crate::db::{DbChatId, DbConnection};
+use crate::models::{DataChatStats, DataPosterStats, DataTopicGrowth};
+use crate::modules::stats;
+
+pub fn router() -> Router {
+    Router::with_path("/stats/v0")
+        .hoop(api_auth)
+        .push(Router::with_path("chats").get(get_chats_v0))
+        .push(Router::with_path("posters").get(get_posters_v0))
+        .push(Router::with_path("hours").get(get_hours_v0))
+        .push(Router::with_path("topics").get(get_topics_v0))
+}
+
+/// Load stats of residential chats for the `period` query parameter.
+async fn load<T, F>(period: Option<&str>, f: F) -> Result<Json<T>, StatusError>
+where
+    F: FnOnce(&mut DbConnection, &[DbChatId], NaiveDateTime) -> QueryResult<T>
+        + Send
+        + 'static,
+    T: Serialize + Send + 'static,
+{
+    let Some(since) = stats::parse_since(period.unwrap_or_default()) else {
+        return Err(StatusError::bad_request().brief("Invalid period."));
+    };
+    let state = state();
+    let chats = stats::residential_chats(&state.env);
+    state
+        .env
+        .db
+        .run(move |conn| f(conn, &chats, since))
+        .await
+        .map(Json)
+        .map_err(|e| {
+            log::error!("Failed to load stats: {e}");
+            StatusError::internal_server_error()
+        })
+}
+
+/// Get message counts per residential chat and topic. The `period` is like
+/// `7d` or `2w`, 30 days by default and at most 366 days.
+#[endpoint(security(("bearer" = [])))]
+async fn get_chats_v0(
+    period: QueryParam<String, false>,
+) -> Result<Json<Vec<DataChatStats>>, StatusError> {
+    load(period.as_deref(), stats::chat_stats).await
+}
+
+/// Get users who sent the most messages in residential chats.
+#[endpoint(security(("bearer" = [])))]
+async fn get_posters_v0(
+    period: QueryParam<String, false>,
+) -> Result<Json<Vec<DataPosterStats>>, StatusError> {
+    load(period.as_deref(), |conn, chats, since| {
+        stats::top_posters(conn, chats, since, stats::TOP_POSTERS)
+    })
+    .await
+}
+
+/// Get message counts in residential chats by weekday (Monday first) and
+/// hour, in UTC.
+#[endpoint(security(("bearer" = [])))]
+async fn get_hours_v0(
+    period: QueryParam<String, false>,
+) -> Result<Json<Vec<Vec<i64>>>, StatusError> {
+    load(period.as_deref(), stats::active_hours).await
+}
+
+/// Get the number of forum topics in residential chats by day.
+#[endpoint(security(("bearer" = [])))]
+async fn get_topics_v0(
+    period: QueryParam<String, false>,
+) -> Result<Json<Vec<DataTopicGrowth>>, StatusError> {
+    load(period.as_deref(), stats::topic_growth).await
+}